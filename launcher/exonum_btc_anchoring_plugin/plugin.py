@@ -46,6 +46,8 @@ class AnchoringInstanceSpecLoader(InstanceSpecLoader):
                 instance.config["network"])
             config.anchoring_interval = instance.config["anchoring_interval"]
             config.transaction_fee = instance.config["transaction_fee"]
+            config.replace_by_fee = instance.config.get("replace_by_fee", False)
+            config.pay_to_contract = instance.config.get("pay_to_contract", False)
 
             anchoring_keys = []
             for keypair in instance.config["anchoring_keys"]:
@@ -24,7 +24,10 @@ use anyhow::{anyhow, ensure};
 use async_trait::async_trait;
 use btc_transaction_utils::{p2wsh, TxInRef};
 use exonum::{blockchain::IndexProof, crypto::Hash, helpers::Height};
-use exonum_merkledb::ListProof;
+use exonum_merkledb::{
+    access::{Access, AccessExt},
+    Database, ListProof, MapProof, TemporaryDB,
+};
 use exonum_rust_runtime::{
     api::{self, ServiceApiBuilder, ServiceApiState},
     Broadcaster,
@@ -37,7 +40,10 @@ use std::cmp::{
 };
 
 use crate::{
-    blockchain::{AddFunds, BtcAnchoringInterface, Schema, SignInput},
+    blockchain::{
+        AddFunds, BitcoinConfirmation, BtcAnchoringInterface, BumpFee, ReportConfirmation,
+        ReportUnconfirmed, Schema, SignInput, SignInputs,
+    },
     btc,
     config::Config,
 };
@@ -49,6 +55,43 @@ pub struct TransactionProof {
     pub index_proof: IndexProof,
     /// Proof for the specific transaction in this table.
     pub transaction_proof: ListProof<btc::Transaction>,
+    /// Proof for the payload of the transaction if it has been committed in
+    /// the pay-to-contract mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract_payload_proof: Option<ContractPayloadProof>,
+    /// Bitcoin confirmation status of the transaction if it has been reported by
+    /// the quorum of the anchoring nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitcoin_confirmation: Option<BitcoinConfirmation>,
+}
+
+/// A proof of existence for the payload of an anchoring transaction committed in
+/// the pay-to-contract mode.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractPayloadProof {
+    /// Proof of authenticity for a contract payloads index within the database.
+    pub index_proof: IndexProof,
+    /// Proof for the payload of the specific transaction in this table.
+    pub payload_proof: MapProof<btc::Sha256d, btc::Payload>,
+}
+
+/// A proof of inclusion of the block hash into the Merkle root of the anchored blocks
+/// committed by the anchoring transaction payload.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockProof {
+    /// Anchoring transaction, which payload contains the Merkle root of the anchored blocks.
+    pub anchoring_transaction: btc::Transaction,
+    /// Payload of the anchoring transaction.
+    pub payload: btc::Payload,
+    /// Height of the first anchored block.
+    pub blocks_start: Height,
+    /// Proof for the block hash in the list of the anchored blocks hashes, the block
+    /// index in this list is the difference between the block height and `blocks_start`.
+    pub block_hash_proof: ListProof<Hash>,
+    /// Bitcoin confirmation status of the anchoring transaction if it has been reported
+    /// by the quorum of the anchoring nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitcoin_confirmation: Option<BitcoinConfirmation>,
 }
 
 /// State of the next anchoring transaction proposal.
@@ -64,6 +107,20 @@ pub enum AnchoringProposalState {
         // `UnspentTxOutValue::Balance` variant. [ECR-3222]
         /// Input transactions.
         inputs: Vec<btc::Transaction>,
+        /// Payload committed by the proposal.
+        payload: btc::Payload,
+        /// Pay-to-contract commitments of the outputs spent by the corresponding inputs.
+        /// Such inputs should be signed by the keys tweaked by the commitment.
+        #[serde(default)]
+        input_commitments: Vec<Option<Hash>>,
+        /// Fee per virtual byte of the package consisting of the proposal and the
+        /// unconfirmed latest anchoring transaction, if the proposal accelerates it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        package_fee_rate: Option<u64>,
+        /// Address to which the proposal sweeps the anchoring funds, if the sweep has been
+        /// requested by the actual configuration.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sweep_address: Option<btc::Address>,
     },
     /// Insufficient funds to create an anchoring transaction proposal. Please fill up an anchoring wallet.
     InsufficientFunds {
@@ -74,17 +131,40 @@ pub enum AnchoringProposalState {
     },
     /// Initial funding transaction is absent.
     NoInitialFunds,
+    /// Anchoring is paused by the actual configuration.
+    Paused,
+    /// Anchoring funds have been swept to the sweep address, so the anchoring is stopped.
+    Swept {
+        /// Identifier of the sweep transaction.
+        txid: btc::Sha256d,
+    },
 }
 
 impl AnchoringProposalState {
     fn try_from_proposal(
-        proposal: Option<Result<(btc::Transaction, Vec<btc::Transaction>), btc::BuilderError>>,
+        schema: &Schema<impl Access>,
+        proposal: Option<
+            Result<(btc::Transaction, Vec<btc::Transaction>, btc::Payload), btc::BuilderError>,
+        >,
     ) -> Result<Self, api::Error> {
         match proposal {
-            None => Ok(AnchoringProposalState::None),
-            Some(Ok((transaction, inputs))) => Ok(AnchoringProposalState::Available {
+            None => Ok(match schema.sweep_transaction() {
+                Some(tx) => AnchoringProposalState::Swept { txid: tx.id() },
+                None if schema.actual_config().paused => AnchoringProposalState::Paused,
+                None => AnchoringProposalState::None,
+            }),
+            Some(Ok((transaction, inputs, payload))) => Ok(AnchoringProposalState::Available {
+                package_fee_rate: schema.package_fee_rate(&transaction, &inputs),
+                sweep_address: schema.actual_config().sweep_address.filter(|address| {
+                    address.0.script_pubkey() == transaction.0.output[0].script_pubkey
+                }),
+                input_commitments: inputs
+                    .iter()
+                    .map(|input| schema.contract_commitment(input))
+                    .collect(),
                 transaction,
                 inputs,
+                payload,
             }),
             Some(Err(btc::BuilderError::InsufficientFunds { total_fee, balance })) => {
                 Ok(AnchoringProposalState::InsufficientFunds { total_fee, balance })
@@ -152,6 +232,19 @@ pub trait PublicApi {
         &self,
         height: Option<Height>,
     ) -> Result<TransactionProof, Self::Error>;
+    /// Returns a proof of inclusion of the block with the given height into the Merkle
+    /// root of the anchored blocks committed by the corresponding anchoring transaction.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/block-proof` |
+    /// | Method      | GET   |
+    /// | Query type  | [`BlockProofQuery`] |
+    /// | Return type | [`BlockProof`] |
+    ///
+    /// [`BlockProofQuery`]: struct.BlockProofQuery.html
+    /// [`BlockProof`]: struct.BlockProof.html
+    async fn block_proof(&self, height: Height) -> Result<BlockProof, Self::Error>;
     /// Returns an actual anchoring configuration.
     ///
     /// | Property    | Value |
@@ -170,6 +263,14 @@ pub trait PublicApi {
 pub trait PrivateApi {
     /// Error type for the current API client implementation.
     type Error;
+    /// Checks if the given error means that the requested endpoint does not exist, for example
+    /// because the node runs a previous version of the service.
+    fn is_not_found(_error: &Self::Error) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
     /// Creates and broadcasts the `TxSignature` transaction, which is signed
     /// by the current node, and returns its hash.
     ///
@@ -183,6 +284,19 @@ pub trait PrivateApi {
     /// [`SignInput`]: ../blockchain/struct.SignInput.html
     /// [`Hash`]: https://docs.rs/exonum-crypto/latest/exonum_crypto/struct.Hash.html
     async fn sign_input(&self, sign_input: SignInput) -> Result<Hash, Self::Error>;
+    /// Creates and broadcasts the `SignInputs` transaction with signatures for all inputs
+    /// of the anchoring proposal, which is signed by the current node, and returns its hash.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/sign-inputs` |
+    /// | Method      | POST   |
+    /// | Query type  | [`SignInputs`] |
+    /// | Return type | [`Hash`] |
+    ///
+    /// [`SignInputs`]: ../blockchain/struct.SignInputs.html
+    /// [`Hash`]: https://docs.rs/exonum-crypto/latest/exonum_crypto/struct.Hash.html
+    async fn sign_inputs(&self, sign_inputs: SignInputs) -> Result<Hash, Self::Error>;
     /// Adds funds via suitable funding transaction.
     ///
     /// Bitcoin transaction should have output with value to the current anchoring address.
@@ -198,6 +312,49 @@ pub trait PrivateApi {
     /// [`AddFunds`]: ../blockchain/struct.AddFunds.html
     /// [`Hash`]: https://docs.rs/exonum-crypto/latest/exonum_crypto/struct.Hash.html
     async fn add_funds(&self, transaction: btc::Transaction) -> Result<Hash, Self::Error>;
+    /// Requests to replace the latest anchoring transaction by the one with a higher fee.
+    ///
+    /// The replacement is possible only if the replace-by-fee is enabled in the actual
+    /// configuration. The request will be applied if 2/3+1 anchoring nodes sent it.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/bump-fee` |
+    /// | Method      | POST   |
+    /// | Query type  | [`BumpFee`] |
+    /// | Return type | [`Hash`] |
+    ///
+    /// [`BumpFee`]: ../blockchain/struct.BumpFee.html
+    /// [`Hash`]: https://docs.rs/exonum-crypto/latest/exonum_crypto/struct.Hash.html
+    async fn bump_fee(&self, bump_fee: BumpFee) -> Result<Hash, Self::Error>;
+    /// Reports that the latest anchoring transaction is still unconfirmed in the Bitcoin
+    /// network, so the following anchoring transaction should accelerate it.
+    /// The report will be applied if 2/3+1 anchoring nodes sent it.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/report-unconfirmed` |
+    /// | Method      | POST   |
+    /// | Query type  | [`ReportUnconfirmed`] |
+    /// | Return type | [`Hash`] |
+    ///
+    /// [`ReportUnconfirmed`]: ../blockchain/struct.ReportUnconfirmed.html
+    /// [`Hash`]: https://docs.rs/exonum-crypto/latest/exonum_crypto/struct.Hash.html
+    async fn report_unconfirmed(&self, report: ReportUnconfirmed) -> Result<Hash, Self::Error>;
+    /// Reports that the anchoring transaction has been committed to the Bitcoin blockchain.
+    /// The Bitcoin block and the number of confirmations confirmed by 2/3+1 anchoring nodes
+    /// are recorded as the Bitcoin confirmation status of the transaction.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/report-confirmation` |
+    /// | Method      | POST   |
+    /// | Query type  | [`ReportConfirmation`] |
+    /// | Return type | [`Hash`] |
+    ///
+    /// [`ReportConfirmation`]: ../blockchain/struct.ReportConfirmation.html
+    /// [`Hash`]: https://docs.rs/exonum-crypto/latest/exonum_crypto/struct.Hash.html
+    async fn report_confirmation(&self, report: ReportConfirmation) -> Result<Hash, Self::Error>;
     /// Returns a proposal for the next anchoring transaction, if it makes sense.
     /// If there is not enough satoshis to create a proposal an error is returned.
     ///
@@ -210,6 +367,32 @@ pub trait PrivateApi {
     ///
     /// [`AnchoringProposalState`]: enum.AnchoringProposalState.html
     async fn anchoring_proposal(&self) -> Result<AnchoringProposalState, Self::Error>;
+    /// Returns a proposal for the next anchoring transaction as a BIP-174 partially signed
+    /// transaction for the offline signing, if the proposal is available.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/anchoring-proposal/psbt` |
+    /// | Method      | GET   |
+    /// | Query type  | - |
+    /// | Return type | [`Option<btc::Psbt>`] |
+    ///
+    /// [`Option<btc::Psbt>`]: ../btc/struct.Psbt.html
+    async fn anchoring_proposal_psbt(&self) -> Result<Option<btc::Psbt>, Self::Error>;
+    /// Converts the signatures made by the anchoring key of the current node in the given
+    /// partially signed anchoring proposal into the `SignInput` transactions, broadcasts
+    /// them and returns their hashes.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/sign-psbt` |
+    /// | Method      | POST   |
+    /// | Query type  | [`btc::Psbt`] |
+    /// | Return type | [`Vec<Hash>`] |
+    ///
+    /// [`btc::Psbt`]: ../btc/struct.Psbt.html
+    /// [`Vec<Hash>`]: https://docs.rs/exonum-crypto/latest/exonum_crypto/struct.Hash.html
+    async fn sign_psbt(&self, psbt: btc::Psbt) -> Result<Vec<Hash>, Self::Error>;
     /// Returns an actual anchoring configuration.
     ///
     /// | Property    | Value |
@@ -221,6 +404,31 @@ pub trait PrivateApi {
     ///
     /// [`config`]: ../config/struct.Config.html
     async fn config(&self) -> Result<Config, Self::Error>;
+    /// Returns the following anchoring configuration if the node is in the transition state.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/config/following` |
+    /// | Method      | GET   |
+    /// | Query type  | - |
+    /// | Return type | [`Option<Config>`] |
+    ///
+    /// [`Option<Config>`]: ../config/struct.Config.html
+    async fn following_config(&self) -> Result<Option<Config>, Self::Error>;
+    /// Returns the hash of the block with the given height. Unlike other methods, it uses
+    /// the public API of the explorer service, so the sync utility can check the anchored
+    /// block hash independently of the anchoring service.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/explorer/v1/block` |
+    /// | Method      | GET   |
+    /// | Query type  | [`BlockQuery`] |
+    /// | Return type | [`Hash`] |
+    ///
+    /// [`BlockQuery`]: https://docs.rs/exonum-explorer/latest/exonum_explorer/api/struct.BlockQuery.html
+    /// [`Hash`]: https://docs.rs/exonum-crypto/latest/exonum_crypto/struct.Hash.html
+    async fn block_hash(&self, height: Height) -> Result<Hash, Self::Error>;
     /// Returns an anchoring transaction with the specified index in anchoring transactions chain.
     ///
     /// | Property    | Value |
@@ -236,6 +444,31 @@ pub trait PrivateApi {
         &self,
         index: u64,
     ) -> Result<Option<btc::Transaction>, Self::Error>;
+    /// Returns the payload of an anchoring transaction with the specified index in anchoring
+    /// transactions chain, including the payload committed in the pay-to-contract mode.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/transaction/payload` |
+    /// | Method      | GET   |
+    /// | Query type  | [`IndexQuery`] |
+    /// | Return type | [`Option<btc::Payload>`] |
+    ///
+    /// ['IndexQuery']: struct.IndexQuery.html
+    /// [`Option<btc::Payload>`]: ../btc/struct.Payload.html
+    async fn transaction_payload(&self, index: u64) -> Result<Option<btc::Payload>, Self::Error>;
+    /// Returns the unspent funding transactions recorded by the anchoring service, which
+    /// may be spent by the anchoring transaction proposal.
+    ///
+    /// | Property    | Value |
+    /// |-------------|-------|
+    /// | Path        | `/api/services/{btc_anchoring}/funding-transactions` |
+    /// | Method      | GET   |
+    /// | Query type  | - |
+    /// | Return type | [`Vec<btc::Transaction>`] |
+    ///
+    /// [`Vec<btc::Transaction>`]: ../btc/struct.Transaction.html
+    async fn funding_transactions(&self) -> Result<Vec<btc::Transaction>, Self::Error>;
     /// Returns a total number of anchoring transactions in the chain.
     ///
     /// | Property    | Value |
@@ -264,33 +497,95 @@ impl ApiImpl {
         Ok(Schema::new(self.0.service_data()).actual_config())
     }
 
+    /// Returns the Bitcoin key of this node in the given configuration.
+    fn bitcoin_key(&self, config: &Config) -> anyhow::Result<btc::PublicKey> {
+        config
+            .find_bitcoin_key(&self.0.service_key())
+            .map(|(_, bitcoin_key)| bitcoin_key)
+            .ok_or_else(|| anyhow!("This node is not an anchoring node."))
+    }
+
     fn verify_sign_input(&self, sign_input: &SignInput) -> anyhow::Result<()> {
         let schema = Schema::new(self.0.service_data());
         let (proposal, inputs) = schema
             .actual_proposed_anchoring_transaction(self.0.data().for_core())
             .ok_or_else(|| anyhow!("Anchoring transaction proposal is absent."))??;
 
-        // Verify transaction content.
-        let input = inputs
-            .get(sign_input.input as usize)
-            .ok_or_else(|| anyhow!("Missing input with index: {}", sign_input.input))?;
+        let config = schema.actual_config();
+        let bitcoin_key = self.bitcoin_key(&config)?;
+        verify_input_signature(
+            &schema,
+            &config,
+            bitcoin_key,
+            &proposal,
+            &inputs,
+            sign_input,
+        )
+    }
+
+    fn verify_sign_inputs(&self, sign_inputs: &SignInputs) -> anyhow::Result<()> {
+        let schema = Schema::new(self.0.service_data());
+        let (proposal, inputs) = schema
+            .actual_proposed_anchoring_transaction(self.0.data().for_core())
+            .ok_or_else(|| anyhow!("Anchoring transaction proposal is absent."))??;
+        ensure!(
+            sign_inputs.txid == proposal.id(),
+            "Transaction {} is not the actual anchoring transaction proposal.",
+            sign_inputs.txid
+        );
+        ensure!(
+            sign_inputs.input_signatures.len() == inputs.len(),
+            "Expected {} input signatures, but got {}.",
+            inputs.len(),
+            sign_inputs.input_signatures.len()
+        );
 
-        // Find corresponding Bitcoin key.
         let config = schema.actual_config();
-        let bitcoin_key = config
+        let bitcoin_key = self.bitcoin_key(&config)?;
+        for (input, input_signature) in sign_inputs.input_signatures.iter().enumerate() {
+            let sign_input = SignInput {
+                txid: sign_inputs.txid,
+                input: input as u32,
+                input_signature: input_signature.clone(),
+            };
+            verify_input_signature(
+                &schema,
+                &config,
+                bitcoin_key,
+                &proposal,
+                &inputs,
+                &sign_input,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn verify_psbt(&self, psbt: &btc::Psbt) -> anyhow::Result<Vec<SignInput>> {
+        let bitcoin_key = Schema::new(self.0.service_data())
+            .actual_config()
             .find_bitcoin_key(&self.0.service_key())
             .ok_or_else(|| anyhow!("This node is not an anchoring node."))?
             .1;
 
-        // Verify input signature.
-        p2wsh::InputSigner::new(config.redeem_script())
-            .verify_input(
-                TxInRef::new(proposal.as_ref(), sign_input.input as usize),
-                input.as_ref(),
-                &bitcoin_key.0,
-                sign_input.input_signature.as_ref(),
-            )
-            .map_err(|e| anyhow!("Input signature verification failed: {}", e))
+        let txid = psbt.id();
+        let sign_inputs = psbt
+            .input_signatures(&bitcoin_key)?
+            .into_iter()
+            .map(|(input, input_signature)| SignInput {
+                txid,
+                input,
+                input_signature,
+            })
+            .collect::<Vec<_>>();
+        ensure!(
+            !sign_inputs.is_empty(),
+            "There are no signatures made by the anchoring key of this node."
+        );
+        // Each signature is verified against the actual anchoring proposal.
+        for sign_input in &sign_inputs {
+            self.verify_sign_input(sign_input)?;
+        }
+        Ok(sign_inputs)
     }
 
     fn verify_funding_tx(&self, tx: &btc::Transaction) -> anyhow::Result<()> {
@@ -299,7 +594,7 @@ impl ApiImpl {
         let schema = Schema::new(self.0.service_data());
         let config = schema.actual_config();
         ensure!(
-            !schema.spent_funding_transactions.contains(&txid),
+            !schema.is_spent_funding_transaction(tx),
             "Funding transaction {} has been already used.",
             txid
         );
@@ -311,19 +606,97 @@ impl ApiImpl {
         Ok(())
     }
 
+    fn verify_fee_bump(&self, bump_fee: &BumpFee) -> anyhow::Result<()> {
+        let schema = Schema::new(self.0.service_data());
+        ensure!(
+            schema.actual_config().replace_by_fee,
+            "Replace-by-fee is disabled in the actual anchoring configuration."
+        );
+
+        let latest_tx = schema
+            .transactions_chain
+            .last()
+            .ok_or_else(|| anyhow!("Anchoring transactions chain is empty."))?;
+        ensure!(
+            latest_tx.id() == bump_fee.txid,
+            "Transaction {} is not the latest anchoring transaction.",
+            bump_fee.txid
+        );
+        ensure!(
+            latest_tx.is_replaceable(),
+            "Transaction {} does not signal replaceability.",
+            bump_fee.txid
+        );
+        ensure!(
+            schema.unconfirmed_transaction() == Some(bump_fee.txid),
+            "Transaction {} has not been reported as unconfirmed.",
+            bump_fee.txid
+        );
+        Ok(())
+    }
+
+    fn verify_unconfirmed_report(&self, report: &ReportUnconfirmed) -> anyhow::Result<()> {
+        let latest_tx = Schema::new(self.0.service_data())
+            .transactions_chain
+            .last()
+            .ok_or_else(|| anyhow!("Anchoring transactions chain is empty."))?;
+        ensure!(
+            latest_tx.id() == report.txid,
+            "Transaction {} is not the latest anchoring transaction.",
+            report.txid
+        );
+        Ok(())
+    }
+
+    fn verify_confirmation_report(&self, report: &ReportConfirmation) -> anyhow::Result<()> {
+        let tx = Schema::new(self.0.service_data())
+            .transactions_chain
+            .get(report.index)
+            .ok_or_else(|| anyhow!("There is no anchoring transaction at {}.", report.index))?;
+        ensure!(
+            tx.id() == report.txid,
+            "Transaction {} is not the anchoring transaction at {}.",
+            report.txid,
+            report.index
+        );
+        ensure!(
+            report.confirmations > 0,
+            "Transaction {} has no confirmations.",
+            report.txid
+        );
+        Ok(())
+    }
+
     fn transaction_proof(&self, tx_index: u64) -> TransactionProof {
         let index_proof = self
             .0
             .data()
             .proof_for_service_index("transactions_chain")
             .unwrap();
-        let transaction_proof = Schema::new(self.0.service_data())
+        let schema = Schema::new(self.0.service_data());
+        let transaction_proof = schema.transactions_chain.get_proof(tx_index);
+        let bitcoin_confirmation = schema
             .transactions_chain
-            .get_proof(tx_index);
+            .get(tx_index)
+            .and_then(|tx| schema.bitcoin_confirmation(&tx.id()));
+        let contract_payload_proof = schema
+            .transactions_chain
+            .get(tx_index)
+            .filter(|tx| schema.contract_payloads.contains(&tx.id()))
+            .map(|tx| ContractPayloadProof {
+                index_proof: self
+                    .0
+                    .data()
+                    .proof_for_service_index("contract_payloads")
+                    .unwrap(),
+                payload_proof: schema.contract_payloads.get_proof(tx.id()),
+            });
 
         TransactionProof {
             index_proof,
             transaction_proof,
+            contract_payload_proof,
+            bitcoin_confirmation,
         }
     }
 }
@@ -344,43 +717,14 @@ impl ApiImpl {
 
     async fn find_transaction(self, height: Option<Height>) -> api::Result<TransactionProof> {
         let anchoring_schema = Schema::new(self.0.service_data());
-        let tx_chain = anchoring_schema.transactions_chain;
+        let tx_chain = &anchoring_schema.transactions_chain;
 
         if tx_chain.is_empty() {
             return Ok(self.transaction_proof(0));
         }
 
         let tx_index = if let Some(height) = height {
-            // Handmade binary search.
-            let f = |index| -> Ordering {
-                // index is always in [0, size), that means index is >= 0 and < size.
-                // index >= 0: by definition
-                // index < size: index = size / 2 + size / 4 + size / 8 ...
-                let other = tx_chain
-                    .get(index)
-                    .unwrap()
-                    .anchoring_payload()
-                    .unwrap()
-                    .block_height;
-                other.cmp(&height)
-            };
-
-            let mut base = 0;
-            let mut size = tx_chain.len();
-            while size > 1 {
-                let half = size / 2;
-                let mid = base + half;
-                let cmp = f(mid);
-                base = if cmp == Greater { base } else { mid };
-                size -= half;
-            }
-            // Don't forget to check base value.
-            let cmp = f(base);
-            if cmp == Equal {
-                base
-            } else {
-                cmp::min(base + (cmp == Less) as u64, tx_chain.len() - 1)
-            }
+            find_transaction_index(&anchoring_schema, height)
         } else {
             tx_chain.len() - 1
         };
@@ -388,6 +732,65 @@ impl ApiImpl {
         Ok(self.transaction_proof(tx_index))
     }
 
+    async fn block_proof(self, height: Height) -> api::Result<BlockProof> {
+        let not_found = || {
+            api::Error::not_found()
+                .title("Block proof is unavailable")
+                .detail(format!(
+                    "Block with height {} is not committed by the anchored blocks root.",
+                    height
+                ))
+        };
+
+        let anchoring_schema = Schema::new(self.0.service_data());
+        let tx_chain = &anchoring_schema.transactions_chain;
+        if tx_chain.is_empty() {
+            return Err(not_found());
+        }
+
+        // The blocks anchored by the transaction which commits to the configuration hash
+        // are committed by the following anchoring transaction.
+        let tx_index = (find_transaction_index(&anchoring_schema, height)..tx_chain.len())
+            .find(|&index| {
+                let tx = tx_chain.get(index).unwrap();
+                !anchoring_schema
+                    .anchoring_payload(&tx)
+                    .unwrap()
+                    .defers_blocks_root()
+            })
+            .ok_or_else(not_found)?;
+        let anchoring_transaction = tx_chain.get(tx_index).unwrap();
+        let payload = anchoring_schema
+            .anchoring_payload(&anchoring_transaction)
+            .unwrap();
+        let blocks_start = anchoring_schema.anchored_blocks_start(tx_index).unwrap();
+        if payload.blocks_root.is_none() || height < blocks_start || height > payload.block_height {
+            return Err(not_found());
+        }
+
+        // Build the list of the anchored blocks hashes in order to create a proof.
+        let block_hashes = self.0.data().for_core().block_hashes_by_height();
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut blocks_list = fork.get_proof_list::<_, Hash>("block_hashes");
+        blocks_list.extend(
+            block_hashes
+                .iter_from(blocks_start.0)
+                .take((payload.block_height.0 - blocks_start.0 + 1) as usize),
+        );
+        let block_hash_proof = blocks_list.get_proof(height.0 - blocks_start.0);
+        let bitcoin_confirmation =
+            anchoring_schema.bitcoin_confirmation(&anchoring_transaction.id());
+
+        Ok(BlockProof {
+            anchoring_transaction,
+            payload,
+            blocks_start,
+            block_hash_proof,
+            bitcoin_confirmation,
+        })
+    }
+
     async fn config(self) -> api::Result<Config> {
         self.actual_config().map_err(api::Error::internal)
     }
@@ -409,6 +812,20 @@ impl ApiImpl {
             .map_err(|e| api::Error::internal(e).title("Sign input request failed"))
     }
 
+    async fn sign_inputs(self, sign_inputs: SignInputs) -> Result<Hash, api::Error> {
+        // Verify Bitcoin signatures.
+        self.verify_sign_inputs(&sign_inputs).map_err(|e| {
+            api::Error::bad_request()
+                .title("Sign inputs request verification has failed")
+                .detail(e.to_string())
+        })?;
+
+        self.broadcaster()?
+            .sign_inputs((), sign_inputs)
+            .await
+            .map_err(|e| api::Error::internal(e).title("Sign inputs request failed"))
+    }
+
     async fn add_funds(self, transaction: btc::Transaction) -> Result<Hash, api::Error> {
         self.verify_funding_tx(&transaction).map_err(|e| {
             api::Error::bad_request()
@@ -422,21 +839,115 @@ impl ApiImpl {
             .map_err(|e| api::Error::internal(e).title("Add funds request failed"))
     }
 
+    async fn bump_fee(self, bump_fee: BumpFee) -> Result<Hash, api::Error> {
+        self.verify_fee_bump(&bump_fee).map_err(|e| {
+            api::Error::bad_request()
+                .title("Fee bump request verification has failed")
+                .detail(e.to_string())
+        })?;
+
+        self.broadcaster()?
+            .bump_fee((), bump_fee)
+            .await
+            .map_err(|e| api::Error::internal(e).title("Bump fee request failed"))
+    }
+
+    async fn report_unconfirmed(self, report: ReportUnconfirmed) -> Result<Hash, api::Error> {
+        self.verify_unconfirmed_report(&report).map_err(|e| {
+            api::Error::bad_request()
+                .title("Unconfirmed transaction report verification has failed")
+                .detail(e.to_string())
+        })?;
+
+        self.broadcaster()?
+            .report_unconfirmed((), report)
+            .await
+            .map_err(|e| api::Error::internal(e).title("Report unconfirmed request failed"))
+    }
+
+    async fn report_confirmation(self, report: ReportConfirmation) -> Result<Hash, api::Error> {
+        self.verify_confirmation_report(&report).map_err(|e| {
+            api::Error::bad_request()
+                .title("Confirmed transaction report verification has failed")
+                .detail(e.to_string())
+        })?;
+
+        self.broadcaster()?
+            .report_confirmation((), report)
+            .await
+            .map_err(|e| api::Error::internal(e).title("Report confirmation request failed"))
+    }
+
     async fn anchoring_proposal(self) -> Result<AnchoringProposalState, api::Error> {
         let core_schema = self.0.data().for_core();
         let anchoring_schema = Schema::new(self.0.service_data());
 
         AnchoringProposalState::try_from_proposal(
-            anchoring_schema.actual_proposed_anchoring_transaction(core_schema),
+            &anchoring_schema,
+            anchoring_schema.actual_anchoring_proposal(core_schema),
         )
     }
 
+    async fn anchoring_proposal_psbt(self) -> Result<Option<btc::Psbt>, api::Error> {
+        let config = Schema::new(self.0.service_data()).actual_config();
+        let psbt = match self.anchoring_proposal().await? {
+            AnchoringProposalState::Available {
+                transaction,
+                inputs,
+                input_commitments,
+                ..
+            } => Some(btc::Psbt::new(
+                &transaction,
+                &inputs,
+                &config.redeem_script(),
+                &input_commitments,
+            )),
+            _ => None,
+        };
+        Ok(psbt)
+    }
+
+    async fn sign_psbt(self, psbt: btc::Psbt) -> Result<Vec<Hash>, api::Error> {
+        let sign_inputs = self.verify_psbt(&psbt).map_err(|e| {
+            api::Error::bad_request()
+                .title("PSBT verification has failed")
+                .detail(e.to_string())
+        })?;
+
+        let broadcaster = self.broadcaster()?;
+        let mut tx_hashes = Vec::with_capacity(sign_inputs.len());
+        for sign_input in sign_inputs {
+            let tx_hash = broadcaster
+                .sign_input((), sign_input)
+                .await
+                .map_err(|e| api::Error::internal(e).title("Sign input request failed"))?;
+            tx_hashes.push(tx_hash);
+        }
+        Ok(tx_hashes)
+    }
+
+    async fn following_config(self) -> api::Result<Option<Config>> {
+        Ok(Schema::new(self.0.service_data()).following_config())
+    }
+
     async fn transaction_with_index(self, index: u64) -> api::Result<Option<btc::Transaction>> {
         Ok(Schema::new(self.0.service_data())
             .transactions_chain
             .get(index))
     }
 
+    async fn transaction_payload(self, index: u64) -> api::Result<Option<btc::Payload>> {
+        let schema = Schema::new(self.0.service_data());
+        Ok(schema
+            .transactions_chain
+            .get(index)
+            .and_then(|tx| schema.anchoring_payload(&tx)))
+    }
+
+    async fn funding_transactions(self) -> api::Result<Vec<btc::Transaction>> {
+        Ok(Schema::new(self.0.service_data()).unspent_funding_transactions())
+    }
+
     async fn transactions_count(self) -> api::Result<AnchoringChainLength> {
         Ok(Schema::new(self.0.service_data())
             .transactions_chain
@@ -452,6 +963,13 @@ pub struct FindTransactionQuery {
     pub height: Option<Height>,
 }
 
+/// Query parameters for the block proof request.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BlockProofQuery {
+    /// Exonum block height.
+    pub height: Height,
+}
+
 /// Query parameters for the anchoring transaction request.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct IndexQuery {
@@ -459,6 +977,74 @@ pub struct IndexQuery {
     pub index: u64,
 }
 
+/// Verifies the signature of the anchoring transaction proposal input made by the given
+/// Bitcoin key.
+fn verify_input_signature(
+    schema: &Schema<impl Access>,
+    config: &Config,
+    bitcoin_key: btc::PublicKey,
+    proposal: &btc::Transaction,
+    inputs: &[btc::Transaction],
+    sign_input: &SignInput,
+) -> anyhow::Result<()> {
+    // Verify transaction content.
+    let input = inputs
+        .get(sign_input.input as usize)
+        .ok_or_else(|| anyhow!("Missing input with index: {}", sign_input.input))?;
+
+    // The input which spends the output committed in the pay-to-contract mode
+    // is signed by the tweaked key.
+    let commitment = schema.contract_commitment(input);
+    let bitcoin_key = match commitment {
+        Some(commitment) => btc::contract_public_key(&bitcoin_key, &commitment),
+        None => bitcoin_key,
+    };
+
+    // Verify input signature.
+    p2wsh::InputSigner::new(config.committed_redeem_script(commitment.as_ref()))
+        .verify_input(
+            TxInRef::new(proposal.as_ref(), sign_input.input as usize),
+            input.as_ref(),
+            &bitcoin_key.0,
+            sign_input.input_signature.as_ref(),
+        )
+        .map_err(|e| anyhow!("Input signature verification failed: {}", e))
+}
+
+/// Returns the index of the anchoring transaction with the given height or of the first one
+/// with the greater height. If there is no such transaction, returns the latest one.
+fn find_transaction_index(schema: &Schema<impl Access>, height: Height) -> u64 {
+    let tx_chain = &schema.transactions_chain;
+    // Handmade binary search.
+    let f = |index| -> Ordering {
+        // index is always in [0, size), that means index is >= 0 and < size.
+        // index >= 0: by definition
+        // index < size: index = size / 2 + size / 4 + size / 8 ...
+        let other = schema
+            .anchoring_payload(&tx_chain.get(index).unwrap())
+            .unwrap()
+            .block_height;
+        other.cmp(&height)
+    };
+
+    let mut base = 0;
+    let mut size = tx_chain.len();
+    while size > 1 {
+        let half = size / 2;
+        let mid = base + half;
+        let cmp = f(mid);
+        base = if cmp == Greater { base } else { mid };
+        size -= half;
+    }
+    // Don't forget to check base value.
+    let cmp = f(base);
+    if cmp == Equal {
+        base
+    } else {
+        cmp::min(base + (cmp == Less) as u64, tx_chain.len() - 1)
+    }
+}
+
 pub(crate) fn wire(builder: &mut ServiceApiBuilder) {
     builder
         .public_scope()
@@ -471,22 +1057,52 @@ pub(crate) fn wire(builder: &mut ServiceApiBuilder) {
         .endpoint("find-transaction", |state, query: FindTransactionQuery| {
             ApiImpl(state).find_transaction(query.height)
         })
+        .endpoint("block-proof", |state, query: BlockProofQuery| {
+            ApiImpl(state).block_proof(query.height)
+        })
         .endpoint("config", |state, _query: ()| ApiImpl(state).config());
     builder
         .private_scope()
         .endpoint_mut("sign-input", |state, query: SignInput| {
             ApiImpl(state).sign_input(query)
         })
+        .endpoint_mut("sign-inputs", |state, query: SignInputs| {
+            ApiImpl(state).sign_inputs(query)
+        })
         .endpoint_mut("add-funds", |state, query: btc::Transaction| {
             ApiImpl(state).add_funds(query)
         })
+        .endpoint_mut("bump-fee", |state, query: BumpFee| {
+            ApiImpl(state).bump_fee(query)
+        })
+        .endpoint_mut("report-unconfirmed", |state, query: ReportUnconfirmed| {
+            ApiImpl(state).report_unconfirmed(query)
+        })
+        .endpoint_mut("report-confirmation", |state, query: ReportConfirmation| {
+            ApiImpl(state).report_confirmation(query)
+        })
+        .endpoint_mut("sign-psbt", |state, query: btc::Psbt| {
+            ApiImpl(state).sign_psbt(query)
+        })
         .endpoint("anchoring-proposal", |state, _query: ()| {
             ApiImpl(state).anchoring_proposal()
         })
+        .endpoint("anchoring-proposal/psbt", |state, _query: ()| {
+            ApiImpl(state).anchoring_proposal_psbt()
+        })
         .endpoint("config", |state, _query: ()| ApiImpl(state).config())
+        .endpoint("config/following", |state, _query: ()| {
+            ApiImpl(state).following_config()
+        })
         .endpoint("transaction", |state, query: IndexQuery| {
             ApiImpl(state).transaction_with_index(query.index)
         })
+        .endpoint("transaction/payload", |state, query: IndexQuery| {
+            ApiImpl(state).transaction_payload(query.index)
+        })
+        .endpoint("funding-transactions", |state, _query: ()| {
+            ApiImpl(state).funding_transactions()
+        })
         .endpoint("transactions-count", |state, _query: ()| {
             ApiImpl(state).transactions_count()
         });
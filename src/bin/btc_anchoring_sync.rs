@@ -0,0 +1,457 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Command line interface of the btc anchoring sync utility.
+
+use anyhow::{anyhow, ensure};
+use bitcoin::util::bip32::ExtendedPrivKey;
+use exonum_btc_anchoring::{
+    api::PrivateApi,
+    blockchain::BumpFee,
+    btc,
+    sync::{
+        runner::{
+            AnchoringSyncRunner, ApiClient, BitcoinRpcConfig, ElectrumConfig, ExtendedKeyConfig,
+            KeyPoolContent, SyncConfig,
+        },
+        AgreementPolicy, Signer, SignerDaemon, SignerSecret,
+    },
+};
+use rand_core::{OsRng, RngCore};
+use structopt::StructOpt;
+
+use std::{collections::HashMap, fs, net::SocketAddr, path::PathBuf};
+
+/// Environment variable with the passphrase of the encrypted key pool.
+const PASSPHRASE_ENV: &str = "BTC_ANCHORING_SYNC_PASSPHRASE";
+
+/// Generate initial configuration for the btc anchoring sync utility.
+#[derive(Debug, StructOpt)]
+struct GenerateConfigCommand {
+    /// Path to a sync utility configuration file which will be created after
+    /// running this command.
+    #[structopt(long, short = "o", default_value = "btc_anchoring_sync.toml")]
+    output: PathBuf,
+    /// Anchoring node private API url address.
+    #[structopt(long, short = "e", default_value = "http://localhost:8081")]
+    exonum_private_api: String,
+    /// Anchoring node public API url address, which is used to check the anchored
+    /// block hashes.
+    #[structopt(long, default_value = "http://localhost:8080")]
+    exonum_public_api: String,
+    /// Bitcoin network type.
+    #[structopt(long, short = "n", default_value = "testnet")]
+    bitcoin_network: bitcoin::Network,
+    /// Name of the anchoring service instance.
+    #[structopt(long, short = "i", default_value = "anchoring")]
+    instance_name: String,
+    /// Bitcoin RPC url.
+    #[structopt(long)]
+    bitcoin_rpc_host: Option<String>,
+    /// Bitcoin RPC username.
+    #[structopt(long)]
+    bitcoin_rpc_user: Option<String>,
+    /// Bitcoin RPC password.
+    #[structopt(long)]
+    bitcoin_rpc_password: Option<String>,
+    /// Base URL of the Esplora API, which is used instead of the Bitcoin RPC to broadcast
+    /// anchoring transactions.
+    #[structopt(long)]
+    esplora_url: Option<String>,
+    /// Address of the Electrum server in the form `{host}:{port}`, which is used instead of
+    /// the Bitcoin RPC to broadcast anchoring transactions.
+    #[structopt(long)]
+    electrum_address: Option<String>,
+    /// Connect to the Electrum server over TLS.
+    #[structopt(long, requires = "electrum-address")]
+    electrum_tls: bool,
+    /// Policy of agreement between the Bitcoin relays: `first-success`, `majority` or
+    /// `min-confirmations`. It is required if several relays are specified.
+    #[structopt(long)]
+    relay_agreement: Option<AgreementPolicy>,
+    /// Derive the anchoring keys from a new BIP-32 extended private key instead of
+    /// generating them separately.
+    #[structopt(long)]
+    hd: bool,
+    /// Derive the anchoring keys from the given BIP-32 extended private key.
+    #[structopt(long, conflicts_with = "hd")]
+    xprv: Option<ExtendedPrivKey>,
+    #[structopt(flatten)]
+    passphrase: PassphraseOptions,
+}
+
+#[derive(Debug, StructOpt)]
+struct RunCommand {
+    /// Path to a sync utility configuration file.
+    #[structopt(long, short = "c")]
+    config: PathBuf,
+    #[structopt(flatten)]
+    passphrase: PassphraseOptions,
+}
+
+/// Generates a new Bitcoin key pair and add them to the key pool of the specified
+/// configuration file. If the config uses the BIP-32 extended key, the key with the next
+/// rotation index is derived instead.
+#[derive(Debug, StructOpt)]
+struct GenerateKeypairCommand {
+    /// Path to a sync utility configuration file.
+    #[structopt(long, short = "c")]
+    config: PathBuf,
+    #[structopt(flatten)]
+    passphrase: PassphraseOptions,
+}
+
+/// Encrypts the plaintext key pool of the specified configuration file.
+#[derive(Debug, StructOpt)]
+struct EncryptKeysCommand {
+    /// Path to a sync utility configuration file.
+    #[structopt(long, short = "c")]
+    config: PathBuf,
+    #[structopt(flatten)]
+    passphrase: PassphraseOptions,
+}
+
+/// Runs the signer daemon, which signs anchoring transactions by the keys from the key pool
+/// of the specified configuration file on requests of the sync utility.
+#[derive(Debug, StructOpt)]
+struct RunSignerCommand {
+    /// Path to a sync utility configuration file.
+    #[structopt(long, short = "c")]
+    config: PathBuf,
+    /// Address to listen to the requests of the sync utility.
+    #[structopt(long, short = "l", default_value = "127.0.0.1:8091")]
+    listen_address: SocketAddr,
+    /// Path to a file with the secret shared with the sync utility, which authenticates
+    /// its requests.
+    #[structopt(long)]
+    secret_file: PathBuf,
+    #[structopt(flatten)]
+    passphrase: PassphraseOptions,
+}
+
+/// Requests to replace the latest anchoring transaction, which has been reported as
+/// unconfirmed, by the one with the given fee rate. The replacement is accepted once
+/// 2/3+1 anchoring nodes have requested the same fee rate.
+#[derive(Debug, StructOpt)]
+struct BumpFeeCommand {
+    /// Path to a sync utility configuration file.
+    #[structopt(long, short = "c")]
+    config: PathBuf,
+    /// Fee rate of the replacement in satoshis per virtual byte.
+    #[structopt(long)]
+    fee: u64,
+}
+
+/// Source of the passphrase of the encrypted key pool.
+#[derive(Debug, StructOpt)]
+struct PassphraseOptions {
+    /// Path to a file with the passphrase of the encrypted key pool. If not specified,
+    /// the passphrase is taken from the `BTC_ANCHORING_SYNC_PASSPHRASE` environment variable.
+    #[structopt(long)]
+    passphrase_file: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Commands {
+    /// Generate initial configuration for the btc anchoring sync utility.
+    GenerateConfig(GenerateConfigCommand),
+    /// Run btc anchoring sync utility.
+    Run(RunCommand),
+    /// Generate a new Bitcoin key pair and add them to the key pool of the specified
+    /// configuration file.
+    GenerateKeypair(GenerateKeypairCommand),
+    /// Run the signer daemon, which signs anchoring transactions on requests of the btc
+    /// anchoring sync utility.
+    RunSigner(RunSignerCommand),
+    /// Encrypt the plaintext key pool of the specified configuration file.
+    EncryptKeys(EncryptKeysCommand),
+    /// Request to replace the latest unconfirmed anchoring transaction by the one
+    /// with a higher fee rate.
+    BumpFee(BumpFeeCommand),
+}
+
+impl PassphraseOptions {
+    /// Reads the passphrase from the file or from the environment variable.
+    fn passphrase(&self) -> anyhow::Result<String> {
+        let passphrase = if let Some(path) = &self.passphrase_file {
+            // Ignore the trailing newline, which is added by most text editors.
+            fs::read_to_string(path)?
+                .trim_end_matches(&['\r', '\n'][..])
+                .to_owned()
+        } else {
+            std::env::var(PASSPHRASE_ENV).map_err(|_| {
+                anyhow!(
+                    "Passphrase of the encrypted key pool is not specified, use either \
+                     the `--passphrase-file` option or the `{}` environment variable.",
+                    PASSPHRASE_ENV
+                )
+            })?
+        };
+        ensure!(!passphrase.is_empty(), "Passphrase should not be empty.");
+        Ok(passphrase)
+    }
+}
+
+impl GenerateConfigCommand {
+    fn run(self) -> anyhow::Result<()> {
+        let xprv = if self.hd {
+            let mut seed = [0_u8; 32];
+            OsRng.fill_bytes(&mut seed);
+            Some(ExtendedPrivKey::new_master(self.bitcoin_network, &seed)?)
+        } else {
+            self.xprv
+        };
+
+        let mut key_pool = KeyPoolContent::default();
+        let (bitcoin_pub_key, bitcoin_extended_key) = if let Some(xprv) = xprv {
+            key_pool.extended_private_key = Some(xprv.to_string());
+            let bitcoin_pub_key = btc::derive_public_key(&xprv, 0)?;
+            let extended_key = ExtendedKeyConfig {
+                public_keys: vec![bitcoin_pub_key],
+            };
+            (bitcoin_pub_key, Some(extended_key))
+        } else {
+            let bitcoin_keypair = btc::gen_keypair(self.bitcoin_network);
+            let bitcoin_pub_key = bitcoin_keypair.0;
+            key_pool.keys.extend(std::iter::once(bitcoin_keypair));
+            (bitcoin_pub_key, None)
+        };
+
+        let bitcoin_rpc_config = self.bitcoin_rpc_config();
+        let relays_count = [
+            bitcoin_rpc_config.is_some(),
+            self.esplora_url.is_some(),
+            self.electrum_address.is_some(),
+        ]
+        .iter()
+        .filter(|&&is_set| is_set)
+        .count();
+        ensure!(
+            relays_count <= 1 || self.relay_agreement.is_some(),
+            "Several Bitcoin relays are specified, choose how they should agree \
+             via the `--relay-agreement` option"
+        );
+        let mut sync_config = SyncConfig {
+            exonum_private_api: self.exonum_private_api,
+            exonum_public_api: self.exonum_public_api,
+            bitcoin_key_pool: HashMap::new(),
+            encrypted_key_pool: None,
+            bitcoin_extended_key,
+            instance_name: self.instance_name,
+            bitcoin_rpc_config,
+            esplora_url: self.esplora_url,
+            electrum: self.electrum_address.map(|address| ElectrumConfig {
+                address,
+                tls: self.electrum_tls,
+            }),
+            relay_agreement: self.relay_agreement,
+            remote_signer: None,
+            max_fee: None,
+            sync_state_file: Some(self.output.with_file_name("sync_state.toml")),
+        };
+        sync_config.lock_key_pool(&key_pool, &self.passphrase.passphrase()?)?;
+
+        sync_config.save(self.output)?;
+        log::info!("Generated initial configuration for the btc anchoring sync util.");
+        log::trace!(
+            "Available Bitcoin keys in key pool: {:?}",
+            key_pool.keys.keys().collect::<Vec<_>>()
+        );
+        // Print the received Bitcoin public key to use it in scripts.
+        println!("{}", bitcoin_pub_key);
+        Ok(())
+    }
+
+    fn bitcoin_rpc_config(&self) -> Option<BitcoinRpcConfig> {
+        self.bitcoin_rpc_host.clone().map(|host| BitcoinRpcConfig {
+            host,
+            user: self.bitcoin_rpc_user.clone(),
+            password: self.bitcoin_rpc_password.clone(),
+        })
+    }
+}
+
+impl RunCommand {
+    async fn run(self) -> anyhow::Result<()> {
+        let sync_config = SyncConfig::load(self.config)?;
+
+        // Delegate signing to the signer daemon, if it is specified.
+        if let Some(remote_signer) = sync_config.remote_signer()? {
+            let runner = AnchoringSyncRunner::new(sync_config.api_client(), remote_signer);
+            run_sync(runner, &sync_config).await
+        } else {
+            let key_pool = sync_config
+                .key_pool(sync_config.unlock_key_pool(|| self.passphrase.passphrase())?)?;
+            let runner = AnchoringSyncRunner::new(sync_config.api_client(), key_pool);
+            run_sync(runner, &sync_config).await
+        }
+    }
+}
+
+async fn run_sync<S>(
+    mut runner: AnchoringSyncRunner<ApiClient, S>,
+    sync_config: &SyncConfig,
+) -> anyhow::Result<()>
+where
+    S: Signer + 'static,
+    S::Error: Into<anyhow::Error>,
+{
+    if let Some(max_fee) = sync_config.max_fee {
+        runner = runner.with_max_fee(max_fee);
+    }
+    if let Some(path) = sync_config.sync_state_file.as_ref() {
+        runner = runner.with_state_file(path);
+    }
+
+    if let Some(relay) = sync_config.composite_relay()? {
+        runner.with_bitcoin_relay(relay).run().await
+    } else if let Some(relay) = sync_config.esplora_relay() {
+        runner.with_bitcoin_relay(relay).run().await
+    } else if let Some(relay) = sync_config.electrum_relay() {
+        runner.with_bitcoin_relay(relay).run().await
+    } else if let Some(relay) = sync_config.bitcoin_relay()? {
+        runner.with_bitcoin_relay(relay).run().await
+    } else {
+        runner.run().await
+    }
+}
+
+impl RunSignerCommand {
+    async fn run(self) -> anyhow::Result<()> {
+        let sync_config = SyncConfig::load(self.config)?;
+        let key_pool =
+            sync_config.key_pool(sync_config.unlock_key_pool(|| self.passphrase.passphrase())?)?;
+        let secret = SignerSecret::load(&self.secret_file)?;
+        if !self.listen_address.ip().is_loopback() {
+            log::warn!(
+                "Signer daemon is listening on the non-loopback address {}, its requests \
+                 are not encrypted.",
+                self.listen_address
+            );
+        }
+        log::info!("Signer daemon is listening on {}", self.listen_address);
+        SignerDaemon::new(key_pool, secret)
+            .listen(self.listen_address)
+            .await
+    }
+}
+
+impl GenerateKeypairCommand {
+    fn run(self) -> anyhow::Result<()> {
+        let mut sync_config = SyncConfig::load(&self.config)?;
+        let mut key_pool = sync_config.unlock_key_pool(|| self.passphrase.passphrase())?;
+
+        // Derive the key with the next rotation index from the extended private key,
+        // the key pool remains unchanged in this case.
+        if let Some(extended_key) = sync_config.bitcoin_extended_key.as_mut() {
+            let xprv = key_pool
+                .extended_private_key()?
+                .ok_or_else(|| anyhow!("Extended private key is absent in the key pool."))?;
+            let bitcoin_pub_key = btc::derive_public_key(&xprv, extended_key.index() + 1)?;
+            extended_key.public_keys.push(bitcoin_pub_key);
+            sync_config.save(self.config)?;
+            println!("{}", bitcoin_pub_key);
+            return Ok(());
+        }
+
+        // Extract Bitcoin network type from the one of Bitcoin private keys in the pool.
+        let network = key_pool
+            .keys
+            .values()
+            .next()
+            .map(|key| key.0.network)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unable to determine Bitcoin network type from config.\
+                     Perhaps pool of keys in config is empty."
+                )
+            })?;
+        let bitcoin_keypair = btc::gen_keypair(network);
+        let bitcoin_pub_key = bitcoin_keypair.0;
+
+        key_pool.keys.extend(std::iter::once(bitcoin_keypair));
+        sync_config.lock_key_pool(&key_pool, &self.passphrase.passphrase()?)?;
+        sync_config.save(self.config)?;
+        // Print the received Bitcoin public key to use it in scripts.
+        println!("{}", bitcoin_pub_key);
+        Ok(())
+    }
+}
+
+impl EncryptKeysCommand {
+    fn run(self) -> anyhow::Result<()> {
+        let mut sync_config = SyncConfig::load(&self.config)?;
+        ensure!(
+            !sync_config.bitcoin_key_pool.is_empty(),
+            "There are no plaintext Bitcoin keys in the config."
+        );
+
+        // Plaintext keys are merged with the already encrypted ones, if any.
+        let key_pool = sync_config.unlock_key_pool(|| self.passphrase.passphrase())?;
+        sync_config.lock_key_pool(&key_pool, &self.passphrase.passphrase()?)?;
+        sync_config.save(self.config)?;
+        log::info!("Encrypted {} Bitcoin keys.", key_pool.keys.len());
+        Ok(())
+    }
+}
+
+impl BumpFeeCommand {
+    async fn run(self) -> anyhow::Result<()> {
+        let sync_config = SyncConfig::load(self.config)?;
+        let api_client = sync_config.api_client();
+
+        let index = api_client
+            .transactions_count()
+            .await?
+            .value
+            .checked_sub(1)
+            .ok_or_else(|| anyhow!("Anchoring transactions chain is empty."))?;
+        let txid = api_client
+            .transaction_with_index(index)
+            .await?
+            .ok_or_else(|| anyhow!("There is no anchoring transaction at {}.", index))?
+            .id();
+        let hash = api_client
+            .bump_fee(BumpFee {
+                txid,
+                fee: self.fee,
+            })
+            .await?;
+        log::info!(
+            "Requested to replace the anchoring transaction {} in the Exonum transaction {}.",
+            txid,
+            hash.to_hex()
+        );
+        Ok(())
+    }
+}
+
+impl Commands {
+    async fn run(self) -> anyhow::Result<()> {
+        match self {
+            Commands::GenerateConfig(cmd) => cmd.run(),
+            Commands::GenerateKeypair(cmd) => cmd.run(),
+            Commands::Run(cmd) => cmd.run().await,
+            Commands::RunSigner(cmd) => cmd.run().await,
+            Commands::EncryptKeys(cmd) => cmd.run(),
+            Commands::BumpFee(cmd) => cmd.run().await,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    exonum::helpers::init_logger()?;
+    Commands::from_args().run().await
+}
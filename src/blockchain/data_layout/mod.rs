@@ -72,6 +72,56 @@ impl ObjectHash for TxInputId {
     }
 }
 
+/// Unique transaction output identifier composed of a transaction identifier
+/// and an output index.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TxOutputId {
+    /// Transaction identifier.
+    pub txid: Sha256d,
+    /// Transaction output index.
+    pub output: u32,
+}
+
+impl TxOutputId {
+    /// Creates a new identifier.
+    pub fn new(txid: Sha256d, output: u32) -> Self {
+        Self { txid, output }
+    }
+}
+
+impl BinaryKey for TxOutputId {
+    fn size(&self) -> usize {
+        self.txid.size() + self.output.size()
+    }
+
+    fn read(inp: &[u8]) -> Self {
+        let mut reader = Cursor::new(inp);
+
+        let txid = {
+            let mut txid = [0_u8; 32];
+            let _ = reader.read(&mut txid).unwrap();
+            Sha256d::new(txid)
+        };
+        let output = reader.read_u32::<LittleEndian>().unwrap();
+        Self { txid, output }
+    }
+
+    fn write(&self, out: &mut [u8]) -> usize {
+        let mut writer = Cursor::new(out);
+        let _ = writer.write(&self.txid.0[..]).unwrap();
+        writer.write_u32::<LittleEndian>(self.output).unwrap();
+        self.size()
+    }
+}
+
+impl ObjectHash for TxOutputId {
+    fn object_hash(&self) -> Hash {
+        let mut bytes = [0_u8; 36];
+        self.write(&mut bytes);
+        crypto::hash(bytes.as_ref())
+    }
+}
+
 #[test]
 fn test_tx_input_id_binary_key() {
     let txout = TxInputId {
@@ -88,3 +138,20 @@ fn test_tx_input_id_binary_key() {
     let buf_hash = crypto::hash(&buf);
     assert_eq!(txout2.object_hash(), buf_hash);
 }
+
+#[test]
+fn test_tx_output_id_binary_key() {
+    let txout = TxOutputId {
+        txid: Sha256d::from_slice(crypto::hash(&[1, 2, 3]).as_ref()).unwrap(),
+        output: 2,
+    };
+
+    let mut buf = vec![0_u8; txout.size()];
+    txout.write(&mut buf);
+
+    let txout2 = TxOutputId::read(&buf);
+    assert_eq!(txout, txout2);
+
+    let buf_hash = crypto::hash(&buf);
+    assert_eq!(txout2.object_hash(), buf_hash);
+}
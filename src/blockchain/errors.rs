@@ -36,6 +36,22 @@ pub enum Error {
     AlreadyUsedFundingTx = 5,
     /// Funding transaction is unsuitable.
     UnsuitableFundingTx = 6,
+    /// Replace-by-fee is disabled in the actual anchoring configuration.
+    ReplaceByFeeDisabled = 7,
+    /// The transaction to be replaced is not the latest replaceable anchoring transaction
+    /// or it has not been reported as unconfirmed.
+    UnexpectedReplacedTx = 8,
+    /// The new fee does not exceed the fee of the replaced transaction by the minimal
+    /// relay fee.
+    InsufficientFeeBump = 9,
+    /// The reported transaction is not the latest anchoring transaction.
+    UnexpectedUnconfirmedTx = 10,
+    /// The reported transaction is absent in the anchoring chain at the given index
+    /// or has no confirmations.
+    UnexpectedConfirmedTx = 11,
+    /// The number of input signatures does not match the number of inputs of the anchoring
+    /// proposal.
+    UnexpectedInputSignatures = 12,
 }
 
 impl Error {
@@ -15,7 +15,10 @@
 //! Blockchain implementation details for the BTC anchoring service.
 
 pub use self::{schema::Schema, transactions::BtcAnchoringInterface};
-pub use crate::proto::{AddFunds, SignInput};
+pub use crate::proto::{
+    AddFunds, BitcoinConfirmation, BumpFee, ReportConfirmation, ReportUnconfirmed, SignInput,
+    SignInputs, SkippedHeights,
+};
 
 use bitcoin::blockdata::script::Script;
 use btc_transaction_utils::{multisig::RedeemScript, p2wsh};
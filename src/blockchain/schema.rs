@@ -14,34 +14,56 @@
 
 //! Information schema for the btc anchoring service.
 
-use exonum::{blockchain::Schema as CoreSchema, helpers::Height};
+use bitcoin::blockdata::script::Script;
+use btc_transaction_utils::multisig::RedeemScript;
+use exonum::{blockchain::Schema as CoreSchema, crypto::Hash, helpers::Height};
 use exonum_derive::FromAccess;
 use exonum_merkledb::{
     access::{Access, FromAccess, RawAccessMut},
-    Entry, ProofListIndex, ProofMapIndex,
+    Entry, HashTag, ObjectHash, ProofListIndex, ProofMapIndex,
 };
 use log::{error, trace};
 
+use std::cmp;
+
 use crate::{
-    btc::{self, BtcAnchoringTransactionBuilder, BuilderError, Sha256d, Transaction},
-    config::Config,
-    proto::BinaryMap,
+    btc::{
+        self, contract_redeem_script, BtcAnchoringTransactionBuilder, BuilderError, Payload,
+        Sha256d, Transaction,
+    },
+    config::{CatchUpPolicy, Config},
+    proto::{AnchoredBlocksRoot, BinaryMap},
 };
 
-use super::{data_layout::*, BtcAnchoringState};
+use super::{
+    data_layout::*, BitcoinConfirmation, BtcAnchoringState, BumpFee, ReportConfirmation,
+    ReportUnconfirmed, SkippedHeights,
+};
 
 /// A set of signatures for a transaction input ordered by the anchoring node identifiers.
 pub type InputSignatures = BinaryMap<u16, btc::InputSignature>;
 /// A set of funding transaction confirmations.
 pub type TransactionConfirmations = BinaryMap<btc::PublicKey, ()>;
+/// A set of the latest confirmation reports of the anchoring nodes.
+pub type ConfirmationReports = BinaryMap<btc::PublicKey, ReportConfirmation>;
+
+/// Maximum number of inputs of the anchoring transaction. One of them is reserved for the
+/// previous anchoring transaction, and the rest can be used to spend the unspent funding
+/// transactions. The remaining funding transactions are spent by the following proposals.
+pub const MAX_ANCHORING_TX_INPUTS: usize = 10;
+
+/// Maximum number of blocks committed by the Merkle root of the anchored blocks in
+/// the payload of a single anchoring transaction. If the anchoring has been stalled for
+/// longer, then only the latest blocks up to the anchored height are committed.
+pub const MAX_ANCHORED_BLOCKS: u64 = 20_000;
 
 /// Information schema for `exonum-btc-anchoring`.
 #[derive(Debug, FromAccess)]
 pub struct Schema<T: Access> {
     /// Complete chain of the anchoring transactions.
     pub transactions_chain: ProofListIndex<T::Base, Transaction>,
-    /// Already spent funding transactions.
-    pub(crate) spent_funding_transactions: ProofMapIndex<T::Base, Sha256d, Transaction>,
+    /// Already spent outputs of the funding transactions.
+    pub(crate) spent_funding_outputs: ProofMapIndex<T::Base, TxOutputId, Transaction>,
     /// Signatures for the given transaction input.
     pub(crate) transaction_signatures: ProofMapIndex<T::Base, TxInputId, InputSignatures>,
     /// Actual anchoring configuration entry.
@@ -51,8 +73,45 @@ pub struct Schema<T: Access> {
     /// Confirmations for the corresponding funding transaction.
     pub(crate) unconfirmed_funding_transactions:
         ProofMapIndex<T::Base, Sha256d, TransactionConfirmations>,
-    /// Entry that may contain an unspent funding transaction for the
-    /// actual configuration.
+    /// Unspent funding transactions for the actual configuration.
+    pub(crate) unspent_funding_transactions: ProofMapIndex<T::Base, Sha256d, Transaction>,
+    /// Confirmations for the corresponding fee bump request.
+    pub(crate) unconfirmed_fee_bumps: ProofMapIndex<T::Base, Hash, TransactionConfirmations>,
+    /// Entry that may contain an accepted request to replace the latest anchoring
+    /// transaction by the one with a higher fee.
+    pub(crate) fee_bump: Entry<T::Base, BumpFee>,
+    /// Anchoring transactions which have been replaced by ones with a higher fee.
+    pub(crate) replaced_transactions: ProofMapIndex<T::Base, Sha256d, Transaction>,
+    /// Confirmations for the corresponding unconfirmed transaction report.
+    pub(crate) unconfirmed_reports: ProofMapIndex<T::Base, Hash, TransactionConfirmations>,
+    /// Entry that may contain an identifier of the latest anchoring transaction which
+    /// has been reported as unconfirmed in the Bitcoin network.
+    pub(crate) unconfirmed_transaction: Entry<T::Base, Sha256d>,
+    /// Hash of the anchoring configuration committed by the latest anchoring transaction
+    /// which payload contains it.
+    pub(crate) anchored_config_hash: Entry<T::Base, Hash>,
+    /// Payloads of the anchoring transactions committed in the pay-to-contract mode.
+    pub contract_payloads: ProofMapIndex<T::Base, Sha256d, Payload>,
+    /// The latest confirmation reports of the anchoring nodes for the corresponding
+    /// anchoring transaction, which are collected until the quorum agrees on them.
+    pub(crate) confirmation_reports: ProofMapIndex<T::Base, Sha256d, ConfirmationReports>,
+    /// Bitcoin confirmation status of the anchoring transactions accepted by the quorum
+    /// of the anchoring nodes.
+    pub bitcoin_confirmations: ProofMapIndex<T::Base, Sha256d, BitcoinConfirmation>,
+    /// Ranges of the anchoring heights skipped by the anchoring transactions
+    /// in the catch-up mode.
+    pub skipped_heights: ProofListIndex<T::Base, SkippedHeights>,
+    /// Indexes in the anchoring chain of the transactions which have swept the anchoring
+    /// funds to the sweep address.
+    pub sweep_transactions: ProofMapIndex<T::Base, Sha256d, u64>,
+    /// Merkle root of the anchored blocks committed by the proposal of the next anchoring
+    /// transaction, which is computed once the anchored block is committed.
+    pub(crate) proposal_blocks_root: Entry<T::Base, AnchoredBlocksRoot>,
+    /// Already spent funding transactions recorded by the previous versions of the service.
+    pub(crate) spent_funding_transactions: ProofMapIndex<T::Base, Sha256d, Transaction>,
+    /// Entry that may contain an unspent funding transaction recorded by the previous
+    /// versions of the service. It is moved to the unspent funding transactions as soon
+    /// as the schema is modified.
     pub(crate) unspent_funding_transaction: Entry<T::Base, Transaction>,
 }
 
@@ -80,9 +139,168 @@ impl<T: Access> Schema<T> {
         self.transaction_signatures.get(input).unwrap_or_default()
     }
 
-    /// Returns an unspent funding transaction for the actual configurations if it exists.
-    pub fn unspent_funding_transaction(&self) -> Option<Transaction> {
-        self.unspent_funding_transaction.get()
+    /// Returns the list of unspent funding transactions for the actual configuration.
+    pub fn unspent_funding_transactions(&self) -> Vec<Transaction> {
+        self.unspent_funding_transaction
+            .get()
+            .into_iter()
+            .chain(self.unspent_funding_transactions.values())
+            .collect()
+    }
+
+    /// Returns an accepted request to replace the latest anchoring transaction if it exists.
+    pub fn fee_bump(&self) -> Option<BumpFee> {
+        self.fee_bump.get()
+    }
+
+    /// Returns an anchoring transaction with the given identifier which has been replaced
+    /// by the one with a higher fee.
+    pub fn replaced_transaction(&self, txid: &Sha256d) -> Option<Transaction> {
+        self.replaced_transactions.get(txid)
+    }
+
+    /// Returns an identifier of the latest anchoring transaction if it has been reported
+    /// as unconfirmed in the Bitcoin network.
+    pub fn unconfirmed_transaction(&self) -> Option<Sha256d> {
+        self.unconfirmed_transaction.get()
+    }
+
+    /// Returns the Bitcoin confirmation status of the given anchoring transaction
+    /// if it has been reported by the quorum of the anchoring nodes.
+    pub fn bitcoin_confirmation(&self, txid: &Sha256d) -> Option<BitcoinConfirmation> {
+        self.bitcoin_confirmations.get(txid)
+    }
+
+    /// Returns the hash of the anchoring configuration committed by the anchoring
+    /// transactions chain, if any.
+    pub fn anchored_config_hash(&self) -> Option<Hash> {
+        self.anchored_config_hash.get()
+    }
+
+    /// Returns the latest anchoring transaction if it has swept the anchoring funds to
+    /// the sweep address. Its output cannot be spent by the anchoring nodes, so
+    /// the following anchoring transactions refer to it in the payload instead.
+    pub fn sweep_transaction(&self) -> Option<Transaction> {
+        self.transactions_chain
+            .last()
+            .filter(|tx| self.sweep_transactions.contains(&tx.id()))
+    }
+
+    /// Returns the payload of the given anchoring transaction. The payload is either
+    /// extracted from the `OP_RETURN` output or, if the transaction has been committed
+    /// in the pay-to-contract mode, taken from the service data.
+    pub fn anchoring_payload(&self, tx: &Transaction) -> Option<Payload> {
+        tx.anchoring_payload()
+            .or_else(|| self.contract_payloads.get(&tx.id()))
+    }
+
+    /// Returns the pay-to-contract commitment of the output of the given anchoring
+    /// transaction if it has been committed in the pay-to-contract mode. The input which
+    /// spends such output should be signed by the keys tweaked by this commitment.
+    pub fn contract_commitment(&self, tx: &Transaction) -> Option<Hash> {
+        self.contract_payloads
+            .get(&tx.id())
+            .map(|payload| payload.commitment())
+    }
+
+    /// Returns the script pubkey of the output of the given anchoring transaction
+    /// which is locked by the given redeem script.
+    fn anchoring_output_script(&self, tx: &Transaction, redeem_script: &RedeemScript) -> Script {
+        match self.contract_commitment(tx) {
+            Some(commitment) => contract_redeem_script(redeem_script, &commitment)
+                .as_ref()
+                .to_v0_p2wsh(),
+            None => redeem_script.as_ref().to_v0_p2wsh(),
+        }
+    }
+
+    /// Returns the fee per virtual byte of the package consisting of the given anchoring
+    /// transaction proposal and the latest anchoring transaction if the proposal accelerates
+    /// it, i.e. the latest anchoring transaction has been reported as unconfirmed.
+    pub fn package_fee_rate(&self, proposal: &Transaction, inputs: &[Transaction]) -> Option<u64> {
+        let latest_tx = self.transactions_chain.last()?;
+        if proposal.prev_tx_id() != latest_tx.id() {
+            return None;
+        }
+
+        let (parent_vsize, parent_fee) = self.unconfirmed_parent(&latest_tx)?;
+        let vsize = proposal.estimate_signed_vsize(&self.actual_config().redeem_script());
+        let fee = proposal.fee(inputs)?;
+        Some((parent_fee + fee) / (parent_vsize + vsize))
+    }
+
+    /// Returns the virtual size and the fee of the given latest anchoring transaction
+    /// if it has been reported as unconfirmed.
+    fn unconfirmed_parent(&self, latest_tx: &Transaction) -> Option<(u64, u64)> {
+        if self.unconfirmed_transaction.get()? != latest_tx.id() {
+            return None;
+        }
+
+        let fee = latest_tx.fee(&self.latest_transaction_inputs()?)?;
+        Some((latest_tx.vsize(), fee))
+    }
+
+    /// Returns the list of transactions whose outputs are spent by the latest
+    /// anchoring transaction.
+    pub fn latest_transaction_inputs(&self) -> Option<Vec<Transaction>> {
+        let latest_tx = self.transactions_chain.last()?;
+        let prev_tx = self
+            .transactions_chain
+            .len()
+            .checked_sub(2)
+            .and_then(|index| self.transactions_chain.get(index));
+
+        latest_tx
+            .0
+            .input
+            .iter()
+            .map(|txin| {
+                let txid = Sha256d::from(txin.previous_output.txid);
+                match prev_tx {
+                    Some(ref prev_tx) if prev_tx.id() == txid => Some(prev_tx.clone()),
+                    _ => self
+                        .spent_funding_outputs
+                        .get(&TxOutputId::new(txid, txin.previous_output.vout))
+                        .or_else(|| self.spent_funding_transactions.get(&txid)),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the unspent outputs of the funding transactions locked by the given script
+    /// with the corresponding funding transactions.
+    fn unspent_funding_outputs(&self, script_pubkey: &Script) -> Vec<(usize, Transaction)> {
+        self.unspent_funding_transactions()
+            .into_iter()
+            .flat_map(|tx| {
+                let txid = tx.id();
+                tx.find_outs(script_pubkey)
+                    .map(|(out, _)| out)
+                    .filter(|&out| {
+                        !self
+                            .spent_funding_outputs
+                            .contains(&TxOutputId::new(txid, out as u32))
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .map(move |out| (out, tx.clone()))
+            })
+            .collect()
+    }
+
+    /// Checks that some of the given funding transaction outputs to the actual anchoring
+    /// address have been already spent.
+    pub fn is_spent_funding_transaction(&self, transaction: &Transaction) -> bool {
+        let txid = transaction.id();
+        if self.spent_funding_transactions.contains(&txid) {
+            return true;
+        }
+        transaction
+            .find_outs(&self.actual_config().anchoring_out_script())
+            .any(|(out, _)| {
+                self.spent_funding_outputs
+                    .contains(&TxOutputId::new(txid, out as u32))
+            })
     }
 
     /// Returns an actual state of anchoring.
@@ -108,61 +326,201 @@ impl<T: Access> Schema<T> {
         core_schema: CoreSchema<impl Access>,
         actual_state: &BtcAnchoringState,
     ) -> Option<Result<(Transaction, Vec<Transaction>), BuilderError>> {
+        self.anchoring_proposal(core_schema, actual_state)
+            .map(|proposal| proposal.map(|(transaction, inputs, _)| (transaction, inputs)))
+    }
+
+    /// Returns the proposal of the next anchoring transaction for the given anchoring state
+    /// along with the payload committed by the proposal.
+    pub fn anchoring_proposal(
+        &self,
+        core_schema: CoreSchema<impl Access>,
+        actual_state: &BtcAnchoringState,
+    ) -> Option<Result<(Transaction, Vec<Transaction>, Payload), BuilderError>> {
         let config = actual_state.actual_config();
+        // The anchoring is resumed from the following anchoring height after the pause.
+        if config.paused {
+            trace!("Anchoring is paused by the actual configuration.");
+            return None;
+        }
+        // Replacement of the latest anchoring transaction takes precedence over
+        // the anchoring of the following height.
+        if let Some(fee_bump) = self.fee_bump.get() {
+            return Some(self.replacement_proposal(config, &fee_bump));
+        }
+
         let unspent_anchoring_transaction = self.transactions_chain.last();
-        let unspent_funding_transaction = self.unspent_funding_transaction.get();
+        let mut unspent_funding_outputs =
+            self.unspent_funding_outputs(&config.anchoring_out_script());
+        // Leave one input for the previous anchoring transaction.
+        unspent_funding_outputs.truncate(MAX_ANCHORING_TX_INPUTS - 1);
+
+        let sweep_script = config
+            .sweep_address
+            .as_ref()
+            .map(|address| address.0.script_pubkey());
 
         let mut builder = BtcAnchoringTransactionBuilder::new(&config.redeem_script());
+        builder.replaceable(config.replace_by_fee);
+        // Sweep transaction pays to the external address, so its payload is committed
+        // by the `OP_RETURN` output in any case.
+        if let Some(script) = sweep_script.clone() {
+            builder.transit_to(script);
+        } else if config.pay_to_contract {
+            builder.pay_to_contract(actual_state.redeem_script());
+        }
         // First anchoring transaction doesn't have previous.
         if let Some(tx) = unspent_anchoring_transaction {
             let tx_id = tx.id();
 
-            // Check that latest anchoring transaction isn't a transition.
-            if actual_state.is_transition() {
-                let current_script_pubkey = &tx.0.output[0].script_pubkey;
-                let outgoing_script_pubkey = &actual_state.script_pubkey();
-                if current_script_pubkey == outgoing_script_pubkey {
-                    trace!(
-                        "Waiting for the moment when the following configuration \
-                         becomes actual."
-                    );
+            // The output of the sweep transaction cannot be spent, so the following
+            // transactions refer to it instead. These are either the sweep transactions
+            // of the remaining funding outputs, or the anchoring transactions resumed
+            // after the sweep address has been cleared and the wallet has been replenished.
+            if self.sweep_transactions.contains(&tx_id) {
+                if sweep_script.is_some() && unspent_funding_outputs.is_empty() {
+                    trace!("Anchoring funds have been swept, anchoring is stopped.");
                     return None;
-                } else {
-                    trace!(
-                        "Transition from {} to {}.",
-                        actual_state.actual_config().anchoring_address(),
-                        actual_state.output_address(),
-                    );
-                    builder.transit_to(actual_state.script_pubkey());
                 }
-            }
+                builder.recover(tx_id);
+            } else {
+                // Check that latest anchoring transaction isn't a transition. The sweep
+                // transaction spends the funds without waiting for the transition.
+                if actual_state.is_transition() && sweep_script.is_none() {
+                    let current_script_pubkey = &tx.0.output[0].script_pubkey;
+                    let outgoing_script_pubkey =
+                        &self.anchoring_output_script(&tx, &actual_state.redeem_script());
+                    if current_script_pubkey == outgoing_script_pubkey {
+                        trace!(
+                            "Waiting for the moment when the following configuration \
+                             becomes actual."
+                        );
+                        return None;
+                    } else {
+                        trace!(
+                            "Transition from {} to {}.",
+                            actual_state.actual_config().anchoring_address(),
+                            actual_state.output_address(),
+                        );
+                        builder.transit_to(actual_state.script_pubkey());
+                    }
+                }
 
-            // TODO Re-implement recovery business logic [ECR-3581]
-            if let Err(e) = builder.prev_tx(tx) {
-                if unspent_funding_transaction.is_none() {
-                    return Some(Err(e));
+                // Let the proposal pay for the latest anchoring transaction if it is stuck.
+                if let Some((parent_vsize, parent_fee)) = self.unconfirmed_parent(&tx) {
+                    builder.accelerate(parent_vsize, parent_fee);
+                }
+
+                if let Some(payload) = self.contract_payloads.get(&tx_id) {
+                    builder.prev_contract_payload(&payload);
+                }
+                // TODO Re-implement recovery business logic [ECR-3581]
+                if let Err(e) = builder.prev_tx(tx) {
+                    if unspent_funding_outputs.is_empty() {
+                        return Some(Err(e));
+                    }
+                    error!("Anchoring is broken: '{}'. Will try to recover", e);
+                    builder.recover(tx_id);
                 }
-                error!("Anchoring is broken: '{}'. Will try to recover", e);
-                builder.recover(tx_id);
             }
         }
 
-        if let Some(tx) = unspent_funding_transaction {
-            if let Err(e) = builder.additional_funds(tx) {
+        for (out, tx) in unspent_funding_outputs {
+            if let Err(e) = builder.additional_funding_output(tx, out) {
                 return Some(Err(e));
             }
         }
 
         // Add corresponding payload.
-        let latest_anchored_height = self.latest_anchored_height();
-        let anchoring_height = actual_state.following_anchoring_height(latest_anchored_height);
+        let anchoring_height = self.proposed_anchoring_height(&core_schema, actual_state);
         let anchoring_block_hash = core_schema.block_hash_by_height(anchoring_height)?;
+        let blocks_start = blocks_interval_start(
+            self.committed_blocks_height(self.transactions_chain.len()),
+            anchoring_height,
+        );
+        let blocks_root = self.anchored_blocks_root(&core_schema, blocks_start, anchoring_height);
 
         builder.payload(anchoring_height, anchoring_block_hash);
+        builder.blocks_root(blocks_root);
+        // Commit the actual configuration if it has been changed since the previous anchoring.
+        let config_hash = config.object_hash();
+        if self.anchored_config_hash() != Some(config_hash) {
+            builder.config_hash(config_hash);
+        }
         builder.fee(config.transaction_fee);
 
         // Create anchoring proposal.
-        Some(builder.create())
+        Some(builder.create_with_payload())
+    }
+
+    /// Returns the height of the block which is anchored by the proposal of the next
+    /// anchoring transaction for the given anchoring state.
+    fn proposed_anchoring_height(
+        &self,
+        core_schema: &CoreSchema<impl Access>,
+        actual_state: &BtcAnchoringState,
+    ) -> Height {
+        let config = actual_state.actual_config();
+        let latest_anchored_height = self.latest_anchored_height();
+        let anchoring_height = actual_state.following_anchoring_height(latest_anchored_height);
+        // Skip the anchoring heights missed while the anchoring has been stalled. The blocks
+        // at these heights are still committed by the Merkle root of the anchored blocks.
+        if config.catch_up_policy == CatchUpPolicy::LatestHeight
+            && actual_state.is_regular()
+            && latest_anchored_height.is_some()
+        {
+            let latest_anchoring_height = config.previous_anchoring_height(core_schema.height());
+            cmp::max(anchoring_height, latest_anchoring_height)
+        } else {
+            anchoring_height
+        }
+    }
+
+    /// Returns the Merkle root of the hashes of the blocks in the given range inclusive.
+    /// The root is computed from scratch unless it has been already computed for
+    /// the proposal of the next anchoring transaction.
+    fn anchored_blocks_root(
+        &self,
+        core_schema: &CoreSchema<impl Access>,
+        from: Height,
+        to: Height,
+    ) -> Hash {
+        match self.proposal_blocks_root.get() {
+            Some(cached) if cached.from == from.0 && cached.to == to.0 => cached.root,
+            _ => blocks_root(core_schema, from, to),
+        }
+    }
+
+    /// Returns the proposal of the transaction which replaces the latest anchoring
+    /// transaction according to the given fee bump request.
+    pub(crate) fn replacement_proposal(
+        &self,
+        config: &Config,
+        fee_bump: &BumpFee,
+    ) -> Result<(Transaction, Vec<Transaction>, Payload), BuilderError> {
+        let replaced_tx = self
+            .transactions_chain
+            .last()
+            .ok_or(BuilderError::UnsuitableReplacedTx)?;
+        debug_assert_eq!(replaced_tx.id(), fee_bump.txid);
+        let payload = self
+            .anchoring_payload(&replaced_tx)
+            .ok_or(BuilderError::UnsuitableReplacedTx)?;
+        let inputs = self
+            .latest_transaction_inputs()
+            .ok_or(BuilderError::UnsuitableReplacedTx)?;
+
+        let mut builder = BtcAnchoringTransactionBuilder::new(&config.redeem_script());
+        // The first input may spend the output committed in the pay-to-contract mode.
+        if let Some(prev_payload) = inputs
+            .first()
+            .and_then(|input| self.contract_payloads.get(&input.id()))
+        {
+            builder.prev_contract_payload(&prev_payload);
+        }
+        builder.replace(&replaced_tx, payload, inputs)?;
+        builder.fee(fee_bump.fee);
+        builder.create_with_payload()
     }
 
     /// Returns the proposal of the next anchoring transaction for the actual anchoring state.
@@ -174,49 +532,161 @@ impl<T: Access> Schema<T> {
         self.proposed_anchoring_transaction(core_schema, &actual_state)
     }
 
+    /// Returns the proposal of the next anchoring transaction for the actual anchoring state
+    /// along with the payload committed by the proposal.
+    pub fn actual_anchoring_proposal(
+        &self,
+        core_schema: CoreSchema<impl Access>,
+    ) -> Option<Result<(Transaction, Vec<Transaction>, Payload), BuilderError>> {
+        let actual_state = self.actual_state();
+        self.anchoring_proposal(core_schema, &actual_state)
+    }
+
+    /// Returns the height of the first block committed by the Merkle root of the anchored
+    /// blocks in the payload of the anchoring transaction with the given index.
+    pub fn anchored_blocks_start(&self, tx_index: u64) -> Option<Height> {
+        let anchored_height = self
+            .transactions_chain
+            .get(tx_index)
+            .and_then(|tx| self.anchoring_payload(&tx))?
+            .block_height;
+        Some(blocks_interval_start(
+            self.committed_blocks_height(tx_index),
+            anchored_height,
+        ))
+    }
+
+    /// Returns the height of the latest block committed by the anchoring transactions
+    /// preceding the one with the given index. The blocks anchored by the transaction
+    /// which commits to the configuration hash are committed by the following one.
+    fn committed_blocks_height(&self, tx_index: u64) -> Option<Height> {
+        (0..tx_index).rev().find_map(|index| {
+            let tx = self.transactions_chain.get(index)?;
+            let payload = self.anchoring_payload(&tx)?;
+            if payload.defers_blocks_root() {
+                None
+            } else {
+                Some(payload.block_height)
+            }
+        })
+    }
+
     /// Returns the height of the latest anchored block.
     pub fn latest_anchored_height(&self) -> Option<Height> {
         let tx = self.transactions_chain.last()?;
         Some(
-            tx.anchoring_metadata()
+            self.anchoring_payload(&tx)
                 .expect(
                     "Expected payload in the anchoring transaction. \
                      If this error occurs, inform the service authors about it.",
                 )
-                .1
                 .block_height,
         )
     }
 }
 
+/// Computes the Merkle root of the hashes of the committed blocks in the given range inclusive.
+fn blocks_root(core_schema: &CoreSchema<impl Access>, from: Height, to: Height) -> Hash {
+    let block_hashes = core_schema
+        .block_hashes_by_height()
+        .iter_from(from.0)
+        .take((to.0 - from.0 + 1) as usize)
+        .collect::<Vec<_>>();
+    HashTag::hash_list(&block_hashes)
+}
+
+/// Returns the height of the first block following the latest anchored one. If the same
+/// height is anchored once again, then the only anchored block is the block itself.
+/// The interval never exceeds `MAX_ANCHORED_BLOCKS` blocks.
+fn blocks_interval_start(
+    latest_anchored_height: Option<Height>,
+    anchoring_height: Height,
+) -> Height {
+    let start = match latest_anchored_height {
+        Some(height) if height < anchoring_height => height.next(),
+        Some(_) => anchoring_height,
+        None => Height::zero(),
+    };
+    let min_start = Height((anchoring_height.0 + 1).saturating_sub(MAX_ANCHORED_BLOCKS));
+    cmp::max(start, min_start)
+}
+
 impl<T> Schema<T>
 where
     T: Access,
     T::Base: RawAccessMut,
 {
-    /// Adds a finalized transaction to the tail of the anchoring transactions.
-    pub(crate) fn push_anchoring_transaction(&mut self, tx: Transaction) {
-        // An unspent funding transaction is always unconditionally added to the anchoring
-        // transaction proposal, so we can simply move it to the list of spent.
-        if let Some(funding_transaction) = self.unspent_funding_transaction.take() {
-            self.spent_funding_transactions
-                .put(&funding_transaction.id(), funding_transaction);
-        }
-        // Special case if we have an active following configuration.
-        if let Some(config) = self.following_config() {
+    /// Adds a finalized transaction with the given payload to the tail of the anchoring
+    /// transactions.
+    pub(crate) fn push_anchoring_transaction(&mut self, tx: Transaction, payload: Payload) {
+        let anchored_height = payload.block_height;
+        self.take_legacy_funding_transaction();
+        // The finalized transaction either spends or replaces the unconfirmed one.
+        self.unconfirmed_transaction.remove();
+        if let Some(config_hash) = payload.config_hash {
+            self.anchored_config_hash.set(config_hash);
+        }
+        // Keep the payload which is not contained in the transaction itself.
+        if tx.anchoring_payload().is_none() {
+            self.contract_payloads.put(&tx.id(), payload);
+        }
+        // If there is an accepted fee bump request, then the finalized transaction
+        // is the replacement of the latest anchoring transaction.
+        if self.fee_bump.exists() {
+            self.replace_latest_anchoring_transaction(tx);
+            return;
+        }
+        // Record the anchoring heights skipped by the transaction in the catch-up mode.
+        if let Some(latest_anchored_height) = self.latest_anchored_height() {
+            let expected_height = self
+                .actual_state()
+                .following_anchoring_height(Some(latest_anchored_height));
+            if anchored_height > expected_height {
+                let last_skipped_height = self
+                    .actual_config()
+                    .previous_anchoring_height(anchored_height.previous());
+                self.skipped_heights.push(SkippedHeights {
+                    from: expected_height.0,
+                    to: last_skipped_height.0,
+                });
+            }
+        }
+        // Mark the funding outputs spent by the anchoring transaction as spent. The rest
+        // of them will be spent by the following anchoring transactions.
+        for txin in &tx.0.input {
+            let txid = Sha256d::from(txin.previous_output.txid);
+            if let Some(funding_transaction) = self.unspent_funding_transactions.get(&txid) {
+                let output = TxOutputId::new(txid, txin.previous_output.vout);
+                self.spent_funding_outputs
+                    .put(&output, funding_transaction.clone());
+                // Funding transaction is spent if there are no more unspent outputs
+                // locked by the same script.
+                let script_pubkey =
+                    &funding_transaction.0.output[output.output as usize].script_pubkey;
+                let is_spent = funding_transaction
+                    .find_outs(script_pubkey)
+                    .all(|(out, _)| {
+                        self.spent_funding_outputs
+                            .contains(&TxOutputId::new(txid, out as u32))
+                    });
+                if is_spent {
+                    self.unspent_funding_transactions.remove(&txid);
+                }
+            }
+        }
+        // Special case if we have an active following configuration. The sweep transaction
+        // does not pay to the following address, so the transition is abandoned.
+        let is_sweep = self.actual_config().sweep_address.map_or(false, |address| {
+            address.0.script_pubkey() == tx.0.output[0].script_pubkey
+        });
+        if let Some(config) = self.following_config().filter(|_| !is_sweep) {
             // Check that the anchoring transaction is correct.
-            let tx_out_script = tx
-                .anchoring_metadata()
-                .expect(
-                    "Unable to find metadata in the anchoring transaction. \
-                     If this error occurs, inform the service authors about it.",
-                )
-                .0;
+            let tx_out_script = &tx.0.output[0].script_pubkey;
             // If there is a following config, then the anchoring transaction's output should have
             // same script as in the following config.
             // Otherwise, this is a critical error in the logic of the anchoring.
             assert_eq!(
-                config.anchoring_out_script(),
+                self.anchoring_output_script(&tx, &config.redeem_script()),
                 *tx_out_script,
                 "Malformed output address in the anchoring transaction. \
                  If this error occurs, inform the service authors about it."
@@ -225,19 +695,203 @@ where
             self.following_config.remove();
             self.actual_config.set(config);
         }
+        // Record the sweep, so that it does not depend on the following changes
+        // of the sweep address.
+        if is_sweep {
+            self.sweep_transactions
+                .put(&tx.id(), self.transactions_chain.len());
+        }
         self.transactions_chain.push(tx);
     }
 
-    /// Sets the given transaction as the current unspent funding transaction.
-    pub(crate) fn set_funding_transaction(&mut self, transaction: btc::Transaction) {
+    /// Computes the Merkle root of the anchored blocks committed by the proposal of the next
+    /// anchoring transaction once the anchored block is committed, so the proposal does
+    /// not compute it each time it is requested.
+    pub(crate) fn update_proposal_blocks_root(&mut self, core_schema: CoreSchema<impl Access>) {
+        let actual_state = self.actual_state();
+        if actual_state.actual_config().paused {
+            return;
+        }
+
+        let to = self.proposed_anchoring_height(&core_schema, &actual_state);
+        // The anchored block has not been committed yet.
+        if core_schema.block_hashes_by_height().len() <= to.0 {
+            return;
+        }
+        let from = blocks_interval_start(
+            self.committed_blocks_height(self.transactions_chain.len()),
+            to,
+        );
+        let is_actual = self
+            .proposal_blocks_root
+            .get()
+            .map_or(false, |cached| cached.from == from.0 && cached.to == to.0);
+        if !is_actual {
+            self.proposal_blocks_root.set(AnchoredBlocksRoot {
+                from: from.0,
+                to: to.0,
+                root: blocks_root(&core_schema, from, to),
+            });
+        }
+    }
+
+    /// Replaces the latest anchoring transaction by the given one with a higher fee.
+    fn replace_latest_anchoring_transaction(&mut self, tx: Transaction) {
+        let index = self.transactions_chain.len() - 1;
+        let replaced_tx = self.transactions_chain.get(index).unwrap();
+        // The replacement of the sweep transaction is the sweep transaction as well.
+        if self.sweep_transactions.contains(&replaced_tx.id()) {
+            self.sweep_transactions.remove(&replaced_tx.id());
+            self.sweep_transactions.put(&tx.id(), index);
+        }
+        self.replaced_transactions
+            .put(&replaced_tx.id(), replaced_tx);
+        self.transactions_chain.set(index, tx);
+        self.fee_bump.remove();
+    }
+
+    /// Sets the given request to replace the latest anchoring transaction as accepted.
+    pub(crate) fn set_fee_bump(&mut self, fee_bump: BumpFee) {
+        // Remove confirmations for this request to avoid attack of re-setting it.
+        self.unconfirmed_fee_bumps
+            .put(&fee_bump.object_hash(), TransactionConfirmations::default());
+        self.fee_bump.set(fee_bump);
+    }
+
+    /// Marks the latest anchoring transaction as unconfirmed according to the given report.
+    pub(crate) fn set_unconfirmed_transaction(&mut self, report: ReportUnconfirmed) {
+        // Remove confirmations for this report to avoid attack of re-setting it.
+        self.unconfirmed_reports
+            .put(&report.object_hash(), TransactionConfirmations::default());
+        self.unconfirmed_transaction.set(report.txid);
+    }
+
+    /// Records the Bitcoin confirmation status of the anchoring transaction according
+    /// to the given report and the number of confirmations accepted by the quorum.
+    pub(crate) fn set_bitcoin_confirmation(
+        &mut self,
+        report: ReportConfirmation,
+        confirmations: u32,
+    ) {
+        // The confirmed transaction can no longer be replaced.
+        if self
+            .fee_bump
+            .get()
+            .map_or(false, |fee_bump| fee_bump.txid == report.txid)
+        {
+            self.fee_bump.remove();
+        }
+        // The confirmed transaction no longer needs to be accelerated by its child.
+        if self.unconfirmed_transaction.get() == Some(report.txid) {
+            self.unconfirmed_transaction.remove();
+        }
+        self.bitcoin_confirmations.put(
+            &report.txid,
+            BitcoinConfirmation {
+                block_hash: report.block_hash,
+                block_height: report.block_height,
+                confirmations,
+            },
+        );
+    }
+
+    /// Adds the given transaction to the list of unspent funding transactions.
+    pub(crate) fn add_funding_transaction(&mut self, transaction: btc::Transaction) {
         debug_assert!(
-            !self.spent_funding_transactions.contains(&transaction.id()),
+            !self.is_spent_funding_transaction(&transaction),
             "Funding transaction must be unspent."
         );
+        self.take_legacy_funding_transaction();
         // Remove confirmations for this transaction to avoid attack of re-setting
         // this transaction as funding.
         self.unconfirmed_funding_transactions
             .put(&transaction.id(), TransactionConfirmations::default());
-        self.unspent_funding_transaction.set(transaction);
+        self.unspent_funding_transactions
+            .put(&transaction.id(), transaction);
+    }
+
+    /// Moves the unspent funding transaction recorded by the previous versions of the service
+    /// to the list of unspent funding transactions.
+    fn take_legacy_funding_transaction(&mut self) {
+        if let Some(transaction) = self.unspent_funding_transaction.take() {
+            self.unspent_funding_transactions
+                .put(&transaction.id(), transaction);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::network::constants::Network;
+    use btc_transaction_utils::test_data::secp_gen_keypair;
+    use exonum::crypto;
+    use exonum_merkledb::{Database, TemporaryDB};
+
+    use crate::{
+        config::{AnchoringKeys, Config},
+        test_helpers::create_fake_funding_transaction,
+    };
+
+    use exonum::helpers::Height;
+
+    use super::{blocks_interval_start, Schema, MAX_ANCHORED_BLOCKS};
+
+    #[test]
+    fn blocks_interval_start_is_capped() {
+        assert_eq!(blocks_interval_start(None, Height(100)), Height(0));
+        assert_eq!(
+            blocks_interval_start(Some(Height(1_000)), Height(2_000)),
+            Height(1_001)
+        );
+        assert_eq!(
+            blocks_interval_start(Some(Height(2_000)), Height(2_000)),
+            Height(2_000)
+        );
+
+        let anchoring_height = Height(MAX_ANCHORED_BLOCKS * 3);
+        let capped_start = Height(anchoring_height.0 + 1 - MAX_ANCHORED_BLOCKS);
+        assert_eq!(blocks_interval_start(None, anchoring_height), capped_start);
+        assert_eq!(
+            blocks_interval_start(Some(Height(1_000)), anchoring_height),
+            capped_start
+        );
+    }
+
+    #[test]
+    fn legacy_funding_transactions() {
+        let anchoring_keys = (0..4).map(|_| AnchoringKeys {
+            bitcoin_key: secp_gen_keypair(Network::Testnet).0.into(),
+            service_key: crypto::gen_keypair().0,
+        });
+        let config = Config::with_public_keys(Network::Testnet, anchoring_keys).unwrap();
+        let address = config.anchoring_address();
+        let spent_tx = create_fake_funding_transaction(&address, 10_000);
+        let unspent_tx = create_fake_funding_transaction(&address, 20_000);
+        let added_tx = create_fake_funding_transaction(&address, 30_000);
+
+        let db = TemporaryDB::new();
+        let fork = db.fork();
+        let mut schema = Schema::new(&fork);
+        schema.actual_config.set(config);
+        // Fill the indexes used by the previous versions of the service.
+        schema
+            .spent_funding_transactions
+            .put(&spent_tx.id(), spent_tx.clone());
+        schema.unspent_funding_transaction.set(unspent_tx.clone());
+
+        assert!(schema.is_spent_funding_transaction(&spent_tx));
+        assert!(!schema.is_spent_funding_transaction(&unspent_tx));
+        assert_eq!(
+            schema.unspent_funding_transactions(),
+            vec![unspent_tx.clone()]
+        );
+
+        // The legacy unspent funding transaction is kept along with the added one.
+        schema.add_funding_transaction(added_tx.clone());
+        assert!(!schema.unspent_funding_transaction.exists());
+        let unspent_txs = schema.unspent_funding_transactions();
+        assert_eq!(unspent_txs.len(), 2);
+        assert!(unspent_txs.contains(&unspent_tx));
+        assert!(unspent_txs.contains(&added_tx));
     }
 }
@@ -14,10 +14,20 @@
 
 //! BTC anchoring transactions.
 
-pub use crate::proto::{AddFunds, SignInput};
+pub use crate::proto::{
+    AddFunds, BumpFee, ReportConfirmation, ReportUnconfirmed, SignInput, SignInputs,
+};
 
 use btc_transaction_utils::{p2wsh::InputSigner, TxInRef};
-use exonum::runtime::{CommonError, ExecutionError, ExecutionFail};
+use exonum::{
+    blockchain::Schema as CoreSchema,
+    crypto::Hash,
+    merkledb::{
+        access::{Access, RawAccessMut},
+        ObjectHash,
+    },
+    runtime::{CommonError, ExecutionError, ExecutionFail},
+};
 use exonum_derive::{exonum_interface, interface_method};
 use exonum_rust_runtime::ExecutionContext;
 use log::{info, trace};
@@ -27,9 +37,13 @@ use crate::{btc, config::Config, BtcAnchoringService};
 use super::{
     data_layout::TxInputId,
     errors::Error,
-    schema::{InputSignatures, Schema, TransactionConfirmations},
+    schema::{ConfirmationReports, InputSignatures, Schema, TransactionConfirmations},
 };
 
+/// Minimal fee rate in satoshis per virtual byte, which the replacement transaction should
+/// pay for its own relay in addition to the fee of the replaced one according to BIP-125.
+const MIN_RELAY_FEE: u64 = 1;
+
 impl SignInput {
     // Check that input signature is correct.
     fn verify_signature(
@@ -84,6 +98,143 @@ impl TransactionConfirmations {
     }
 }
 
+impl ConfirmationReports {
+    /// Replaces the previous report of the specified anchoring node by the given one.
+    fn report_by_node(&mut self, public_key: btc::PublicKey, report: ReportConfirmation) {
+        self.0.insert(public_key, report);
+    }
+
+    /// Returns the greatest number of confirmations of the Bitcoin block with the given key
+    /// reported by the quorum of the anchoring nodes, if there are enough reports.
+    fn quorum_confirmations(&self, config: &Config, block_key: &Hash) -> Option<u32> {
+        let mut confirmations = self
+            .0
+            .values()
+            .filter(|report| report.block_key() == *block_key)
+            .map(|report| report.confirmations)
+            .collect::<Vec<_>>();
+        confirmations.sort_unstable_by(|a, b| b.cmp(a));
+        confirmations
+            .get(config.byzantine_quorum().checked_sub(1)?)
+            .copied()
+    }
+}
+
+impl ReportConfirmation {
+    /// Returns the key of the reports for the same Bitcoin block, which do not depend
+    /// on the reported number of confirmations.
+    pub(crate) fn block_key(&self) -> Hash {
+        Self {
+            confirmations: 0,
+            ..self.clone()
+        }
+        .object_hash()
+    }
+}
+
+/// Returns the actual anchoring proposal if it has the given identifier. Returns `None`
+/// if there is no proposal and the given identifier belongs to the latest anchoring
+/// transaction, i.e. the proposal has been already finalized.
+fn signed_proposal<T: Access>(
+    schema: &Schema<T>,
+    core_schema: CoreSchema<impl Access>,
+    txid: btc::Sha256d,
+) -> Result<Option<(btc::Transaction, Vec<btc::Transaction>, btc::Payload)>, ExecutionError> {
+    let proposal = if let Some(proposal) = schema
+        .actual_anchoring_proposal(core_schema)
+        .transpose()
+        .map_err(Error::anchoring_builder_error)?
+    {
+        proposal
+    } else {
+        // There is no anchoring request at the current blockchain state.
+        // Make sure txid is equal to the identifier of the last anchoring transaction.
+        let latest_anchoring_txid = schema
+            .transactions_chain
+            .last()
+            // If the anchoring chain is not established, then the proposal must exist.
+            .unwrap()
+            .id();
+        if latest_anchoring_txid == txid {
+            return Ok(None);
+        } else {
+            return Err(Error::UnexpectedProposalTxId.into());
+        }
+    };
+
+    // Make sure txid is equal to the identifier of the anchoring transaction proposal.
+    if proposal.0.id() != txid {
+        return Err(Error::UnexpectedProposalTxId.into());
+    }
+    Ok(Some(proposal))
+}
+
+/// Checks that the input signature made by the given anchoring node is correct. If the input
+/// spends the output committed in the pay-to-contract mode, then it is signed by the tweaked key.
+fn verify_input_signature<T: Access>(
+    schema: &Schema<T>,
+    actual_config: &Config,
+    public_key: &btc::PublicKey,
+    proposal: &btc::Transaction,
+    inputs: &[btc::Transaction],
+    sign_input: &SignInput,
+) -> Result<(), ExecutionError> {
+    let input_commitment = inputs
+        .get(sign_input.input as usize)
+        .and_then(|input| schema.contract_commitment(input));
+    let input_signer =
+        InputSigner::new(actual_config.committed_redeem_script(input_commitment.as_ref()));
+    let public_key = match input_commitment {
+        Some(commitment) => btc::contract_public_key(public_key, &commitment),
+        None => *public_key,
+    };
+    sign_input.verify_signature(&input_signer, &public_key, proposal, inputs)
+}
+
+/// Finalizes the anchoring proposal and adds it to the tail of the anchoring transactions
+/// if each of its inputs has enough signatures.
+fn finalize_proposal<T>(
+    schema: &mut Schema<T>,
+    actual_config: &Config,
+    proposal: btc::Transaction,
+    inputs: &[btc::Transaction],
+    payload: btc::Payload,
+) where
+    T: Access,
+    T::Base: RawAccessMut,
+{
+    let quorum = actual_config.redeem_script().content().quorum;
+    let mut finalized_tx = proposal.clone();
+    // Make sure we reach a quorum for each input.
+    for (index, input) in inputs.iter().enumerate() {
+        let input_id = TxInputId::new(proposal.id(), index as u32);
+        let signatures_for_input = schema.input_signatures(&input_id);
+        // We have not enough signatures for this input, so we can not finalize this
+        // proposal at the moment.
+        if signatures_for_input.len() != quorum {
+            return;
+        }
+
+        let input_commitment = schema.contract_commitment(input);
+        let input_signer =
+            InputSigner::new(actual_config.committed_redeem_script(input_commitment.as_ref()));
+        input_signer.spend_input(
+            &mut finalized_tx.0.input[index],
+            signatures_for_input.values(),
+        );
+    }
+
+    info!("====== ANCHORING ======");
+    info!("txid: {}", finalized_tx.id().to_string());
+    info!("height: {}", payload.block_height);
+    info!("hash: {}", payload.block_hash.to_hex());
+    info!("balance: {}", finalized_tx.0.output[0].value);
+    trace!("Anchoring txhex: {}", finalized_tx.to_string());
+
+    // Add finalized transaction to the tail of anchoring transactions.
+    schema.push_anchoring_transaction(finalized_tx, payload);
+}
+
 /// Exonum BTC anchoring transactions.
 #[exonum_interface]
 pub trait BtcAnchoringInterface<Ctx> {
@@ -99,6 +250,29 @@ pub trait BtcAnchoringInterface<Ctx> {
     /// The transaction will be applied if 2/3+1 anchoring nodes sent it.
     #[interface_method(id = 1)]
     fn add_funds(&self, context: Ctx, arg: AddFunds) -> Self::Output;
+    /// Requests to replace the latest anchoring transaction by the one with a higher fee.
+    ///
+    /// The replacement is possible only if the replace-by-fee is enabled in the actual
+    /// configuration. The request will be applied if 2/3+1 anchoring nodes sent it.
+    #[interface_method(id = 2)]
+    fn bump_fee(&self, context: Ctx, arg: BumpFee) -> Self::Output;
+    /// Reports that the latest anchoring transaction is still unconfirmed in the Bitcoin
+    /// network, so the following anchoring transaction should accelerate it.
+    ///
+    /// The report will be applied if 2/3+1 anchoring nodes sent it.
+    #[interface_method(id = 3)]
+    fn report_unconfirmed(&self, context: Ctx, arg: ReportUnconfirmed) -> Self::Output;
+    /// Reports that the anchoring transaction has been committed to the Bitcoin blockchain.
+    ///
+    /// The Bitcoin block and the number of confirmations confirmed by 2/3+1 anchoring
+    /// nodes are recorded as the Bitcoin confirmation status of the transaction.
+    #[interface_method(id = 4)]
+    fn report_confirmation(&self, context: Ctx, arg: ReportConfirmation) -> Self::Output;
+    /// Signs all inputs of the anchoring transaction proposal at once.
+    ///
+    /// The signatures are verified together, so either all of them or none are accepted.
+    #[interface_method(id = 5)]
+    fn sign_inputs(&self, context: Ctx, arg: SignInputs) -> Self::Output;
 }
 
 impl BtcAnchoringInterface<ExecutionContext<'_>> for BtcAnchoringService {
@@ -119,40 +293,26 @@ impl BtcAnchoringInterface<ExecutionContext<'_>> for BtcAnchoringService {
             .ok_or(Error::UnauthorizedAnchoringKey)?;
 
         // Check that there is an anchoring proposal for the actual blockchain state.
-        let (proposal, expected_inputs) = if let Some(proposal) = schema
-            .actual_proposed_anchoring_transaction(context.data().for_core())
-            .transpose()
-            .map_err(Error::anchoring_builder_error)?
+        let (proposal, expected_inputs, payload) = if let Some(proposal) =
+            signed_proposal(&schema, context.data().for_core(), arg.txid)?
         {
             proposal
         } else {
-            // There is no anchoring request at the current blockchain state.
-            // Make sure txid is equal to the identifier of the last anchoring transaction.
-            let latest_anchoring_txid = schema
-                .transactions_chain
-                .last()
-                // If the anchoring chain is not established, then the proposal must exist.
-                .unwrap()
-                .id();
-            if latest_anchoring_txid == arg.txid {
-                return Ok(());
-            } else {
-                return Err(Error::UnexpectedProposalTxId.into());
-            }
+            return Ok(());
         };
 
-        // Make sure txid is equal to the identifier of the anchoring transaction proposal.
-        if proposal.id() != arg.txid {
-            return Err(Error::UnexpectedProposalTxId.into());
-        }
-
         // Check that input signature is correct.
-        let redeem_script = actual_config.redeem_script();
-        let quorum = redeem_script.content().quorum;
-        let input_signer = InputSigner::new(redeem_script);
-        arg.verify_signature(&input_signer, &public_key, &proposal, &expected_inputs)?;
+        verify_input_signature(
+            &schema,
+            &actual_config,
+            &public_key,
+            &proposal,
+            &expected_inputs,
+            &arg,
+        )?;
 
         // All preconditions are correct and we can use this signature.
+        let quorum = actual_config.redeem_script().content().quorum;
         let input_id = TxInputId::new(proposal.id(), arg.input);
         let mut input_signatures = schema.input_signatures(&input_id);
         let mut input_signature_len = input_signatures.len();
@@ -171,35 +331,85 @@ impl BtcAnchoringInterface<ExecutionContext<'_>> for BtcAnchoringService {
         // If we have enough signatures for specific input we have to check that we also have
         // sufficient signatures to finalize proposal transaction.
         if input_signature_len == quorum {
-            let mut finalized_tx: btc::Transaction = proposal.clone();
-            // Make sure we reach a quorum for each input.
-            for index in 0..expected_inputs.len() {
-                let input_id = TxInputId::new(proposal.id(), index as u32);
-                let signatures_for_input = schema.input_signatures(&input_id);
-                // We have not enough signatures for this input, so we can not finalize this
-                // proposal at the moment.
-                if signatures_for_input.len() != quorum {
-                    return Ok(());
-                }
+            finalize_proposal(
+                &mut schema,
+                &actual_config,
+                proposal,
+                &expected_inputs,
+                payload,
+            );
+        }
+        Ok(())
+    }
 
-                input_signer.spend_input(
-                    &mut finalized_tx.0.input[index],
-                    signatures_for_input.values(),
-                );
-            }
+    fn sign_inputs(&self, context: ExecutionContext<'_>, arg: SignInputs) -> Self::Output {
+        let author = context
+            .caller()
+            .author()
+            .ok_or(CommonError::UnauthorizedCaller)?;
+
+        let mut schema = Schema::new(context.service_data());
+
+        // Check that author is authorized to sign inputs of the anchoring proposal.
+        let actual_config = schema.actual_config();
+        let (anchoring_node_id, public_key) = actual_config
+            .find_bitcoin_key(&author)
+            .ok_or(Error::UnauthorizedAnchoringKey)?;
 
-            let payload = finalized_tx.anchoring_metadata().unwrap().1;
+        // Check that there is an anchoring proposal for the actual blockchain state.
+        let (proposal, expected_inputs, payload) = if let Some(proposal) =
+            signed_proposal(&schema, context.data().for_core(), arg.txid)?
+        {
+            proposal
+        } else {
+            return Ok(());
+        };
+        if arg.input_signatures.len() != expected_inputs.len() {
+            return Err(Error::UnexpectedInputSignatures.into());
+        }
 
-            info!("====== ANCHORING ======");
-            info!("txid: {}", finalized_tx.id().to_string());
-            info!("height: {}", payload.block_height);
-            info!("hash: {}", payload.block_hash.to_hex());
-            info!("balance: {}", finalized_tx.0.output[0].value);
-            trace!("Anchoring txhex: {}", finalized_tx.to_string());
+        // Check all the input signatures before using any of them.
+        let sign_inputs = arg
+            .input_signatures
+            .into_iter()
+            .enumerate()
+            .map(|(input, input_signature)| SignInput {
+                txid: arg.txid,
+                input: input as u32,
+                input_signature,
+            })
+            .collect::<Vec<_>>();
+        for sign_input in &sign_inputs {
+            verify_input_signature(
+                &schema,
+                &actual_config,
+                &public_key,
+                &proposal,
+                &expected_inputs,
+                sign_input,
+            )?;
+        }
 
-            // Add finalized transaction to the tail of anchoring transactions.
-            schema.push_anchoring_transaction(finalized_tx);
+        // Add signatures for the inputs which have not reached the quorum yet.
+        let quorum = actual_config.redeem_script().content().quorum;
+        for sign_input in sign_inputs {
+            let input_id = TxInputId::new(proposal.id(), sign_input.input);
+            let mut input_signatures = schema.input_signatures(&input_id);
+            if input_signatures.len() < quorum {
+                input_signatures.insert(anchoring_node_id, sign_input.input_signature);
+                schema
+                    .transaction_signatures
+                    .put(&input_id, input_signatures);
+            }
         }
+
+        finalize_proposal(
+            &mut schema,
+            &actual_config,
+            proposal,
+            &expected_inputs,
+            payload,
+        );
         Ok(())
     }
 
@@ -217,14 +427,20 @@ impl BtcAnchoringInterface<ExecutionContext<'_>> for BtcAnchoringService {
             .ok_or(Error::UnauthorizedAnchoringKey)?;
 
         // Check that the given transaction is suitable.
-        let (_, txout) = arg
+        let anchoring_out_script = actual_config.anchoring_out_script();
+        let funding_values = arg
             .transaction
-            .find_out(&actual_config.anchoring_out_script())
-            .ok_or(Error::UnsuitableFundingTx)?;
+            .find_outs(&anchoring_out_script)
+            .map(|(_, txout)| txout.value)
+            .collect::<Vec<_>>();
+        if funding_values.is_empty() {
+            return Err(Error::UnsuitableFundingTx.into());
+        }
+        let balance = funding_values.iter().sum::<u64>();
 
         // Check that the transaction has not been used before
         let funding_txid = arg.transaction.id();
-        if schema.spent_funding_transactions.contains(&funding_txid) {
+        if schema.is_spent_funding_transaction(&arg.transaction) {
             return Err(Error::AlreadyUsedFundingTx.into());
         }
 
@@ -240,9 +456,9 @@ impl BtcAnchoringInterface<ExecutionContext<'_>> for BtcAnchoringService {
         if confirmations.has_enough_confirmations(&actual_config)? {
             info!("====== ADD_FUNDS ======");
             info!("txid: {}", arg.transaction.id().to_string());
-            info!("balance: {}", txout.value);
+            info!("balance: {}", balance);
 
-            schema.set_funding_transaction(arg.transaction);
+            schema.add_funding_transaction(arg.transaction);
         } else {
             schema
                 .unconfirmed_funding_transactions
@@ -250,4 +466,172 @@ impl BtcAnchoringInterface<ExecutionContext<'_>> for BtcAnchoringService {
         }
         Ok(())
     }
+
+    fn bump_fee(&self, context: ExecutionContext<'_>, arg: BumpFee) -> Self::Output {
+        let author = context
+            .caller()
+            .author()
+            .ok_or(CommonError::UnauthorizedCaller)?;
+        let mut schema = Schema::new(context.service_data());
+
+        // Check that author is authorized to request the fee bump.
+        let actual_config = schema.actual_config();
+        let (_, public_key) = actual_config
+            .find_bitcoin_key(&author)
+            .ok_or(Error::UnauthorizedAnchoringKey)?;
+
+        if !actual_config.replace_by_fee {
+            return Err(Error::ReplaceByFeeDisabled.into());
+        }
+
+        // Check that the replaced transaction is the latest replaceable anchoring transaction,
+        // which has been reported as still unconfirmed. Otherwise it may have been already
+        // mined, and the replacement would never be confirmed.
+        let replaced_tx = schema
+            .transactions_chain
+            .last()
+            .filter(|tx| tx.id() == arg.txid && tx.is_replaceable())
+            .filter(|_| schema.unconfirmed_transaction() == Some(arg.txid))
+            .ok_or(Error::UnexpectedReplacedTx)?;
+        // Check that the new fee exceeds the fee of the replaced transaction at least
+        // by the minimal relay fee of the replacement.
+        let replaced_fee = schema
+            .latest_transaction_inputs()
+            .and_then(|inputs| replaced_tx.fee(&inputs))
+            .ok_or(Error::UnexpectedReplacedTx)?;
+        let vsize = replaced_tx.vsize();
+        let min_fee = replaced_fee.saturating_add(vsize.saturating_mul(MIN_RELAY_FEE));
+        if arg.fee.saturating_mul(vsize) < min_fee {
+            return Err(Error::InsufficientFeeBump.into());
+        }
+
+        // Add confirmation from this node for this request.
+        let request_hash = arg.object_hash();
+        let mut confirmations = schema
+            .unconfirmed_fee_bumps
+            .get(&request_hash)
+            .unwrap_or_default();
+        confirmations.confirm_by_node(public_key);
+
+        // Accept this request if there are enough confirmations, otherwise just write
+        // confirmation to the schema.
+        if confirmations.has_enough_confirmations(&actual_config)? {
+            // Make sure that the replacement can be created.
+            schema
+                .replacement_proposal(&actual_config, &arg)
+                .map_err(Error::anchoring_builder_error)?;
+
+            info!("====== BUMP_FEE ======");
+            info!("txid: {}", arg.txid.to_string());
+            info!("fee: {}", arg.fee);
+
+            schema.set_fee_bump(arg);
+        } else {
+            schema
+                .unconfirmed_fee_bumps
+                .put(&request_hash, confirmations);
+        }
+        Ok(())
+    }
+
+    fn report_unconfirmed(
+        &self,
+        context: ExecutionContext<'_>,
+        arg: ReportUnconfirmed,
+    ) -> Self::Output {
+        let author = context
+            .caller()
+            .author()
+            .ok_or(CommonError::UnauthorizedCaller)?;
+        let mut schema = Schema::new(context.service_data());
+
+        // Check that author is authorized to report unconfirmed transactions.
+        let actual_config = schema.actual_config();
+        let (_, public_key) = actual_config
+            .find_bitcoin_key(&author)
+            .ok_or(Error::UnauthorizedAnchoringKey)?;
+
+        // Check that the reported transaction is the latest anchoring transaction.
+        schema
+            .transactions_chain
+            .last()
+            .filter(|tx| tx.id() == arg.txid)
+            .ok_or(Error::UnexpectedUnconfirmedTx)?;
+
+        // Add confirmation from this node for this report.
+        let report_hash = arg.object_hash();
+        let mut confirmations = schema
+            .unconfirmed_reports
+            .get(&report_hash)
+            .unwrap_or_default();
+        confirmations.confirm_by_node(public_key);
+
+        // Accept this report if there are enough confirmations, otherwise just write
+        // confirmation to the schema.
+        if confirmations.has_enough_confirmations(&actual_config)? {
+            info!("====== UNCONFIRMED_TRANSACTION ======");
+            info!("txid: {}", arg.txid.to_string());
+
+            schema.set_unconfirmed_transaction(arg);
+        } else {
+            schema.unconfirmed_reports.put(&report_hash, confirmations);
+        }
+        Ok(())
+    }
+
+    fn report_confirmation(
+        &self,
+        context: ExecutionContext<'_>,
+        arg: ReportConfirmation,
+    ) -> Self::Output {
+        let author = context
+            .caller()
+            .author()
+            .ok_or(CommonError::UnauthorizedCaller)?;
+        let mut schema = Schema::new(context.service_data());
+
+        // Check that author is authorized to report confirmed transactions.
+        let actual_config = schema.actual_config();
+        let (_, public_key) = actual_config
+            .find_bitcoin_key(&author)
+            .ok_or(Error::UnauthorizedAnchoringKey)?;
+
+        // Check that the reported transaction is in the anchoring chain.
+        schema
+            .transactions_chain
+            .get(arg.index)
+            .filter(|tx| tx.id() == arg.txid && arg.confirmations > 0)
+            .ok_or(Error::UnexpectedConfirmedTx)?;
+
+        // Replace the previous report of this node for this transaction, so the outdated
+        // reports for another Bitcoin block are not taken into account.
+        let txid = arg.txid;
+        let mut reports = schema.confirmation_reports.get(&txid).unwrap_or_default();
+        reports.report_by_node(public_key, arg.clone());
+
+        let quorum_confirmations =
+            match reports.quorum_confirmations(&actual_config, &arg.block_key()) {
+                Some(confirmations) => confirmations,
+                None => {
+                    schema.confirmation_reports.put(&txid, reports);
+                    return Ok(());
+                }
+            };
+
+        // Record the confirmation status if it has been changed by the quorum of the anchoring
+        // nodes. The following changes are collected by the new reports.
+        schema.confirmation_reports.remove(&txid);
+        let known = schema.bitcoin_confirmation(&txid);
+        if known.map_or(true, |known| {
+            known.block_hash != arg.block_hash || known.confirmations < quorum_confirmations
+        }) {
+            info!("====== BITCOIN_CONFIRMATION ======");
+            info!("txid: {}", txid.to_string());
+            info!("block: {}", arg.block_hash.to_string());
+            info!("confirmations: {}", quorum_confirmations);
+
+            schema.set_bitcoin_confirmation(arg, quorum_confirmations);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,129 @@
+// Copyright 2019 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pay-to-contract commitments to the anchoring payload.
+//!
+//! In the pay-to-contract mode each public key `P` of the anchoring redeem script is
+//! tweaked as `P + H(P || c)·G`, where `c` is the commitment to the anchoring payload.
+//! The corresponding private keys are tweaked in the same way, thus the anchoring output
+//! can be spent only by the anchoring nodes which know the payload.
+
+use btc_transaction_utils::multisig::{RedeemScript, RedeemScriptBuilder};
+use exonum::crypto::{Hash, HashStream};
+use secp256k1::Secp256k1;
+
+use super::{PrivateKey, PublicKey};
+
+/// Computes the tweak of the given public key for the given commitment.
+fn contract_tweak(public_key: &bitcoin::PublicKey, commitment: &Hash) -> Hash {
+    HashStream::new()
+        .update(&public_key.key.serialize())
+        .update(commitment.as_ref())
+        .hash()
+}
+
+/// Returns the public key tweaked by the given pay-to-contract commitment.
+pub fn contract_public_key(public_key: &PublicKey, commitment: &Hash) -> PublicKey {
+    let tweak = contract_tweak(&public_key.0, commitment);
+    let mut tweaked_key = public_key.0;
+    tweaked_key
+        .key
+        .add_exp_assign(&Secp256k1::verification_only(), tweak.as_ref())
+        .expect("The probability of an invalid tweak is negligible.");
+    PublicKey(tweaked_key)
+}
+
+/// Returns the private key tweaked by the given pay-to-contract commitment. The result
+/// corresponds to the public key returned by the [`contract_public_key`] function.
+///
+/// [`contract_public_key`]: fn.contract_public_key.html
+pub fn contract_private_key(private_key: &PrivateKey, commitment: &Hash) -> PrivateKey {
+    let public_key = private_key.0.public_key(&Secp256k1::signing_only());
+    let tweak = contract_tweak(&public_key, commitment);
+    let mut tweaked_key = private_key.0.clone();
+    tweaked_key
+        .key
+        .add_assign(tweak.as_ref())
+        .expect("The probability of an invalid tweak is negligible.");
+    PrivateKey(tweaked_key)
+}
+
+/// Returns the redeem script with the same quorum, whose public keys are tweaked by
+/// the given pay-to-contract commitment.
+pub fn contract_redeem_script(redeem_script: &RedeemScript, commitment: &Hash) -> RedeemScript {
+    let content = redeem_script.content();
+    let public_keys = content
+        .public_keys
+        .iter()
+        .map(|key| contract_public_key(&PublicKey(*key), commitment).0);
+    RedeemScriptBuilder::with_public_keys(public_keys)
+        .quorum(content.quorum)
+        .to_script()
+        .expect("Tweaked keys should form a correct redeem script.")
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::network::constants::Network;
+    use btc_transaction_utils::multisig::RedeemScriptBuilder;
+    use exonum::crypto::hash;
+    use secp256k1::Secp256k1;
+
+    use crate::btc::{gen_keypair, PublicKey};
+
+    use super::{contract_private_key, contract_public_key, contract_redeem_script};
+
+    #[test]
+    fn test_contract_keys_correspond() {
+        let (public_key, private_key) = gen_keypair(Network::Testnet);
+        let commitment = hash(&[1, 2, 3]);
+
+        let tweaked_public_key = contract_public_key(&public_key, &commitment);
+        let tweaked_private_key = contract_private_key(&private_key, &commitment);
+        assert_ne!(tweaked_public_key, public_key);
+        assert_eq!(
+            PublicKey(tweaked_private_key.0.public_key(&Secp256k1::signing_only())),
+            tweaked_public_key
+        );
+        // Another commitment gives another key.
+        assert_ne!(
+            contract_public_key(&public_key, &hash(&[4, 5, 6])),
+            tweaked_public_key
+        );
+    }
+
+    #[test]
+    fn test_contract_redeem_script() {
+        let keys = (0..4)
+            .map(|_| gen_keypair(Network::Testnet).0)
+            .collect::<Vec<_>>();
+        let redeem_script = RedeemScriptBuilder::with_public_keys(keys.iter().map(|key| key.0))
+            .quorum(3)
+            .to_script()
+            .unwrap();
+        let commitment = hash(&[1, 2, 3]);
+
+        let tweaked_script = contract_redeem_script(&redeem_script, &commitment);
+        let content = tweaked_script.content();
+        assert_eq!(content.quorum, 3);
+        assert_eq!(
+            content.public_keys,
+            keys.iter()
+                .map(|key| contract_public_key(key, &commitment).0)
+                .collect::<Vec<_>>()
+        );
+        // The tweaked redeem script has the same length, so the fee estimation is unaffected.
+        assert_eq!(tweaked_script.as_ref().len(), redeem_script.as_ref().len());
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! BIP-32 hierarchical derivation of the anchoring keys.
+//!
+//! The anchoring key with the rotation index `i` is the hardened child `i` of
+//! the extended private key. Unlike the non-hardened derivation, the anchoring private key
+//! along with the extended public key does not reveal the extended private key and
+//! the other anchoring keys. As a consequence, the anchoring public keys cannot be derived
+//! from the extended public key, so the extended private key is required to derive them.
+
+use bitcoin::util::bip32::{ChildNumber, Error, ExtendedPrivKey};
+use secp256k1::Secp256k1;
+
+use super::{PrivateKey, PublicKey};
+
+/// Derives the anchoring private key with the given rotation index from the extended
+/// private key.
+pub fn derive_private_key(xprv: &ExtendedPrivKey, index: u32) -> Result<PrivateKey, Error> {
+    let child = ChildNumber::from_hardened_idx(index)?;
+    xprv.ckd_priv(&Secp256k1::new(), child)
+        .map(|xprv| PrivateKey(xprv.private_key))
+}
+
+/// Derives the anchoring public key with the given rotation index from the extended
+/// private key.
+pub fn derive_public_key(xprv: &ExtendedPrivKey, index: u32) -> Result<PublicKey, Error> {
+    derive_private_key(xprv, index)
+        .map(|private_key| PublicKey(private_key.0.public_key(&Secp256k1::signing_only())))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        network::constants::Network,
+        util::bip32::{ChildNumber, ExtendedPrivKey},
+    };
+    use secp256k1::Secp256k1;
+
+    use crate::btc::PublicKey;
+
+    use super::{derive_private_key, derive_public_key};
+
+    #[test]
+    fn test_derived_keys_correspond() {
+        let xprv = ExtendedPrivKey::new_master(Network::Testnet, &[1; 32]).unwrap();
+
+        for index in 0..4 {
+            let private_key = derive_private_key(&xprv, index).unwrap();
+            let public_key = derive_public_key(&xprv, index).unwrap();
+            assert_eq!(private_key.0.network, Network::Testnet);
+            assert_eq!(
+                PublicKey(private_key.0.public_key(&Secp256k1::signing_only())),
+                public_key
+            );
+        }
+        // Each rotation index gives another key.
+        assert_ne!(
+            derive_public_key(&xprv, 0).unwrap(),
+            derive_public_key(&xprv, 1).unwrap()
+        );
+        // The keys are derived by the hardened derivation.
+        let normal_child = xprv
+            .ckd_priv(&Secp256k1::new(), ChildNumber::from_normal_idx(0).unwrap())
+            .unwrap();
+        assert_ne!(
+            derive_private_key(&xprv, 0).unwrap().0.key,
+            normal_child.private_key.key
+        );
+        // Rotation index should fit into the hardened indexes range.
+        derive_public_key(&xprv, 1 << 31).unwrap_err();
+        derive_private_key(&xprv, 1 << 31).unwrap_err();
+    }
+}
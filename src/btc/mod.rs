@@ -17,7 +17,10 @@
 pub use btc_transaction_utils::test_data::{secp_gen_keypair, secp_gen_keypair_with_rng};
 
 pub use self::{
+    contract::{contract_private_key, contract_public_key, contract_redeem_script},
+    hd::{derive_private_key, derive_public_key},
     payload::Payload,
+    psbt::Psbt,
     transaction::{BtcAnchoringTransactionBuilder, BuilderError, Transaction},
 };
 
@@ -33,7 +36,10 @@ use serde_derive::{Deserialize, Serialize};
 #[macro_use]
 mod macros;
 
+pub(crate) mod contract;
+pub(crate) mod hd;
 pub(crate) mod payload;
+pub(crate) mod psbt;
 pub(crate) mod transaction;
 
 /// Bitcoin ECDSA private key wrapper.
@@ -12,8 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use exonum::{crypto::Hash, helpers::Height};
+use exonum::{
+    crypto::{self, Hash},
+    helpers::Height,
+};
+use exonum_merkledb::{BinaryValue, ObjectHash};
 
+use anyhow::anyhow;
 use bitcoin::blockdata::{
     opcodes::all::OP_RETURN,
     script::{Builder, Instruction, Script},
@@ -21,6 +26,8 @@ use bitcoin::blockdata::{
 use byteorder::{ByteOrder, LittleEndian};
 use serde_derive::{Deserialize, Serialize};
 
+use std::borrow::Cow;
+
 use super::Sha256d;
 
 const PAYLOAD_PREFIX: &[u8] = b"EXONUM";
@@ -28,6 +35,10 @@ const PAYLOAD_HEADER_LEN: usize = 8;
 const PAYLOAD_V1: u8 = 1;
 const PAYLOAD_V1_KIND_REGULAR: u8 = 0;
 const PAYLOAD_V1_KIND_RECOVER: u8 = 1;
+const PAYLOAD_V2: u8 = 2;
+const PAYLOAD_V2_KIND_REGULAR: u8 = 0;
+const PAYLOAD_V3: u8 = 3;
+const PAYLOAD_V3_KIND_REGULAR: u8 = 0;
 
 /// Anchoring transaction payload.
 ///
@@ -36,13 +47,46 @@ const PAYLOAD_V1_KIND_RECOVER: u8 = 1;
 /// | Position in bytes     | Description                                       |
 /// |-----------------------|---------------------------------------------------|
 /// | 0..6                  | ASCII-encoded prefix `EXONUM`                     |
-/// | 6                     | Version byte, currently is 1                      |
+/// | 6                     | Version byte, equals to 1                         |
 /// | 7                     | Payload kind: (0 is regular, 1 is recover)        |
 /// | 8..16                 | Block height                                      |
 /// | 16..48                | Block hash                                        |
 /// | 48..80 (Optionally)   | Txid of previous tx chain (only for recover kind) |
 ///
 /// In this way the length of `regular` payload is 48, and for `recover` is 80.
+///
+/// Data layout in `OP_RETURN` script for `Payload` v.2:
+///
+/// | Position in bytes     | Description                                       |
+/// |-----------------------|---------------------------------------------------|
+/// | 0..6                  | ASCII-encoded prefix `EXONUM`                     |
+/// | 6                     | Version byte, equals to 2                         |
+/// | 7                     | Payload kind: (0 is regular)                      |
+/// | 8..16                 | Block height                                      |
+/// | 16..48                | Block hash                                        |
+/// | 48..80                | Merkle root of the anchored blocks hashes         |
+///
+/// The anchored blocks are the blocks following the previously anchored one up to the
+/// block with the given height inclusive. Their hashes are committed in the same way as
+/// the [`ProofListIndex`] does, so the length of payload is 80.
+///
+/// Data layout in `OP_RETURN` script for `Payload` v.3:
+///
+/// | Position in bytes     | Description                                       |
+/// |-----------------------|---------------------------------------------------|
+/// | 0..6                  | ASCII-encoded prefix `EXONUM`                     |
+/// | 6                     | Version byte, equals to 3                         |
+/// | 7                     | Payload kind: (0 is regular)                      |
+/// | 8..16                 | Block height                                      |
+/// | 16..48                | Block hash                                        |
+/// | 48..80                | Hash of the actual anchoring configuration        |
+///
+/// This version is used by the first anchoring transaction signed with the changed
+/// anchoring configuration. Since the `OP_RETURN` script has no room for both hashes,
+/// the blocks anchored by such transaction are committed by the Merkle root of the
+/// anchored blocks in the payload of the following anchoring transaction.
+///
+/// [`ProofListIndex`]: https://docs.rs/exonum-merkledb/latest/exonum_merkledb/struct.ProofListIndex.html
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Payload {
     /// Anchored block height.
@@ -51,6 +95,12 @@ pub struct Payload {
     pub block_hash: Hash,
     /// `Txid` of previous transactions chain if it has been lost.
     pub prev_tx_chain: Option<Sha256d>,
+    /// Merkle root of the anchored blocks hashes if it is committed by the payload.
+    #[serde(default)]
+    pub blocks_root: Option<Hash>,
+    /// Hash of the anchoring configuration if it is committed by the payload.
+    #[serde(default)]
+    pub config_hash: Option<Hash>,
 }
 
 #[derive(Debug)]
@@ -59,15 +109,25 @@ enum PayloadV1 {
     Recover(Height, Hash, Sha256d),
 }
 
+#[derive(Debug)]
+enum PayloadV2 {
+    Regular(Height, Hash, Hash),
+}
+
+#[derive(Debug)]
+enum PayloadV3 {
+    Regular(Height, Hash, Hash),
+}
+
 #[derive(Debug, Default)]
-pub struct PayloadV1Builder {
+pub struct PayloadBuilder {
     block_hash: Option<Hash>,
     block_height: Option<Height>,
     prev_tx_chain: Option<Sha256d>,
+    blocks_root: Option<Hash>,
+    config_hash: Option<Hash>,
 }
 
-pub type PayloadBuilder = PayloadV1Builder;
-
 #[cfg_attr(feature = "cargo-clippy", allow(clippy::len_without_is_empty))]
 impl PayloadV1 {
     fn read(bytes: &[u8]) -> Option<Self> {
@@ -146,12 +206,148 @@ impl PayloadV1 {
     }
 }
 
-impl PayloadV1Builder {
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::len_without_is_empty))]
+impl PayloadV2 {
+    fn read(bytes: &[u8]) -> Option<Self> {
+        let kind = bytes[0];
+        let data = &bytes[1..];
+        match kind {
+            PAYLOAD_V2_KIND_REGULAR => {
+                if data.len() != 72 {
+                    return None;
+                }
+
+                let block_height = LittleEndian::read_u64(&data[0..8]);
+                let block_hash = Hash::from_slice(&data[8..40]).unwrap();
+                let blocks_root = Hash::from_slice(&data[40..72]).unwrap();
+                Some(PayloadV2::Regular(
+                    Height(block_height),
+                    block_hash,
+                    blocks_root,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn write(&self, buf: &mut [u8]) {
+        let kind = self.kind();
+        buf[0] = kind as u8;
+
+        let buf = &mut buf[1..];
+        debug_assert_eq!(buf.len(), self.len());
+        // Serialize data
+        match *self {
+            PayloadV2::Regular(height, hash, blocks_root) => {
+                LittleEndian::write_u64(&mut buf[0..8], height.0);
+                buf[8..40].copy_from_slice(hash.as_ref());
+                buf[40..72].copy_from_slice(blocks_root.as_ref());
+            }
+        };
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            PayloadV2::Regular(..) => 72,
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match *self {
+            PayloadV2::Regular(..) => PAYLOAD_V2_KIND_REGULAR,
+        }
+    }
+
+    fn into_script(self) -> Script {
+        let len = self.len() + PAYLOAD_HEADER_LEN;
+        let mut buf = vec![0; len];
+        // Serialize header
+        buf[0..6].copy_from_slice(PAYLOAD_PREFIX);
+        buf[6] = PAYLOAD_V2;
+        self.write(&mut buf[7..]);
+        // Build script
+        Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_slice(buf.as_ref())
+            .into_script()
+    }
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::len_without_is_empty))]
+impl PayloadV3 {
+    fn read(bytes: &[u8]) -> Option<Self> {
+        let kind = bytes[0];
+        let data = &bytes[1..];
+        match kind {
+            PAYLOAD_V3_KIND_REGULAR => {
+                if data.len() != 72 {
+                    return None;
+                }
+
+                let block_height = LittleEndian::read_u64(&data[0..8]);
+                let block_hash = Hash::from_slice(&data[8..40]).unwrap();
+                let config_hash = Hash::from_slice(&data[40..72]).unwrap();
+                Some(PayloadV3::Regular(
+                    Height(block_height),
+                    block_hash,
+                    config_hash,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn write(&self, buf: &mut [u8]) {
+        let kind = self.kind();
+        buf[0] = kind as u8;
+
+        let buf = &mut buf[1..];
+        debug_assert_eq!(buf.len(), self.len());
+        // Serialize data
+        match *self {
+            PayloadV3::Regular(height, hash, config_hash) => {
+                LittleEndian::write_u64(&mut buf[0..8], height.0);
+                buf[8..40].copy_from_slice(hash.as_ref());
+                buf[40..72].copy_from_slice(config_hash.as_ref());
+            }
+        };
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            PayloadV3::Regular(..) => 72,
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match *self {
+            PayloadV3::Regular(..) => PAYLOAD_V3_KIND_REGULAR,
+        }
+    }
+
+    fn into_script(self) -> Script {
+        let len = self.len() + PAYLOAD_HEADER_LEN;
+        let mut buf = vec![0; len];
+        // Serialize header
+        buf[0..6].copy_from_slice(PAYLOAD_PREFIX);
+        buf[6] = PAYLOAD_V3;
+        self.write(&mut buf[7..]);
+        // Build script
+        Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_slice(buf.as_ref())
+            .into_script()
+    }
+}
+
+impl PayloadBuilder {
     pub fn new() -> Self {
         Self {
             block_hash: None,
             block_height: None,
             prev_tx_chain: None,
+            blocks_root: None,
+            config_hash: None,
         }
     }
 
@@ -170,19 +366,60 @@ impl PayloadV1Builder {
         self
     }
 
+    pub fn blocks_root(mut self, blocks_root: Option<Hash>) -> Self {
+        self.blocks_root = blocks_root;
+        self
+    }
+
+    pub fn config_hash(mut self, config_hash: Option<Hash>) -> Self {
+        self.config_hash = config_hash;
+        self
+    }
+
     pub fn into_script(self) -> Script {
         let block_height = self.block_height.expect("Block height is not set");
         let block_hash = self.block_hash.expect("Block hash is not set");
 
-        let payload = match self.prev_tx_chain {
-            Some(txid) => PayloadV1::Recover(block_height, block_hash, txid),
-            None => PayloadV1::Regular(block_height, block_hash),
-        };
-        payload.into_script()
+        // The recover payload has no room for the additional hashes within the `OP_RETURN`
+        // size limit, so it is still encoded by the first version. The configuration hash
+        // takes precedence over the blocks root.
+        match (self.prev_tx_chain, self.config_hash, self.blocks_root) {
+            (Some(txid), ..) => PayloadV1::Recover(block_height, block_hash, txid).into_script(),
+            (None, Some(config_hash), _) => {
+                PayloadV3::Regular(block_height, block_hash, config_hash).into_script()
+            }
+            (None, None, Some(blocks_root)) => {
+                PayloadV2::Regular(block_height, block_hash, blocks_root).into_script()
+            }
+            (None, None, None) => PayloadV1::Regular(block_height, block_hash).into_script(),
+        }
     }
 }
 
 impl Payload {
+    /// Returns `true` if the blocks anchored by this payload are committed by the following
+    /// anchoring transaction, because the payload commits to the configuration hash instead.
+    pub fn defers_blocks_root(&self) -> bool {
+        self.blocks_root.is_none() && self.config_hash.is_some()
+    }
+
+    /// Returns the `OP_RETURN` script which contains the payload.
+    pub fn to_script(&self) -> Script {
+        PayloadBuilder::new()
+            .block_hash(self.block_hash)
+            .block_height(self.block_height)
+            .prev_tx_chain(self.prev_tx_chain)
+            .blocks_root(self.blocks_root)
+            .config_hash(self.config_hash)
+            .into_script()
+    }
+
+    /// Returns the commitment to the payload, which is used to tweak the anchoring keys
+    /// in the pay-to-contract mode.
+    pub fn commitment(&self) -> Hash {
+        crypto::hash(self.to_script().as_bytes())
+    }
+
     /// Tries to extract payload from given `Script`.
     pub fn from_script(script: &Script) -> Option<Self> {
         let mut instructions = script.iter(true);
@@ -207,6 +444,8 @@ impl Payload {
                     let version = bytes[6];
                     match version {
                         PAYLOAD_V1 => PayloadV1::read(&bytes[7..]).map(Self::from),
+                        PAYLOAD_V2 => PayloadV2::read(&bytes[7..]).map(Self::from),
+                        PAYLOAD_V3 => PayloadV3::read(&bytes[7..]).map(Self::from),
                         _ => None,
                     }
                 } else {
@@ -223,16 +462,65 @@ impl From<PayloadV1> for Payload {
                 block_height: height,
                 block_hash: hash,
                 prev_tx_chain: None,
+                blocks_root: None,
+                config_hash: None,
             },
             PayloadV1::Recover(height, hash, txid) => Self {
                 block_height: height,
                 block_hash: hash,
                 prev_tx_chain: Some(txid),
+                blocks_root: None,
+                config_hash: None,
             },
         }
     }
 }
 
+impl From<PayloadV2> for Payload {
+    fn from(v2: PayloadV2) -> Self {
+        match v2 {
+            PayloadV2::Regular(height, hash, blocks_root) => Self {
+                block_height: height,
+                block_hash: hash,
+                prev_tx_chain: None,
+                blocks_root: Some(blocks_root),
+                config_hash: None,
+            },
+        }
+    }
+}
+
+impl From<PayloadV3> for Payload {
+    fn from(v3: PayloadV3) -> Self {
+        match v3 {
+            PayloadV3::Regular(height, hash, config_hash) => Self {
+                block_height: height,
+                block_hash: hash,
+                prev_tx_chain: None,
+                blocks_root: None,
+                config_hash: Some(config_hash),
+            },
+        }
+    }
+}
+
+impl BinaryValue for Payload {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_script().into_bytes()
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> anyhow::Result<Self> {
+        Self::from_script(&Script::from(bytes.into_owned()))
+            .ok_or_else(|| anyhow!("Unable to decode the anchoring payload."))
+    }
+}
+
+impl ObjectHash for Payload {
+    fn object_hash(&self) -> Hash {
+        crypto::hash(&self.to_bytes())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use exonum::crypto::hash;
@@ -326,6 +614,93 @@ mod tests {
         assert_eq!(payload.prev_tx_chain, Some(prev_txid));
     }
 
+    #[test]
+    fn test_payload_v2_regular_serialize() {
+        let block_hash = hash(&[]);
+        let payload_script = PayloadBuilder::new()
+            .block_hash(block_hash)
+            .block_height(Height(1234))
+            .blocks_root(Some(block_hash))
+            .into_script();
+
+        assert_eq!(
+            payload_script.to_hex(),
+            "6a4c5045584f4e554d0200d204000000000000e3b0c44298fc1c149afbf4c8996fb92427ae41e46\
+             49b934ca495991b7852b855e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7\
+             852b855"
+        );
+    }
+
+    #[test]
+    fn test_payload_v2_regular_deserialize() {
+        let payload_script = Script::from_hex(
+            "6a4c5045584f4e554d0200d204000000000000e3b0c44298fc1c\
+             149afbf4c8996fb92427ae41e4649b934ca495991b7852b855e3\
+             b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca49599\
+             1b7852b855",
+        );
+
+        let block_hash = hash(&[]);
+        let payload = Payload::from_script(&payload_script).unwrap();
+        assert_eq!(payload.block_hash, block_hash);
+        assert_eq!(payload.block_height, Height(1234));
+        assert_eq!(payload.prev_tx_chain, None);
+        assert_eq!(payload.blocks_root, Some(block_hash));
+    }
+
+    #[test]
+    fn test_payload_recover_ignores_blocks_root() {
+        let block_hash = hash(&[]);
+        let prev_txid = Sha256d::from_slice(block_hash.as_ref()).unwrap();
+        let payload_script = PayloadBuilder::new()
+            .block_hash(block_hash)
+            .block_height(Height(1234))
+            .prev_tx_chain(Some(prev_txid))
+            .blocks_root(Some(block_hash))
+            .into_script();
+
+        let payload = Payload::from_script(&payload_script).unwrap();
+        assert_eq!(payload.prev_tx_chain, Some(prev_txid));
+        assert_eq!(payload.blocks_root, None);
+    }
+
+    #[test]
+    fn test_payload_v3_regular_serialize() {
+        let block_hash = hash(&[]);
+        let payload_script = PayloadBuilder::new()
+            .block_hash(block_hash)
+            .block_height(Height(1234))
+            .blocks_root(Some(hash(&[1])))
+            .config_hash(Some(block_hash))
+            .into_script();
+
+        assert_eq!(
+            payload_script.to_hex(),
+            "6a4c5045584f4e554d0300d204000000000000e3b0c44298fc1c149afbf4c8996fb92427ae41e46\
+             49b934ca495991b7852b855e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7\
+             852b855"
+        );
+    }
+
+    #[test]
+    fn test_payload_v3_regular_deserialize() {
+        let payload_script = Script::from_hex(
+            "6a4c5045584f4e554d0300d204000000000000e3b0c44298fc1c\
+             149afbf4c8996fb92427ae41e4649b934ca495991b7852b855e3\
+             b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca49599\
+             1b7852b855",
+        );
+
+        let block_hash = hash(&[]);
+        let payload = Payload::from_script(&payload_script).unwrap();
+        assert_eq!(payload.block_hash, block_hash);
+        assert_eq!(payload.block_height, Height(1234));
+        assert_eq!(payload.prev_tx_chain, None);
+        assert_eq!(payload.blocks_root, None);
+        assert_eq!(payload.config_hash, Some(block_hash));
+        assert!(payload.defers_blocks_root());
+    }
+
     #[test]
     fn test_payload_incorrect_deserialize() {
         // Payload from old anchoring transaction
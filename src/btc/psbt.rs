@@ -0,0 +1,270 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! BIP-174 partially signed anchoring transactions for the offline signing.
+
+use anyhow::anyhow;
+use bitcoin::{
+    blockdata::transaction::SigHashType,
+    util::psbt::{raw, PartiallySignedTransaction},
+};
+use btc_transaction_utils::{multisig::RedeemScript, p2wsh, TxInRef};
+use derive_more::{From, Into};
+use exonum::crypto::Hash;
+use secp256k1::Secp256k1;
+
+use super::{
+    contract_private_key, contract_public_key, contract_redeem_script, InputSignature, PrivateKey,
+    PublicKey, Sha256d, Transaction,
+};
+
+/// Type of the proprietary PSBT fields according to the BIP-174.
+const PSBT_PROPRIETARY_TYPE: u8 = 0xFC;
+/// Identifier of the proprietary PSBT fields used by the anchoring service.
+const PSBT_PROPRIETARY_PREFIX: &[u8] = b"exonum";
+/// Subtype of the proprietary PSBT input field with the pay-to-contract commitment
+/// of the spent output.
+const PSBT_CONTRACT_COMMITMENT_SUBTYPE: u8 = 0x00;
+
+/// BIP-174 partially signed Bitcoin transaction wrapper.
+#[derive(Debug, Clone, From, Into, PartialEq)]
+pub struct Psbt(pub PartiallySignedTransaction);
+
+impl_wrapper_for_bitcoin_type! { Psbt }
+
+impl Psbt {
+    /// Creates a partially signed transaction for the given anchoring transaction proposal.
+    ///
+    /// Each input contains the spent output, the transaction which contains it and the witness
+    /// script. If the spent output is committed in the pay-to-contract mode, the input also
+    /// contains the commitment in the proprietary field, so the signer can tweak its key.
+    pub fn new(
+        proposal: &Transaction,
+        inputs: &[Transaction],
+        redeem_script: &RedeemScript,
+        input_commitments: &[Option<Hash>],
+    ) -> Self {
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(proposal.0.clone())
+            .expect("Anchoring transaction proposal should be unsigned.");
+
+        for (index, (psbt_input, prev_tx)) in psbt.inputs.iter_mut().zip(inputs).enumerate() {
+            let commitment = input_commitments.get(index).cloned().flatten();
+            let witness_script = match commitment {
+                Some(commitment) => contract_redeem_script(redeem_script, &commitment),
+                None => redeem_script.clone(),
+            };
+            let vout = proposal.0.input[index].previous_output.vout as usize;
+
+            psbt_input.witness_utxo = prev_tx.0.output.get(vout).cloned();
+            psbt_input.non_witness_utxo = Some(prev_tx.0.clone());
+            psbt_input.witness_script = Some(witness_script.as_ref().clone());
+            psbt_input.sighash_type = Some(SigHashType::All);
+            if let Some(commitment) = commitment {
+                psbt_input
+                    .unknown
+                    .insert(contract_commitment_key(), commitment.as_ref().to_vec());
+            }
+        }
+        Self(psbt)
+    }
+
+    /// Returns the identifier of the unsigned transaction.
+    pub fn id(&self) -> Sha256d {
+        self.0.global.unsigned_tx.txid().into()
+    }
+
+    /// Returns the pay-to-contract commitment of the output spent by the input with
+    /// the given index, if any.
+    pub fn input_commitment(&self, index: usize) -> Option<Hash> {
+        let value = self
+            .0
+            .inputs
+            .get(index)?
+            .unknown
+            .get(&contract_commitment_key())?;
+        Hash::from_slice(value)
+    }
+
+    /// Signs each input of the transaction by the given anchoring key and adds signatures
+    /// to the partial ones. The inputs which spend the outputs committed in
+    /// the pay-to-contract mode are signed by the tweaked key.
+    pub fn sign(
+        &mut self,
+        redeem_script: &RedeemScript,
+        private_key: &PrivateKey,
+    ) -> anyhow::Result<()> {
+        let unsigned_tx = self.0.global.unsigned_tx.clone();
+        for index in 0..self.0.inputs.len() {
+            let (redeem_script, private_key) = match self.input_commitment(index) {
+                Some(commitment) => (
+                    contract_redeem_script(redeem_script, &commitment),
+                    contract_private_key(private_key, &commitment),
+                ),
+                None => (redeem_script.clone(), private_key.clone()),
+            };
+
+            let psbt_input = &mut self.0.inputs[index];
+            let prev_tx = psbt_input
+                .non_witness_utxo
+                .as_ref()
+                .ok_or_else(|| anyhow!("Missing spent transaction for input: {}", index))?;
+            let signature = p2wsh::InputSigner::new(redeem_script).sign_input(
+                TxInRef::new(&unsigned_tx, index),
+                prev_tx,
+                &private_key.0.key,
+            )?;
+
+            let public_key = private_key.0.public_key(&Secp256k1::signing_only());
+            psbt_input.partial_sigs.insert(public_key, signature.into());
+        }
+        Ok(())
+    }
+
+    /// Returns the partial signatures made by the given anchoring key along with the indexes
+    /// of the signed inputs. The signatures of the inputs which spend the outputs committed
+    /// in the pay-to-contract mode are looked up by the tweaked key.
+    pub fn input_signatures(
+        &self,
+        public_key: &PublicKey,
+    ) -> anyhow::Result<Vec<(u32, InputSignature)>> {
+        let mut signatures = Vec::new();
+        for (index, psbt_input) in self.0.inputs.iter().enumerate() {
+            let public_key = match self.input_commitment(index) {
+                Some(commitment) => contract_public_key(public_key, &commitment),
+                None => *public_key,
+            };
+            if let Some(bytes) = psbt_input.partial_sigs.get(&public_key.0) {
+                let signature = btc_transaction_utils::InputSignature::from_bytes(bytes.clone())?;
+                signatures.push((index as u32, InputSignature(signature)));
+            }
+        }
+        Ok(signatures)
+    }
+}
+
+/// Returns the key of the proprietary PSBT input field with the pay-to-contract commitment.
+fn contract_commitment_key() -> raw::Key {
+    let mut key = vec![PSBT_PROPRIETARY_PREFIX.len() as u8];
+    key.extend_from_slice(PSBT_PROPRIETARY_PREFIX);
+    key.push(PSBT_CONTRACT_COMMITMENT_SUBTYPE);
+    raw::Key {
+        type_value: PSBT_PROPRIETARY_TYPE,
+        key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::network::constants::Network;
+    use btc_transaction_utils::{multisig::RedeemScriptBuilder, p2wsh, TxInRef};
+    use exonum::{crypto::hash, helpers::Height};
+    use exonum_merkledb::{BinaryValue, ObjectHash};
+
+    use crate::{
+        btc::{
+            contract_public_key, contract_redeem_script, gen_keypair, Address,
+            BtcAnchoringTransactionBuilder,
+        },
+        test_helpers::create_fake_funding_transaction,
+    };
+
+    use super::Psbt;
+
+    #[test]
+    fn test_psbt_sign() {
+        let keypairs = (0..3)
+            .map(|_| gen_keypair(Network::Testnet))
+            .collect::<Vec<_>>();
+        let redeem_script =
+            RedeemScriptBuilder::with_public_keys(keypairs.iter().map(|keypair| (keypair.0).0))
+                .quorum(2)
+                .to_script()
+                .unwrap();
+        let address = Address(bitcoin::Address::p2wsh(
+            redeem_script.as_ref(),
+            Network::Testnet,
+        ));
+        let funding_tx = create_fake_funding_transaction(&address, 10_000);
+
+        let mut builder = BtcAnchoringTransactionBuilder::new(&redeem_script);
+        builder.additional_funds(funding_tx.clone()).unwrap();
+        builder.fee(1);
+        builder.payload(Height::zero(), funding_tx.object_hash());
+        let (proposal, inputs) = builder.create().unwrap();
+
+        let mut psbt = Psbt::new(&proposal, &inputs, &redeem_script, &[None]);
+        assert_eq!(psbt.id(), proposal.id());
+        assert_eq!(psbt.input_commitment(0), None);
+        assert_eq!(Psbt::from_bytes(psbt.to_bytes().into()).unwrap(), psbt);
+
+        psbt.sign(&redeem_script, &keypairs[0].1).unwrap();
+        psbt.sign(&redeem_script, &keypairs[1].1).unwrap();
+        for (public_key, _) in &keypairs[0..2] {
+            let signatures = psbt.input_signatures(public_key).unwrap();
+            assert_eq!(signatures.len(), 1);
+            assert_eq!(signatures[0].0, 0);
+            p2wsh::InputSigner::new(redeem_script.clone())
+                .verify_input(
+                    TxInRef::new(proposal.as_ref(), 0),
+                    inputs[0].as_ref(),
+                    &public_key.0,
+                    signatures[0].1.as_ref(),
+                )
+                .unwrap();
+        }
+        assert!(psbt.input_signatures(&keypairs[2].0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_psbt_sign_pay_to_contract() {
+        let (public_key, private_key) = gen_keypair(Network::Testnet);
+        let redeem_script = RedeemScriptBuilder::with_public_keys(vec![public_key.0])
+            .to_script()
+            .unwrap();
+        let address = Address(bitcoin::Address::p2wsh(
+            redeem_script.as_ref(),
+            Network::Testnet,
+        ));
+        let funding_tx = create_fake_funding_transaction(&address, 10_000);
+
+        let mut builder = BtcAnchoringTransactionBuilder::new(&redeem_script);
+        builder.additional_funds(funding_tx.clone()).unwrap();
+        builder.fee(1);
+        builder.payload(Height::zero(), funding_tx.object_hash());
+        let (proposal, inputs) = builder.create().unwrap();
+
+        // Pretend that the spent output is committed in the pay-to-contract mode.
+        let commitment = hash(&[1, 2, 3]);
+        let mut psbt = Psbt::new(&proposal, &inputs, &redeem_script, &[Some(commitment)]);
+        assert_eq!(psbt.input_commitment(0), Some(commitment));
+        assert_eq!(Psbt::from_bytes(psbt.to_bytes().into()).unwrap(), psbt);
+
+        // The input is signed by the tweaked key.
+        psbt.sign(&redeem_script, &private_key).unwrap();
+        let tweaked_key = contract_public_key(&public_key, &commitment);
+        assert!(psbt.0.inputs[0].partial_sigs.contains_key(&tweaked_key.0));
+        assert!(!psbt.0.inputs[0].partial_sigs.contains_key(&public_key.0));
+
+        let signatures = psbt.input_signatures(&public_key).unwrap();
+        assert_eq!(signatures.len(), 1);
+        p2wsh::InputSigner::new(contract_redeem_script(&redeem_script, &commitment))
+            .verify_input(
+                TxInRef::new(proposal.as_ref(), 0),
+                inputs[0].as_ref(),
+                &tweaked_key.0,
+                signatures[0].1.as_ref(),
+            )
+            .unwrap();
+    }
+}
@@ -9,15 +9,31 @@
 
 use exonum::{crypto::Hash, helpers::Height};
 
-use bitcoin::blockdata::{
-    script::Script,
-    transaction::{self, OutPoint, TxIn, TxOut},
+use bitcoin::{
+    blockdata::{
+        script::Script,
+        transaction::{self, OutPoint, TxIn, TxOut},
+    },
+    consensus::encode::VarInt,
 };
 use btc_transaction_utils::multisig::RedeemScript;
 use derive_more::{From, Into};
 use thiserror::Error;
 
-use super::{payload::PayloadBuilder, Payload, Sha256d};
+use std::cmp;
+
+use super::{contract::contract_redeem_script, payload::PayloadBuilder, Payload, Sha256d};
+
+/// Ratio between the weight and the virtual size of the transaction.
+const WITNESS_SCALE_FACTOR: u64 = 4;
+/// Length of the segwit marker and flag bytes.
+const SEGWIT_MARKER_LEN: u64 = 2;
+/// Maximum length of a DER-encoded ECDSA signature with the sighash type byte.
+const MAX_INPUT_SIGNATURE_LEN: usize = 73;
+/// Input sequence number which disables the transaction replacement.
+const SEQUENCE_FINAL: u32 = 0xFFFF_FFFF;
+/// Input sequence number which signals the transaction replaceability according to BIP-125.
+const SEQUENCE_REPLACEABLE: u32 = 0xFFFF_FFFD;
 
 /// Bitcoin transaction wrapper.
 #[derive(Debug, Clone, From, Into, PartialEq)]
@@ -44,11 +60,19 @@ impl Transaction {
 
     /// Find output number for the given script pubkey.
     pub fn find_out(&self, script_pubkey: &Script) -> Option<(usize, &TxOut)> {
+        self.find_outs(script_pubkey).next()
+    }
+
+    /// Find all outputs with the given script pubkey and their numbers.
+    pub fn find_outs<'a>(
+        &'a self,
+        script_pubkey: &'a Script,
+    ) -> impl Iterator<Item = (usize, &'a TxOut)> + 'a {
         self.0
             .output
             .iter()
             .enumerate()
-            .find(|out| &out.1.script_pubkey == script_pubkey)
+            .filter(move |out| &out.1.script_pubkey == script_pubkey)
     }
 
     /// Return the anchoring payload for the transaction if it is the anchoring transaction.
@@ -70,18 +94,65 @@ impl Transaction {
     pub fn unspent_value(&self) -> Option<u64> {
         self.0.output.get(0).map(|out| out.value)
     }
+
+    /// Return the virtual size of the transaction.
+    pub fn vsize(&self) -> u64 {
+        (self.0.get_weight() as u64 + WITNESS_SCALE_FACTOR - 1) / WITNESS_SCALE_FACTOR
+    }
+
+    /// Return the fee paid by the transaction, given the list of transactions
+    /// whose outputs are spent by its inputs.
+    pub fn fee(&self, inputs: &[Transaction]) -> Option<u64> {
+        let input_value = self
+            .0
+            .input
+            .iter()
+            .zip(inputs)
+            .map(|(txin, tx)| {
+                tx.0.output
+                    .get(txin.previous_output.vout as usize)
+                    .map(|out| out.value)
+            })
+            .sum::<Option<u64>>()?;
+        let output_value = self.0.output.iter().map(|out| out.value).sum::<u64>();
+        input_value.checked_sub(output_value)
+    }
+
+    /// Estimates the virtual size of the unsigned transaction after all of its inputs are
+    /// signed by the quorum of the keys from the given redeem script.
+    pub fn estimate_signed_vsize(&self, redeem_script: &RedeemScript) -> u64 {
+        estimate_vsize(self, estimate_witness_len(redeem_script))
+    }
+
+    /// Check that the transaction signals replaceability according to BIP-125.
+    pub fn is_replaceable(&self) -> bool {
+        self.0
+            .input
+            .iter()
+            .any(|txin| txin.sequence < SEQUENCE_FINAL - 1)
+    }
 }
 
 /// Builder for the anchoring transactions.
 #[derive(Debug)]
 pub struct BtcAnchoringTransactionBuilder {
+    redeem_script: RedeemScript,
     script_pubkey: Script,
+    prev_script_pubkey: Script,
+    input_witness_len: u64,
     transit_to: Option<Script>,
+    contract: Option<RedeemScript>,
+    op_return: bool,
     prev_tx: Option<Transaction>,
     recovery_tx: Option<Sha256d>,
     additional_funds: Vec<(usize, Transaction)>,
+    replaced_inputs: Option<Vec<(usize, Transaction)>>,
+    replaceable: bool,
+    unconfirmed_parent: Option<(u64, u64)>,
     fee: Option<u64>,
     payload: Option<(Height, Hash)>,
+    blocks_root: Option<Hash>,
+    config_hash: Option<Hash>,
 }
 
 /// Anchoring transaction builder errors.
@@ -107,19 +178,33 @@ pub enum BuilderError {
     /// Funding transaction doesn't contains outputs to the anchoring address.
     #[error("Funding transaction doesn't contains outputs to the anchoring address.")]
     UnsuitableFundingTx,
+    /// Replaced transaction cannot be spent by the actual redeem script.
+    #[error("Replaced transaction cannot be spent by the actual redeem script.")]
+    UnsuitableReplacedTx,
 }
 
 impl BtcAnchoringTransactionBuilder {
     /// Creates a new btc anchoring transaction builder for the given redeem script.
     pub fn new(redeem_script: &RedeemScript) -> BtcAnchoringTransactionBuilder {
+        let script_pubkey = redeem_script.as_ref().to_v0_p2wsh();
         Self {
-            script_pubkey: redeem_script.as_ref().to_v0_p2wsh(),
+            redeem_script: redeem_script.clone(),
+            prev_script_pubkey: script_pubkey.clone(),
+            script_pubkey,
+            input_witness_len: estimate_witness_len(redeem_script),
             transit_to: None,
+            contract: None,
+            op_return: true,
             prev_tx: None,
             recovery_tx: None,
             additional_funds: Vec::default(),
+            replaced_inputs: None,
+            replaceable: false,
+            unconfirmed_parent: None,
             fee: None,
             payload: None,
+            blocks_root: None,
+            config_hash: None,
         }
     }
 
@@ -128,14 +213,33 @@ impl BtcAnchoringTransactionBuilder {
         self.transit_to = Some(script);
     }
 
+    /// Enables the pay-to-contract commitment mode. In this mode the anchoring transaction
+    /// has no `OP_RETURN` output, instead the payload is committed by tweaking the keys of
+    /// the given redeem script, which locks the anchoring output.
+    pub fn pay_to_contract(&mut self, redeem_script: RedeemScript) {
+        self.contract = Some(redeem_script);
+        self.op_return = false;
+    }
+
+    /// Sets the payload of the previous anchoring transaction committed in the pay-to-contract
+    /// mode. This method should be called before the [`prev_tx`](#method.prev_tx) or
+    /// [`replace`](#method.replace) ones.
+    pub fn prev_contract_payload(&mut self, payload: &Payload) {
+        self.prev_script_pubkey =
+            contract_redeem_script(&self.redeem_script, &payload.commitment())
+                .as_ref()
+                .to_v0_p2wsh();
+    }
+
     /// Sets an transaction which corresponding unspent output will use
     /// as an input for the following anchoring transaction.
     pub fn prev_tx(&mut self, tx: Transaction) -> Result<(), BuilderError> {
-        if tx.anchoring_metadata().unwrap().0 != &self.script_pubkey {
-            Err(BuilderError::UnsuitableOutput)
-        } else {
-            self.prev_tx = Some(tx);
-            Ok(())
+        match tx.0.output.get(0) {
+            Some(out) if out.script_pubkey == self.prev_script_pubkey => {
+                self.prev_tx = Some(tx);
+                Ok(())
+            }
+            _ => Err(BuilderError::UnsuitableOutput),
         }
     }
 
@@ -145,18 +249,105 @@ impl BtcAnchoringTransactionBuilder {
         self.recovery_tx = Some(last_tx);
     }
 
-    /// Adds an additional funding transaction which corresponding unspent output
-    /// will use as additional input for the following anchoring transaction.
+    /// Adds an additional funding transaction which corresponding unspent outputs
+    /// will use as additional inputs for the following anchoring transaction.
     pub fn additional_funds(&mut self, tx: Transaction) -> Result<(), BuilderError> {
-        let out = tx
-            .find_out(&self.script_pubkey)
-            .ok_or_else(|| BuilderError::UnsuitableFundingTx)?
-            .0;
-        self.additional_funds.push((out, tx));
+        let outs = tx
+            .find_outs(&self.script_pubkey)
+            .map(|(out, _)| out)
+            .collect::<Vec<_>>();
+        if outs.is_empty() {
+            return Err(BuilderError::UnsuitableFundingTx);
+        }
+
+        for out in outs {
+            self.additional_funds.push((out, tx.clone()));
+        }
         Ok(())
     }
 
-    /// Sets the fee per byte value.
+    /// Adds the output with the given number of the funding transaction as additional
+    /// input for the following anchoring transaction.
+    pub fn additional_funding_output(
+        &mut self,
+        tx: Transaction,
+        out: usize,
+    ) -> Result<(), BuilderError> {
+        match tx.0.output.get(out) {
+            Some(txout) if txout.script_pubkey == self.script_pubkey => {
+                self.additional_funds.push((out, tx));
+                Ok(())
+            }
+            _ => Err(BuilderError::UnsuitableFundingTx),
+        }
+    }
+
+    /// Enables the signaling of the transaction replaceability according to BIP-125.
+    pub fn replaceable(&mut self, replaceable: bool) {
+        self.replaceable = replaceable;
+    }
+
+    /// Sets an anchoring transaction with the given payload which should be replaced by
+    /// the created one. The replacement spends the same outputs and commits to the same
+    /// payload in the same way, but pays the fee set by the [`fee`](#method.fee) method.
+    pub fn replace(
+        &mut self,
+        tx: &Transaction,
+        payload: Payload,
+        inputs: Vec<Transaction>,
+    ) -> Result<(), BuilderError> {
+        let script_pubkey =
+            tx.0.output
+                .get(0)
+                .map(|out| &out.script_pubkey)
+                .ok_or(BuilderError::UnsuitableReplacedTx)?;
+        if tx.0.input.len() != inputs.len() {
+            return Err(BuilderError::UnsuitableReplacedTx);
+        }
+        // Make sure that all inputs of the replaced transaction can be signed by
+        // the actual anchoring nodes.
+        let replaced_inputs =
+            tx.0.input
+                .iter()
+                .zip(inputs)
+                .map(|(txin, input_tx)| {
+                    let out_index = txin.previous_output.vout as usize;
+                    match input_tx.0.output.get(out_index) {
+                        Some(out)
+                            if out.script_pubkey == self.script_pubkey
+                                || out.script_pubkey == self.prev_script_pubkey =>
+                        {
+                            Ok((out_index, input_tx))
+                        }
+                        _ => Err(BuilderError::UnsuitableReplacedTx),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+        if script_pubkey != &self.script_pubkey {
+            self.transit_to = Some(script_pubkey.clone());
+        }
+        // The replaced transaction has no `OP_RETURN` output if it has been committed
+        // in the pay-to-contract mode.
+        self.op_return = tx.anchoring_payload().is_some();
+        self.recovery_tx = payload.prev_tx_chain;
+        self.payload = Some((payload.block_height, payload.block_hash));
+        self.blocks_root = payload.blocks_root;
+        self.config_hash = payload.config_hash;
+        self.replaced_inputs = Some(replaced_inputs);
+        self.replaceable = true;
+        Ok(())
+    }
+
+    /// Sets the virtual size and the fee of the unconfirmed previous anchoring transaction,
+    /// which should be accelerated by the created one. In this case the created transaction
+    /// pays the fee sufficient for the whole package to reach the fee per virtual byte
+    /// set by the [`fee`](#method.fee) method (child pays for parent).
+    pub fn accelerate(&mut self, parent_vsize: u64, parent_fee: u64) {
+        self.unconfirmed_parent = Some((parent_vsize, parent_fee));
+    }
+
+    /// Sets the fee per virtual byte value.
     pub fn fee(&mut self, fee: u64) {
         self.fee = Some(fee);
     }
@@ -166,20 +357,51 @@ impl BtcAnchoringTransactionBuilder {
         self.payload = Some((block_height, block_hash));
     }
 
+    /// Sets the Merkle root of the anchored blocks hashes, which should be committed
+    /// by the anchoring transaction payload.
+    pub fn blocks_root(&mut self, blocks_root: Hash) {
+        self.blocks_root = Some(blocks_root);
+    }
+
+    /// Sets the hash of the anchoring configuration, which should be committed
+    /// by the anchoring transaction payload. It should be set if the configuration
+    /// has been changed since the previous anchoring transaction.
+    pub fn config_hash(&mut self, config_hash: Hash) {
+        self.config_hash = Some(config_hash);
+    }
+
     /// Finalizes the anchoring transaction and returns
     /// it with the list of input transactions.
-    pub fn create(mut self) -> Result<(Transaction, Vec<Transaction>), BuilderError> {
+    pub fn create(self) -> Result<(Transaction, Vec<Transaction>), BuilderError> {
+        self.create_with_payload()
+            .map(|(transaction, inputs, _)| (transaction, inputs))
+    }
+
+    /// Finalizes the anchoring transaction and returns it with the list of input
+    /// transactions and the payload committed by the transaction.
+    pub fn create_with_payload(
+        mut self,
+    ) -> Result<(Transaction, Vec<Transaction>, Payload), BuilderError> {
         // Creates transaction inputs.
         let (input, input_transactions, balance) = {
             let mut input = Vec::new();
             let mut input_transactions = Vec::new();
             let mut balance = 0;
 
-            let tx_iter = self
-                .prev_tx
-                .into_iter()
-                .map(|tx| (0, tx))
-                .chain(self.additional_funds.into_iter());
+            let sequence = if self.replaceable {
+                SEQUENCE_REPLACEABLE
+            } else {
+                SEQUENCE_FINAL
+            };
+            let tx_iter = match self.replaced_inputs.take() {
+                Some(replaced_inputs) => replaced_inputs,
+                None => self
+                    .prev_tx
+                    .into_iter()
+                    .map(|tx| (0, tx))
+                    .chain(self.additional_funds.into_iter())
+                    .collect(),
+            };
             for (out_index, tx) in tx_iter {
                 let txin = TxIn {
                     previous_output: OutPoint {
@@ -187,7 +409,7 @@ impl BtcAnchoringTransactionBuilder {
                         vout: out_index as u32,
                     },
                     script_sig: Script::default(),
-                    sequence: 0xFFFF_FFFF,
+                    sequence,
                     witness: Vec::default(),
                 };
                 balance += tx.0.output[out_index].value;
@@ -208,44 +430,83 @@ impl BtcAnchoringTransactionBuilder {
             .block_hash(block_hash)
             .block_height(block_height)
             .prev_tx_chain(self.recovery_tx)
+            .blocks_root(self.blocks_root)
+            .config_hash(self.config_hash)
             .into_script();
-        let output = match self.transit_to {
-            Some(script) => script,
-            _ => self.script_pubkey,
+        let payload = Payload::from_script(&payload_script).expect("Malformed payload script.");
+        let script_pubkey = match (self.contract.take(), self.transit_to) {
+            (Some(redeem_script), _) => {
+                contract_redeem_script(&redeem_script, &payload.commitment())
+                    .as_ref()
+                    .to_v0_p2wsh()
+            }
+            (None, Some(script)) => script,
+            (None, None) => self.script_pubkey,
         };
+        let mut output = vec![TxOut {
+            value: balance,
+            script_pubkey,
+        }];
+        if self.op_return {
+            output.push(TxOut {
+                value: 0,
+                script_pubkey: payload_script,
+            });
+        }
 
         // Create unsigned transaction.
         let mut transaction = Transaction::from(transaction::Transaction {
             version: 2,
             lock_time: 0,
             input,
-            output: vec![
-                TxOut {
-                    value: balance,
-                    script_pubkey: output,
-                },
-                TxOut {
-                    value: 0,
-                    script_pubkey: payload_script,
-                },
-            ],
+            output,
         });
 
-        // Compute a total fee value.
-        let size_in_bytes = {
-            let bytes = ::bitcoin::consensus::serialize(&transaction.0);
-            bytes.len() as u64
-        };
-        let total_fee = self.fee.expect("Fee per byte isn't set.") * size_in_bytes;
+        // Compute a total fee value taking into account the witness data, which
+        // will be added after the inputs are signed.
+        let vsize = estimate_vsize(&transaction, self.input_witness_len);
+        let fee = self.fee.expect("Fee per byte isn't set.");
+        let mut total_fee = fee * vsize;
+        // Take into account the fee deficit of the unconfirmed parent transaction.
+        if let Some((parent_vsize, parent_fee)) = self.unconfirmed_parent {
+            let package_fee = fee * (vsize + parent_vsize);
+            total_fee = cmp::max(total_fee, package_fee.saturating_sub(parent_fee));
+        }
         if total_fee > balance {
             return Err(BuilderError::InsufficientFunds { total_fee, balance });
         }
         // Set the corresponding fee.
         transaction.0.output[0].value -= total_fee;
-        Ok((transaction, input_transactions))
+        Ok((transaction, input_transactions, payload))
     }
 }
 
+/// Estimates the virtual size of the given unsigned transaction after all of its inputs
+/// are signed, provided that the witness data of each input has the given length.
+fn estimate_vsize(transaction: &Transaction, input_witness_len: u64) -> u64 {
+    let base_size = bitcoin::consensus::serialize(&transaction.0).len() as u64;
+    let witness_size = SEGWIT_MARKER_LEN + input_witness_len * transaction.0.input.len() as u64;
+    let weight = base_size * WITNESS_SCALE_FACTOR + witness_size;
+    // Round up to the nearest virtual byte.
+    (weight + WITNESS_SCALE_FACTOR - 1) / WITNESS_SCALE_FACTOR
+}
+
+/// Estimates the length of the witness data spending the P2WSH output locked
+/// by the given multisig redeem script.
+fn estimate_witness_len(redeem_script: &RedeemScript) -> u64 {
+    let quorum = redeem_script.content().quorum;
+    let script_len = redeem_script.as_ref().len();
+    // Witness stack consists of an empty item required by the `OP_CHECKMULTISIG` opcode,
+    // signatures of the quorum and the redeem script itself.
+    let items_count = quorum + 2;
+    let len = VarInt(items_count as u64).len()
+        + 1
+        + quorum * (1 + MAX_INPUT_SIGNATURE_LEN)
+        + VarInt(script_len as u64).len()
+        + script_len;
+    len as u64
+}
+
 #[cfg(test)]
 mod tests {
     use exonum::crypto::Hash;
@@ -269,7 +530,7 @@ mod tests {
 
     use std::borrow::Cow;
 
-    use crate::btc::PublicKey;
+    use crate::btc::{contract_redeem_script, PublicKey};
 
     use super::{BtcAnchoringTransactionBuilder, BuilderError, Transaction};
 
@@ -461,6 +722,117 @@ mod tests {
         assert_eq!(out_1.value, 0);
     }
 
+    #[test]
+    fn test_anchoring_transaction_builder_witness_fee() {
+        let funding_tx: Transaction = Transaction::from_hex(
+            "02000000000101b651818fe3855d0d5d74de1cf72b56503c16f808519440e842b6\
+             dc2dd570c4930100000000feffffff02deaa7b0000000000160014923904449829\
+             cd865cdfb72abdba0806ce9e48911027000000000000220020e9bb049fdff8f8d3\
+             b33b7335978b1dbb268833a32a69906f9e500e4103151bef02483045022100ddc7\
+             eb1193529a8d0e48cf24f536d5fbb5de3b67d2f56c98190ea8585d58a156022075\
+             e33981f1a7d78ce2915402d4b9b38b8d5311e0aef2e3ccf9284d2ce602968d0121\
+             021d0478acd223fb9b2ad7485f06f12914a1b7effc78390a08c50bfe53b3b24815\
+             062c1400",
+        )
+        .unwrap();
+
+        let keys = vec![
+            "038b782f94d19f34536a96e12e0bad99e6f82c838fa16a4234572f5f132d95ba29",
+            "020ae2216f42575c4196864eda0252c75c61273065f691b32be9a99cb2a3c9b4d1",
+            "02536d5e1464b961562da57207e4a46edb7dade9b92aa29712ca8309c8aba5be5b",
+        ]
+        .iter()
+        .map(|h| PublicKey::from_hex(h).unwrap().0)
+        .collect::<Vec<_>>();
+
+        let redeem_script = RedeemScriptBuilder::with_public_keys(keys)
+            .to_script()
+            .unwrap();
+        let quorum = redeem_script.content().quorum as u64;
+
+        let mut builder = BtcAnchoringTransactionBuilder::new(&redeem_script);
+        builder.additional_funds(funding_tx.clone()).unwrap();
+        builder.fee(10);
+        builder.payload(Height::zero(), funding_tx.object_hash());
+        let (tx, _) = builder.create().unwrap();
+
+        // Unsigned transaction with one input and two outputs takes 153 bytes, and
+        // the witness data consists of the items count, an empty item, signatures
+        // with the length prefixes and the redeem script with the length prefix.
+        let witness_len = 1 + 1 + quorum * 74 + 1 + 105;
+        let weight = 153 * 4 + 2 + witness_len;
+        let vsize = (weight + 3) / 4;
+        assert_eq!(10_000 - tx.0.output[0].value, vsize * 10);
+    }
+
+    #[test]
+    fn test_anchoring_transaction_builder_pay_to_contract() {
+        let funding_tx: Transaction = Transaction::from_hex(
+            "02000000000101b651818fe3855d0d5d74de1cf72b56503c16f808519440e842b6\
+             dc2dd570c4930100000000feffffff02deaa7b0000000000160014923904449829\
+             cd865cdfb72abdba0806ce9e48911027000000000000220020e9bb049fdff8f8d3\
+             b33b7335978b1dbb268833a32a69906f9e500e4103151bef02483045022100ddc7\
+             eb1193529a8d0e48cf24f536d5fbb5de3b67d2f56c98190ea8585d58a156022075\
+             e33981f1a7d78ce2915402d4b9b38b8d5311e0aef2e3ccf9284d2ce602968d0121\
+             021d0478acd223fb9b2ad7485f06f12914a1b7effc78390a08c50bfe53b3b24815\
+             062c1400",
+        )
+        .unwrap();
+
+        let keys = vec![
+            "038b782f94d19f34536a96e12e0bad99e6f82c838fa16a4234572f5f132d95ba29",
+            "020ae2216f42575c4196864eda0252c75c61273065f691b32be9a99cb2a3c9b4d1",
+            "02536d5e1464b961562da57207e4a46edb7dade9b92aa29712ca8309c8aba5be5b",
+        ]
+        .iter()
+        .map(|h| PublicKey::from_hex(h).unwrap().0)
+        .collect::<Vec<_>>();
+
+        let redeem_script = RedeemScriptBuilder::with_public_keys(keys)
+            .to_script()
+            .unwrap();
+
+        let mut builder = BtcAnchoringTransactionBuilder::new(&redeem_script);
+        builder.additional_funds(funding_tx.clone()).unwrap();
+        builder.fee(10);
+        builder.payload(Height::zero(), funding_tx.object_hash());
+        let (op_return_tx, _) = builder.create().unwrap();
+
+        let mut builder = BtcAnchoringTransactionBuilder::new(&redeem_script);
+        builder.pay_to_contract(redeem_script.clone());
+        builder.additional_funds(funding_tx.clone()).unwrap();
+        builder.fee(10);
+        builder.payload(Height::zero(), funding_tx.object_hash());
+        let (tx, _, payload) = builder.create_with_payload().unwrap();
+
+        // The payload is committed by the anchoring output instead of the `OP_RETURN` one.
+        assert_eq!(tx.0.output.len(), 1);
+        assert_eq!(tx.anchoring_payload(), None);
+        assert_eq!(payload, op_return_tx.anchoring_payload().unwrap());
+        assert_eq!(
+            tx.0.output[0].script_pubkey,
+            contract_redeem_script(&redeem_script, &payload.commitment())
+                .as_ref()
+                .to_v0_p2wsh()
+        );
+        assert!(tx.0.output[0].value > op_return_tx.0.output[0].value);
+
+        // The following transaction can spend the anchoring output only if the payload
+        // of the previous transaction is known.
+        let mut builder = BtcAnchoringTransactionBuilder::new(&redeem_script);
+        assert_eq!(
+            builder.prev_tx(tx.clone()),
+            Err(BuilderError::UnsuitableOutput)
+        );
+        builder.prev_contract_payload(&payload);
+        builder.prev_tx(tx.clone()).unwrap();
+        builder.fee(10);
+        builder.payload(Height(1), tx.object_hash());
+        let (next_tx, inputs) = builder.create().unwrap();
+        assert_eq!(next_tx.prev_tx_id(), tx.id());
+        assert_eq!(inputs, vec![tx]);
+    }
+
     #[test]
     fn test_anchoring_transaction_builder_funds() {
         let funding_tx0: Transaction = Transaction::from_hex(
@@ -532,6 +904,61 @@ mod tests {
         assert_eq!(out_1.value, 0);
     }
 
+    #[test]
+    fn test_anchoring_transaction_builder_multiple_funding_outputs() {
+        let mut funding_tx: Transaction = Transaction::from_hex(
+            "02000000000101b651818fe3855d0d5d74de1cf72b56503c16f808519440e842b6\
+             dc2dd570c4930100000000feffffff02deaa7b0000000000160014923904449829\
+             cd865cdfb72abdba0806ce9e48911027000000000000220020e9bb049fdff8f8d3\
+             b33b7335978b1dbb268833a32a69906f9e500e4103151bef02483045022100ddc7\
+             eb1193529a8d0e48cf24f536d5fbb5de3b67d2f56c98190ea8585d58a156022075\
+             e33981f1a7d78ce2915402d4b9b38b8d5311e0aef2e3ccf9284d2ce602968d0121\
+             021d0478acd223fb9b2ad7485f06f12914a1b7effc78390a08c50bfe53b3b24815\
+             062c1400",
+        )
+        .unwrap();
+        // Pay to the anchoring address once again.
+        let anchoring_out = funding_tx.0.output[1].clone();
+        funding_tx.0.output.push(anchoring_out);
+
+        let keys = vec![
+            "038b782f94d19f34536a96e12e0bad99e6f82c838fa16a4234572f5f132d95ba29",
+            "020ae2216f42575c4196864eda0252c75c61273065f691b32be9a99cb2a3c9b4d1",
+            "02536d5e1464b961562da57207e4a46edb7dade9b92aa29712ca8309c8aba5be5b",
+        ]
+        .iter()
+        .map(|h| PublicKey::from_hex(h).unwrap().0)
+        .collect::<Vec<_>>();
+
+        let redeem_script = RedeemScriptBuilder::with_public_keys(keys)
+            .to_script()
+            .unwrap();
+
+        let mut builder = BtcAnchoringTransactionBuilder::new(&redeem_script);
+        builder.additional_funds(funding_tx.clone()).unwrap();
+        builder.fee(1);
+        builder.payload(Height::zero(), funding_tx.object_hash());
+        let (tx, inputs) = builder.create().unwrap();
+
+        assert_eq!(inputs, vec![funding_tx.clone(), funding_tx.clone()]);
+        let outpoints =
+            tx.0.input
+                .iter()
+                .map(|txin| txin.previous_output.vout)
+                .collect::<Vec<_>>();
+        assert_eq!(outpoints, vec![1, 2]);
+        assert_eq!(tx.fee(&inputs), Some(20_000 - tx.0.output[0].value));
+
+        let mut builder = BtcAnchoringTransactionBuilder::new(&redeem_script);
+        assert_eq!(
+            builder
+                .additional_funding_output(funding_tx.clone(), 0)
+                .unwrap_err(),
+            BuilderError::UnsuitableFundingTx
+        );
+        builder.additional_funding_output(funding_tx, 2).unwrap();
+    }
+
     #[test]
     fn test_anchoring_transaction_builder_incorrect_prev_tx() {
         let funding_tx: Transaction = Transaction::from_hex(
@@ -14,7 +14,7 @@
 
 //! BTC anchoring configuration data types.
 
-pub use crate::proto::{AnchoringKeys, Config};
+pub use crate::proto::{AnchoringKeys, CatchUpPolicy, Config};
 
 use anyhow::ensure;
 use bitcoin::network::constants::Network;
@@ -23,7 +23,7 @@ use btc_transaction_utils::{
     p2wsh,
 };
 use exonum::{
-    crypto::PublicKey,
+    crypto::{Hash, PublicKey},
     helpers::{Height, ValidateInput},
 };
 
@@ -36,6 +36,11 @@ impl Default for Config {
             anchoring_keys: vec![],
             anchoring_interval: 5_000,
             transaction_fee: 10,
+            replace_by_fee: false,
+            pay_to_contract: false,
+            sweep_address: None,
+            paused: false,
+            catch_up_policy: CatchUpPolicy::default(),
         }
     }
 }
@@ -93,6 +98,17 @@ impl Config {
             .unwrap()
     }
 
+    /// Returns the redeem script which locks the anchoring output committed in the
+    /// pay-to-contract mode to the given commitment. If there is no commitment, returns
+    /// the actual redeem script.
+    pub fn committed_redeem_script(&self, commitment: Option<&Hash>) -> RedeemScript {
+        let redeem_script = self.redeem_script();
+        match commitment {
+            Some(commitment) => btc::contract_redeem_script(&redeem_script, commitment),
+            None => redeem_script,
+        }
+    }
+
     /// Computes the P2WSH output corresponding to the actual redeem script.
     pub fn anchoring_out_script(&self) -> bitcoin::Script {
         self.redeem_script().as_ref().to_v0_p2wsh()
@@ -141,6 +157,18 @@ impl ValidateInput for Config {
         RedeemScriptBuilder::with_public_keys(self.anchoring_keys.iter().map(|x| x.bitcoin_key.0))
             .quorum(self.byzantine_quorum())
             .to_script()?;
+
+        if let Some(sweep_address) = &self.sweep_address {
+            ensure!(
+                sweep_address.0.network == self.network,
+                "Sweep address should belong to the {:?} network.",
+                self.network
+            );
+            ensure!(
+                *sweep_address != self.anchoring_address(),
+                "Sweep address should differ from the anchoring address."
+            );
+        }
         Ok(())
     }
 }
@@ -244,6 +272,31 @@ mod tests {
                 },
                 "Transaction fee should be greater than",
             ),
+            (
+                Config {
+                    anchoring_keys: gen_anchoring_keys(bitcoin::Network::Regtest, 4),
+                    sweep_address: Some(
+                        "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+                            .parse()
+                            .unwrap(),
+                    ),
+                    ..Config::default()
+                },
+                "Sweep address should belong to the Testnet network",
+            ),
+            (
+                {
+                    let config = Config {
+                        anchoring_keys: gen_anchoring_keys(bitcoin::Network::Regtest, 4),
+                        ..Config::default()
+                    };
+                    Config {
+                        sweep_address: Some(config.anchoring_address()),
+                        ..config
+                    }
+                },
+                "Sweep address should differ from the anchoring address",
+            ),
         ];
 
         for (config, expected_err) in &test_cases {
@@ -128,6 +128,16 @@ pub struct SignInput {
     pub input_signature: btc::InputSignature,
 }
 
+/// Exonum message with signatures for all inputs of a new anchoring transaction.
+#[derive(Debug, Clone, PartialEq, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "self::service::SignInputs")]
+pub struct SignInputs {
+    /// Proposal transaction ID.
+    pub txid: Sha256d,
+    /// Signatures content in the order of the signed inputs.
+    pub input_signatures: Vec<btc::InputSignature>,
+}
+
 /// Exonum message with the unspent funding transaction.
 #[derive(Debug, Clone, PartialEq, ProtobufConvert, BinaryValue, ObjectHash)]
 #[protobuf_convert(source = "self::service::AddFunds")]
@@ -136,6 +146,105 @@ pub struct AddFunds {
     pub transaction: btc::Transaction,
 }
 
+/// Exonum message with a request to replace the latest anchoring transaction
+/// by the one with a higher fee.
+#[derive(Debug, Clone, PartialEq, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "self::service::BumpFee")]
+pub struct BumpFee {
+    /// Identifier of the replaced anchoring transaction.
+    pub txid: Sha256d,
+    /// New fee per virtual byte in satoshis.
+    pub fee: u64,
+}
+
+/// Exonum message with a report that the latest anchoring transaction is still
+/// unconfirmed in the Bitcoin network.
+#[derive(Debug, Clone, PartialEq, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "self::service::ReportUnconfirmed")]
+pub struct ReportUnconfirmed {
+    /// Identifier of the unconfirmed anchoring transaction.
+    pub txid: Sha256d,
+}
+
+/// Exonum message with a report that the anchoring transaction has been committed
+/// to the Bitcoin blockchain.
+#[derive(Debug, Clone, PartialEq, ProtobufConvert, BinaryValue, ObjectHash)]
+#[protobuf_convert(source = "self::service::ReportConfirmation")]
+pub struct ReportConfirmation {
+    /// Index of the anchoring transaction in the anchoring chain.
+    pub index: u64,
+    /// Identifier of the anchoring transaction.
+    pub txid: Sha256d,
+    /// Hash of the Bitcoin block which contains the transaction.
+    pub block_hash: Sha256d,
+    /// Height of this Bitcoin block.
+    pub block_height: u32,
+    /// Number of confirmations of the transaction observed by the node.
+    pub confirmations: u32,
+}
+
+/// Bitcoin confirmation status of the anchoring transaction accepted by the quorum
+/// of the anchoring nodes.
+#[derive(
+    Debug, Clone, PartialEq, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash,
+)]
+#[protobuf_convert(source = "self::service::BitcoinConfirmation")]
+pub struct BitcoinConfirmation {
+    /// Hash of the Bitcoin block which contains the transaction.
+    pub block_hash: Sha256d,
+    /// Height of this Bitcoin block.
+    pub block_height: u32,
+    /// Number of confirmations confirmed by the quorum of the anchoring nodes.
+    pub confirmations: u32,
+}
+
+/// Range of the anchoring heights skipped by the anchoring transaction in the catch-up mode.
+/// The blocks at these heights are still committed by the Merkle root of the anchored blocks
+/// in the payload of this transaction, unless they precede the latest `MAX_ANCHORED_BLOCKS`
+/// blocks up to the anchored height.
+#[derive(
+    Debug, Clone, PartialEq, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash,
+)]
+#[protobuf_convert(source = "self::service::SkippedHeights")]
+pub struct SkippedHeights {
+    /// The first skipped anchoring height.
+    pub from: u64,
+    /// The last skipped anchoring height.
+    pub to: u64,
+}
+
+/// Merkle root of the hashes of the blocks committed by the anchoring transaction proposal.
+#[derive(
+    Debug, Clone, PartialEq, Serialize, Deserialize, ProtobufConvert, BinaryValue, ObjectHash,
+)]
+#[protobuf_convert(source = "self::service::AnchoredBlocksRoot")]
+pub struct AnchoredBlocksRoot {
+    /// Height of the first committed block.
+    pub from: u64,
+    /// Height of the last committed block, which is anchored by the proposal.
+    pub to: u64,
+    /// Merkle root of the hashes of the committed blocks.
+    pub root: Hash,
+}
+
+/// Policy of anchoring the heights missed while the anchoring has been stalled,
+/// e.g. because of the insufficient funds or the pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatchUpPolicy {
+    /// Anchor every missed anchoring height one by one.
+    EveryInterval,
+    /// Skip the missed anchoring heights and anchor the latest anchoring height
+    /// relative to the current Exonum blockchain height.
+    LatestHeight,
+}
+
+impl Default for CatchUpPolicy {
+    fn default() -> Self {
+        CatchUpPolicy::EveryInterval
+    }
+}
+
 /// Consensus parameters in the BTC anchoring.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, BinaryValue, ObjectHash)]
 pub struct Config {
@@ -145,8 +254,26 @@ pub struct Config {
     pub anchoring_keys: Vec<AnchoringKeys>,
     /// Interval in blocks between anchored blocks.
     pub anchoring_interval: u64,
-    /// Fee per byte in satoshis.
+    /// Fee per virtual byte in satoshis.
     pub transaction_fee: u64,
+    /// Signal replaceability of the anchoring transactions according to BIP-125.
+    #[serde(default)]
+    pub replace_by_fee: bool,
+    /// Commit the anchoring payload by tweaking the anchoring keys (pay-to-contract)
+    /// instead of the `OP_RETURN` output.
+    #[serde(default)]
+    pub pay_to_contract: bool,
+    /// Address to which the anchoring funds are swept by the next anchoring transaction,
+    /// after which the anchoring stops.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sweep_address: Option<btc::Address>,
+    /// Halt the anchoring until this flag is cleared. No anchoring transactions are
+    /// proposed while the anchoring is paused.
+    #[serde(default)]
+    pub paused: bool,
+    /// Policy of anchoring the heights missed while the anchoring has been stalled.
+    #[serde(default)]
+    pub catch_up_policy: CatchUpPolicy,
 }
 
 impl ProtobufConvert for Config {
@@ -159,23 +286,49 @@ impl ProtobufConvert for Config {
         proto_struct.set_anchoring_keys(self.anchoring_keys.to_pb().into());
         proto_struct.set_anchoring_interval(self.anchoring_interval.to_pb());
         proto_struct.set_transaction_fee(self.transaction_fee.to_pb());
+        proto_struct.set_replace_by_fee(self.replace_by_fee);
+        proto_struct.set_pay_to_contract(self.pay_to_contract);
+        if let Some(sweep_address) = &self.sweep_address {
+            proto_struct.set_sweep_address(sweep_address.to_string());
+        }
+        proto_struct.set_paused(self.paused);
+        proto_struct.set_catch_up_policy(match self.catch_up_policy {
+            CatchUpPolicy::EveryInterval => self::service::CatchUpPolicy::EVERY_INTERVAL,
+            CatchUpPolicy::LatestHeight => self::service::CatchUpPolicy::LATEST_HEIGHT,
+        });
         proto_struct
     }
 
     fn from_pb(mut pb: Self::ProtoStruct) -> anyhow::Result<Self> {
         let network = bitcoin::Network::from_magic(pb.get_network())
             .ok_or_else(|| anyhow!("Unknown Bitcoin network"))?;
+        let sweep_address = match pb.get_sweep_address() {
+            "" => None,
+            address => Some(address.parse()?),
+        };
 
         Ok(Self {
             network,
             anchoring_keys: ProtobufConvert::from_pb(pb.take_anchoring_keys().into_vec())?,
             anchoring_interval: ProtobufConvert::from_pb(pb.get_anchoring_interval())?,
             transaction_fee: ProtobufConvert::from_pb(pb.get_transaction_fee())?,
+            replace_by_fee: pb.get_replace_by_fee(),
+            pay_to_contract: pb.get_pay_to_contract(),
+            sweep_address,
+            paused: pb.get_paused(),
+            catch_up_policy: match pb.get_catch_up_policy() {
+                self::service::CatchUpPolicy::EVERY_INTERVAL => CatchUpPolicy::EveryInterval,
+                self::service::CatchUpPolicy::LATEST_HEIGHT => CatchUpPolicy::LatestHeight,
+            },
         })
     }
 }
 
 impl_serde_hex_for_binary_value! { SignInput }
+impl_serde_hex_for_binary_value! { SignInputs }
+impl_serde_hex_for_binary_value! { BumpFee }
+impl_serde_hex_for_binary_value! { ReportUnconfirmed }
+impl_serde_hex_for_binary_value! { ReportConfirmation }
 
 impl BinaryValue for btc::Sha256d {
     fn to_bytes(&self) -> Vec<u8> {
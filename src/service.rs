@@ -51,6 +51,12 @@ impl Service for BtcAnchoringService {
         Ok(())
     }
 
+    fn after_transactions(&self, context: ExecutionContext<'_>) -> Result<(), ExecutionError> {
+        let core_schema = context.data().for_core();
+        Schema::new(context.service_data()).update_proposal_blocks_root(core_schema);
+        Ok(())
+    }
+
     fn wire_api(&self, builder: &mut ServiceApiBuilder) {
         api::wire(builder);
     }
@@ -0,0 +1,509 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bitcoin relay, which combines several relays to tolerate a lying or lagging backend.
+
+use async_trait::async_trait;
+use futures::future::{join_all, Future};
+use serde_derive::{Deserialize, Serialize};
+use thiserror::Error;
+
+use std::{
+    cmp::{self, Ordering},
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{BitcoinRelay, TransactionBlock, TransactionStatus};
+use crate::btc;
+
+/// Default delay before the failed relay is queried again.
+pub const DEFAULT_RELAY_BACKOFF: Duration = Duration::from_secs(5);
+/// Default upper bound of the delay before the failed relay is queried again.
+pub const DEFAULT_MAX_RELAY_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Policy which determines the transaction status from the responses of several relays.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgreementPolicy {
+    /// Relays are queried in turn, and the first successful response is used.
+    FirstSuccess,
+    /// Status should be reported by the majority of the configured relays. If the majority
+    /// reports the committed transaction, the least number of confirmations is used.
+    Majority,
+    /// The least advanced status among the successful responses is used, i.e. the transaction
+    /// is considered committed only if every responding relay knows it as committed.
+    MinConfirmations,
+}
+
+impl Default for AgreementPolicy {
+    fn default() -> Self {
+        AgreementPolicy::FirstSuccess
+    }
+}
+
+impl FromStr for AgreementPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first-success" => Ok(AgreementPolicy::FirstSuccess),
+            "majority" => Ok(AgreementPolicy::Majority),
+            "min-confirmations" => Ok(AgreementPolicy::MinConfirmations),
+            other => Err(anyhow::anyhow!(
+                "Unknown agreement policy `{}`, expected one of `first-success`, \
+                 `majority` or `min-confirmations`",
+                other
+            )),
+        }
+    }
+}
+
+/// Errors that occur in the composite relay.
+#[derive(Debug, Error)]
+pub enum CompositeRelayError {
+    /// All relays are backing off after the recent failures.
+    #[error("All Bitcoin relays are unavailable after the recent failures")]
+    NoAvailableRelays,
+    /// Every queried relay has failed.
+    #[error("All Bitcoin relays failed: {}", format_failures(.0))]
+    AllRelaysFailed(Vec<(String, anyhow::Error)>),
+    /// Relays have not reached the agreement on the transaction status.
+    #[error(
+        "Bitcoin relays disagree on the transaction status, \
+         {agreed} of {required} required responses agree"
+    )]
+    NoAgreement {
+        /// Largest number of the relays which have reported the same status.
+        agreed: usize,
+        /// Number of the relays which should report the same status.
+        required: usize,
+    },
+}
+
+fn format_failures(failures: &[(String, anyhow::Error)]) -> String {
+    failures
+        .iter()
+        .map(|(name, e)| format!("{}: {}", name, e))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Health of the relay in the composite relay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelayHealth {
+    /// Name of the relay.
+    pub name: String,
+    /// Number of the failed requests since the last successful one.
+    pub consecutive_failures: u32,
+    /// The relay is not queried until this moment.
+    pub backoff_until: Option<Instant>,
+}
+
+impl RelayHealth {
+    fn is_available(&self, now: Instant) -> bool {
+        self.backoff_until.map_or(true, |until| until <= now)
+    }
+}
+
+/// Error of the relay, which has rejected the request, see `BitcoinRelay::is_rejection`.
+#[derive(Debug, Error)]
+#[error("{0}")]
+struct Rejection(anyhow::Error);
+
+/// Relay with the type erased error.
+struct ErasedRelay<R>(R);
+
+#[async_trait]
+impl<R> BitcoinRelay for ErasedRelay<R>
+where
+    R: BitcoinRelay + Send + Sync + 'static,
+    R::Error: Into<anyhow::Error>,
+{
+    type Error = anyhow::Error;
+
+    fn is_rejection(error: &Self::Error) -> bool {
+        is_rejection(error)
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &btc::Transaction,
+    ) -> Result<btc::Sha256d, Self::Error> {
+        self.0
+            .send_transaction(transaction)
+            .await
+            .map_err(erase_error::<R>)
+    }
+
+    async fn transaction_status(&self, id: btc::Sha256d) -> Result<TransactionStatus, Self::Error> {
+        self.0
+            .transaction_status(id)
+            .await
+            .map_err(erase_error::<R>)
+    }
+
+    async fn transaction_block(
+        &self,
+        id: btc::Sha256d,
+    ) -> Result<Option<TransactionBlock>, Self::Error> {
+        self.0.transaction_block(id).await.map_err(erase_error::<R>)
+    }
+}
+
+/// Erases the type of the relay error keeping the information whether it is a rejection.
+fn erase_error<R>(error: R::Error) -> anyhow::Error
+where
+    R: BitcoinRelay,
+    R::Error: Into<anyhow::Error>,
+{
+    if R::is_rejection(&error) {
+        Rejection(error.into()).into()
+    } else {
+        error.into()
+    }
+}
+
+/// Checks if the erased relay error is a rejection.
+fn is_rejection(error: &anyhow::Error) -> bool {
+    error.is::<Rejection>()
+}
+
+type DynRelay = Box<dyn BitcoinRelay<Error = anyhow::Error> + Send + Sync>;
+
+struct RelayEntry {
+    relay: DynRelay,
+    health: Mutex<RelayHealth>,
+}
+
+/// Bitcoin relay, which broadcasts transactions to all inner relays and determines
+/// the transaction status by the configured [agreement policy](enum.AgreementPolicy.html).
+///
+/// The relay which has failed is not queried for a while, the delay is doubled after each
+/// subsequent failure up to the maximum one and is reset after the successful response.
+/// The rejected requests (see [`BitcoinRelay::is_rejection`]) are not considered failures.
+///
+/// [`BitcoinRelay::is_rejection`]: trait.BitcoinRelay.html#method.is_rejection
+pub struct CompositeRelay {
+    relays: Vec<RelayEntry>,
+    policy: AgreementPolicy,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl fmt::Debug for CompositeRelay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompositeRelay")
+            .field("relays", &self.health())
+            .field("policy", &self.policy)
+            .field("backoff", &self.backoff)
+            .field("max_backoff", &self.max_backoff)
+            .finish()
+    }
+}
+
+impl CompositeRelay {
+    /// Creates an empty composite relay with the given agreement policy.
+    pub fn new(policy: AgreementPolicy) -> Self {
+        Self {
+            relays: Vec::new(),
+            policy,
+            backoff: DEFAULT_RELAY_BACKOFF,
+            max_backoff: DEFAULT_MAX_RELAY_BACKOFF,
+        }
+    }
+
+    /// Adds the relay with the given name, which is used in the logs and errors.
+    pub fn with_relay<R>(mut self, name: impl Into<String>, relay: R) -> Self
+    where
+        R: BitcoinRelay + Send + Sync + 'static,
+        R::Error: Into<anyhow::Error>,
+    {
+        self.relays.push(RelayEntry {
+            relay: Box::new(ErasedRelay(relay)),
+            health: Mutex::new(RelayHealth {
+                name: name.into(),
+                consecutive_failures: 0,
+                backoff_until: None,
+            }),
+        });
+        self
+    }
+
+    /// Sets the delay after the first failure of the relay and the upper bound of the delay.
+    pub fn with_backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Returns the agreement policy of this relay.
+    pub fn policy(&self) -> AgreementPolicy {
+        self.policy
+    }
+
+    /// Returns the current health of the inner relays.
+    pub fn health(&self) -> Vec<RelayHealth> {
+        self.relays
+            .iter()
+            .map(|entry| entry.health.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Returns the relays which are not backing off at the moment.
+    fn available_relays(&self) -> Result<Vec<&RelayEntry>, CompositeRelayError> {
+        let now = Instant::now();
+        let relays = self
+            .relays
+            .iter()
+            .filter(|entry| entry.health.lock().unwrap().is_available(now))
+            .collect::<Vec<_>>();
+        if relays.is_empty() {
+            Err(CompositeRelayError::NoAvailableRelays)
+        } else {
+            Ok(relays)
+        }
+    }
+
+    /// Updates the health of the relay according to the result of the request.
+    fn track<T>(
+        &self,
+        entry: &RelayEntry,
+        result: Result<T, anyhow::Error>,
+    ) -> Result<T, (String, anyhow::Error)> {
+        let mut health = entry.health.lock().unwrap();
+        match result {
+            Ok(value) => {
+                health.consecutive_failures = 0;
+                health.backoff_until = None;
+                Ok(value)
+            }
+            // The relay which has rejected the request is available, so the rejection
+            // does not affect its health.
+            Err(e) if is_rejection(&e) => {
+                log::warn!("Bitcoin relay {} rejected the request. {}", health.name, e);
+                Err((health.name.clone(), e))
+            }
+            Err(e) => {
+                health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+                let exponent = cmp::min(health.consecutive_failures - 1, 16);
+                let backoff = cmp::min(self.backoff * 2_u32.pow(exponent), self.max_backoff);
+                health.backoff_until = Some(Instant::now() + backoff);
+                log::warn!(
+                    "Bitcoin relay {} failed, it will not be queried for {:?}. {}",
+                    health.name,
+                    backoff,
+                    e
+                );
+                Err((health.name.clone(), e))
+            }
+        }
+    }
+
+    /// Sends the request to all available relays concurrently and returns the successful
+    /// responses.
+    async fn query_all<'a, T, F, R>(&'a self, request: F) -> Result<Vec<T>, CompositeRelayError>
+    where
+        F: Fn(&'a DynRelay) -> R,
+        R: Future<Output = Result<T, anyhow::Error>>,
+    {
+        let relays = self.available_relays()?;
+        let responses = join_all(relays.iter().map(|&entry| request(&entry.relay))).await;
+
+        let mut values = Vec::new();
+        let mut failures = Vec::new();
+        for (entry, response) in relays.into_iter().zip(responses) {
+            match self.track(entry, response) {
+                Ok(value) => values.push(value),
+                Err(failure) => failures.push(failure),
+            }
+        }
+
+        if values.is_empty() {
+            Err(CompositeRelayError::AllRelaysFailed(failures))
+        } else {
+            Ok(values)
+        }
+    }
+
+    /// Sends the request to the available relays in turn and returns the first successful
+    /// response.
+    async fn query_first<'a, T, F, R>(&'a self, request: F) -> Result<T, CompositeRelayError>
+    where
+        F: Fn(&'a DynRelay) -> R,
+        R: Future<Output = Result<T, anyhow::Error>>,
+    {
+        let mut failures = Vec::new();
+        for entry in self.available_relays()? {
+            let response = request(&entry.relay).await;
+            match self.track(entry, response) {
+                Ok(value) => return Ok(value),
+                Err(failure) => failures.push(failure),
+            }
+        }
+        Err(CompositeRelayError::AllRelaysFailed(failures))
+    }
+
+    fn majority_status(
+        &self,
+        statuses: Vec<TransactionStatus>,
+    ) -> Result<TransactionStatus, CompositeRelayError> {
+        // The majority is counted among all configured relays, so the unavailable ones
+        // cannot be outvoted by a single responding relay.
+        let required = self.relays.len() / 2 + 1;
+        let mut agreed = 0;
+        for class in &[
+            TransactionStatus::Unknown,
+            TransactionStatus::Mempool,
+            TransactionStatus::Committed(0),
+        ] {
+            let same = statuses
+                .iter()
+                .filter(|status| status_class(**status) == status_class(*class))
+                .copied()
+                .collect::<Vec<_>>();
+            if same.len() >= required {
+                return Ok(same.into_iter().min_by(compare_statuses).unwrap());
+            }
+            agreed = cmp::max(agreed, same.len());
+        }
+        Err(CompositeRelayError::NoAgreement { agreed, required })
+    }
+
+    fn majority_block(
+        &self,
+        blocks: Vec<Option<TransactionBlock>>,
+    ) -> Result<Option<TransactionBlock>, CompositeRelayError> {
+        let required = self.relays.len() / 2 + 1;
+        let mut agreed = 0;
+        for block in &blocks {
+            let same = blocks.iter().filter(|other| *other == block).count();
+            if same >= required {
+                return Ok(*block);
+            }
+            agreed = cmp::max(agreed, same);
+        }
+        Err(CompositeRelayError::NoAgreement { agreed, required })
+    }
+
+    fn min_confirmations_block(
+        blocks: Vec<Option<TransactionBlock>>,
+    ) -> Result<Option<TransactionBlock>, CompositeRelayError> {
+        // The transaction is considered committed only if every responding relay knows it
+        // as committed, and they should agree on the block containing it.
+        if blocks.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+        let required = blocks.len();
+        let agreed = blocks.iter().filter(|block| **block == blocks[0]).count();
+        if agreed == required {
+            Ok(blocks[0])
+        } else {
+            Err(CompositeRelayError::NoAgreement { agreed, required })
+        }
+    }
+}
+
+/// Returns the discriminant of the status without the number of confirmations.
+fn status_class(status: TransactionStatus) -> u8 {
+    match status {
+        TransactionStatus::Unknown => 0,
+        TransactionStatus::Mempool => 1,
+        TransactionStatus::Committed(_) => 2,
+    }
+}
+
+/// Orders statuses from the least to the most advanced one.
+fn compare_statuses(lhs: &TransactionStatus, rhs: &TransactionStatus) -> Ordering {
+    status_class(*lhs)
+        .cmp(&status_class(*rhs))
+        .then_with(|| lhs.confirmations().cmp(&rhs.confirmations()))
+}
+
+#[async_trait]
+impl BitcoinRelay for CompositeRelay {
+    type Error = CompositeRelayError;
+
+    /// The request is considered rejected if every queried relay has rejected it.
+    fn is_rejection(error: &Self::Error) -> bool {
+        match error {
+            CompositeRelayError::AllRelaysFailed(failures) => {
+                !failures.is_empty() && failures.iter().all(|(_, e)| is_rejection(e))
+            }
+            _ => false,
+        }
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &btc::Transaction,
+    ) -> Result<btc::Sha256d, Self::Error> {
+        let relays = self.available_relays()?;
+        let responses = join_all(
+            relays
+                .iter()
+                .map(|entry| entry.relay.send_transaction(transaction)),
+        )
+        .await;
+
+        let mut txid = None;
+        let mut failures = Vec::new();
+        for (entry, response) in relays.into_iter().zip(responses) {
+            match self.track(entry, response) {
+                Ok(id) => txid = txid.or(Some(id)),
+                Err(failure) => failures.push(failure),
+            }
+        }
+        // The transaction is considered sent if at least one relay has accepted it.
+        txid.ok_or(CompositeRelayError::AllRelaysFailed(failures))
+    }
+
+    async fn transaction_status(&self, id: btc::Sha256d) -> Result<TransactionStatus, Self::Error> {
+        match self.policy {
+            AgreementPolicy::FirstSuccess => {
+                self.query_first(|relay| relay.transaction_status(id)).await
+            }
+            AgreementPolicy::Majority => {
+                let statuses = self.query_all(|relay| relay.transaction_status(id)).await?;
+                self.majority_status(statuses)
+            }
+            AgreementPolicy::MinConfirmations => {
+                let statuses = self.query_all(|relay| relay.transaction_status(id)).await?;
+                Ok(statuses.into_iter().min_by(compare_statuses).unwrap())
+            }
+        }
+    }
+
+    /// Returns the block determined by the agreement policy in the same way as the status.
+    async fn transaction_block(
+        &self,
+        id: btc::Sha256d,
+    ) -> Result<Option<TransactionBlock>, Self::Error> {
+        match self.policy {
+            AgreementPolicy::FirstSuccess => {
+                self.query_first(|relay| relay.transaction_block(id)).await
+            }
+            AgreementPolicy::Majority => {
+                let blocks = self.query_all(|relay| relay.transaction_block(id)).await?;
+                self.majority_block(blocks)
+            }
+            AgreementPolicy::MinConfirmations => {
+                let blocks = self.query_all(|relay| relay.transaction_block(id)).await?;
+                Self::min_confirmations_block(blocks)
+            }
+        }
+    }
+}
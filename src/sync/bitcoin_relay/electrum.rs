@@ -0,0 +1,320 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bitcoin relay speaking the [Electrum protocol], which is served by ElectrumX,
+//! electrs and similar servers.
+//!
+//! Each request is sent over a separate TCP connection, optionally wrapped into TLS.
+//!
+//! [Electrum protocol]: https://electrumx.readthedocs.io/en/latest/protocol-methods.html
+
+use async_trait::async_trait;
+use bitcoin::hash_types::{BlockHash, Txid};
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use super::{BitcoinRelay, TransactionBlock, TransactionStatus};
+use crate::btc;
+
+/// Errors that occur when communicating with the Electrum server.
+#[derive(Debug, Error)]
+pub enum ElectrumError {
+    /// Unable to communicate with the Electrum server.
+    #[error("Unable to communicate with the Electrum server: {0}")]
+    Io(#[from] std::io::Error),
+    /// TLS connection to the Electrum server has failed.
+    #[error("TLS connection to the Electrum server failed: {0}")]
+    Tls(#[from] native_tls::Error),
+    /// Electrum server has responded with an error.
+    #[error("Electrum server responded with error {code}: {message}")]
+    Rpc {
+        /// Error code.
+        code: i64,
+        /// Error message.
+        message: String,
+    },
+    /// Electrum server has responded with the unexpected content.
+    #[error("Unexpected response of the Electrum server: {0}")]
+    InvalidResponse(String),
+}
+
+impl ElectrumError {
+    /// Error code used by ElectrumX and similar servers for the errors of the underlying
+    /// Bitcoin node.
+    const DAEMON_ERROR: i64 = 2;
+    /// Error code of the Bitcoin node for the unknown transactions, which is passed
+    /// through as is by electrs.
+    const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+    /// Message of the Bitcoin node for the unknown transactions.
+    const UNKNOWN_TRANSACTION_MESSAGE: &'static str = "No such mempool or blockchain transaction";
+
+    /// Checks if the error means that the requested transaction is unknown to the server.
+    fn is_unknown_transaction(&self) -> bool {
+        match self {
+            ElectrumError::Rpc { code, .. } if *code == Self::RPC_INVALID_ADDRESS_OR_KEY => true,
+            ElectrumError::Rpc { code, message } if *code == Self::DAEMON_ERROR => {
+                message.contains(Self::UNKNOWN_TRANSACTION_MESSAGE)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Verbose transaction returned by the `blockchain.transaction.get` method.
+#[derive(Debug, Deserialize)]
+struct VerboseTransaction {
+    #[serde(default)]
+    blockhash: Option<String>,
+    /// Number of confirmations, which is absent or zero for the transactions in the mempool.
+    #[serde(default)]
+    confirmations: u32,
+}
+
+/// Merkle proof returned by the `blockchain.transaction.get_merkle` method.
+#[derive(Debug, Deserialize)]
+struct MerkleProof {
+    block_height: u32,
+}
+
+/// Header notification returned by the `blockchain.headers.subscribe` method.
+#[derive(Debug, Deserialize)]
+struct HeaderNotification {
+    height: u32,
+}
+
+/// Bitcoin relay, which uses the Electrum server instead of the Bitcoin node RPC.
+#[derive(Debug, Clone)]
+pub struct ElectrumRelay {
+    /// Address of the server in the form `{host}:{port}`.
+    address: String,
+    /// Whether the connection should be wrapped into TLS.
+    tls: bool,
+    /// Identifier of the next request.
+    next_id: Arc<AtomicU64>,
+}
+
+impl ElectrumRelay {
+    /// Creates a new relay, which connects to the Electrum server with the given address
+    /// over plain TCP. The address should be in the form `{host}:{port}`.
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            tls: false,
+            next_id: Arc::default(),
+        }
+    }
+
+    /// Creates a new relay, which connects to the Electrum server with the given address
+    /// over TLS. The certificate of the server is checked against its host name.
+    pub fn with_tls(address: impl Into<String>) -> Self {
+        Self {
+            tls: true,
+            ..Self::new(address)
+        }
+    }
+
+    /// Returns the verbose transaction, or `None` if the transaction is unknown.
+    async fn verbose_transaction(
+        &self,
+        id: btc::Sha256d,
+    ) -> Result<Option<VerboseTransaction>, ElectrumError> {
+        match self
+            .call("blockchain.transaction.get", json!([id.to_string(), true]))
+            .await
+        {
+            Ok(transaction) => Ok(Some(transaction)),
+            // Electrum servers report the unknown transactions by the error response.
+            Err(e) if e.is_unknown_transaction() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the height of the block with the given number of confirmations, which
+    /// contains the transaction with the given identifier.
+    async fn transaction_height(
+        &self,
+        id: btc::Sha256d,
+        confirmations: u32,
+    ) -> Result<u32, ElectrumError> {
+        // The verbose transaction has no block height, so it is calculated from
+        // the current height of the blockchain.
+        let tip: HeaderNotification = self.call("blockchain.headers.subscribe", json!([])).await?;
+        let height = (tip.height + 1).saturating_sub(confirmations);
+        // The Merkle proof makes sure that the transaction is actually included
+        // into the block at the calculated height.
+        let proof: MerkleProof = self
+            .call(
+                "blockchain.transaction.get_merkle",
+                json!([id.to_string(), height]),
+            )
+            .await?;
+        if proof.block_height != height {
+            return Err(ElectrumError::InvalidResponse(format!(
+                "Transaction {} is expected at the height {}, but the Merkle proof is for the height {}",
+                id, height, proof.block_height
+            )));
+        }
+        Ok(height)
+    }
+
+    /// Calls the given method of the Electrum server and returns its result.
+    async fn call<R>(&self, method: &str, params: Value) -> Result<R, ElectrumError>
+    where
+        R: DeserializeOwned,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+        request.push('\n');
+
+        let stream = TcpStream::connect(&self.address).await?;
+        let response = if self.tls {
+            let host = self.address.rsplitn(2, ':').last().unwrap_or(&self.address);
+            let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+            let stream = connector.connect(host, stream).await?;
+            exchange(stream, &request, id).await?
+        } else {
+            exchange(stream, &request, id).await?
+        };
+
+        if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+            return Err(ElectrumError::Rpc {
+                code: error
+                    .get("code")
+                    .and_then(Value::as_i64)
+                    .unwrap_or_default(),
+                message: error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .map_or_else(|| error.to_string(), str::to_owned),
+            });
+        }
+        let result = response.get("result").cloned().unwrap_or(Value::Null);
+        serde_json::from_value(result.clone())
+            .map_err(|_| ElectrumError::InvalidResponse(result.to_string()))
+    }
+}
+
+/// Sends the request over the given stream and reads the response with the given identifier.
+async fn exchange<S>(stream: S, request: &str, id: u64) -> Result<Value, ElectrumError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    stream.get_mut().write_all(request.as_bytes()).await?;
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 {
+            return Err(ElectrumError::InvalidResponse(
+                "Connection has been closed by the server".to_owned(),
+            ));
+        }
+        let response = serde_json::from_str::<Value>(&line)
+            .map_err(|_| ElectrumError::InvalidResponse(line.clone()))?;
+        // Skip the notifications, which have no identifier.
+        if response.get("id").and_then(Value::as_u64) == Some(id) {
+            return Ok(response);
+        }
+    }
+}
+
+#[async_trait]
+impl BitcoinRelay for ElectrumRelay {
+    type Error = ElectrumError;
+
+    fn is_rejection(error: &Self::Error) -> bool {
+        // The server has responded, so it is available.
+        matches!(error, ElectrumError::Rpc { .. })
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &btc::Transaction,
+    ) -> Result<btc::Sha256d, Self::Error> {
+        let txid: String = self
+            .call(
+                "blockchain.transaction.broadcast",
+                json!([transaction.to_string()]),
+            )
+            .await?;
+        txid.parse::<Txid>()
+            .map(btc::Sha256d::from)
+            .map_err(|_| ElectrumError::InvalidResponse(txid))
+    }
+
+    async fn transaction_status(&self, id: btc::Sha256d) -> Result<TransactionStatus, Self::Error> {
+        let status = match self.verbose_transaction(id).await? {
+            Some(transaction) if transaction.confirmations > 0 => {
+                TransactionStatus::Committed(transaction.confirmations)
+            }
+            Some(_) => TransactionStatus::Mempool,
+            None => TransactionStatus::Unknown,
+        };
+        Ok(status)
+    }
+
+    async fn transaction_block(
+        &self,
+        id: btc::Sha256d,
+    ) -> Result<Option<TransactionBlock>, Self::Error> {
+        let mut retried = false;
+        loop {
+            let (block_hash, confirmations) = match self.verbose_transaction(id).await? {
+                Some(VerboseTransaction {
+                    blockhash: Some(block_hash),
+                    confirmations,
+                }) if confirmations > 0 => (block_hash, confirmations),
+                _ => return Ok(None),
+            };
+            let hash = block_hash
+                .parse::<BlockHash>()
+                .map_err(|_| ElectrumError::InvalidResponse(block_hash))?;
+            match self.transaction_height(id, confirmations).await {
+                Ok(height) => {
+                    return Ok(Some(TransactionBlock {
+                        hash: btc::Sha256d(hash.into()),
+                        height,
+                    }))
+                }
+                // A new block may have been mined between the requests of the transaction
+                // and of the current height, so the calculated height is off. In this case
+                // the transaction is requested once again.
+                Err(ElectrumError::Rpc { .. }) | Err(ElectrumError::InvalidResponse(_))
+                    if !retried =>
+                {
+                    retried = true;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
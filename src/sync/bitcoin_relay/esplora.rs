@@ -0,0 +1,202 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bitcoin relay backed by the [Esplora] HTTP API.
+//!
+//! [Esplora]: https://github.com/Blockstream/esplora/blob/master/API.md
+
+use async_trait::async_trait;
+use bitcoin::hash_types::{BlockHash, Txid};
+use reqwest::StatusCode;
+use serde_derive::Deserialize;
+use thiserror::Error;
+
+use super::{BitcoinRelay, TransactionBlock, TransactionStatus};
+use crate::btc;
+
+/// Errors that occur when communicating with the Esplora API.
+#[derive(Debug, Error)]
+pub enum EsploraError {
+    /// HTTP request has failed.
+    #[error("HTTP request to the Esplora API failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// Esplora API has responded with an error status.
+    #[error("Esplora API responded with {status}: {message}")]
+    Api {
+        /// HTTP status of the response.
+        status: StatusCode,
+        /// Error message in the response body.
+        message: String,
+    },
+    /// Esplora API has responded with the unexpected content.
+    #[error("Unexpected response of the Esplora API: {0}")]
+    InvalidResponse(String),
+}
+
+/// Status of the transaction returned by the `GET /tx/:txid/status` endpoint.
+#[derive(Debug, Deserialize)]
+struct EsploraTxStatus {
+    confirmed: bool,
+    #[serde(default)]
+    block_height: Option<u32>,
+    #[serde(default)]
+    block_hash: Option<String>,
+}
+
+/// Bitcoin relay, which uses the Esplora HTTP API instead of the Bitcoin node RPC.
+/// Unlike the `bitcoind` RPC, it does not require the transaction index to look up
+/// the arbitrary transactions.
+#[derive(Debug, Clone)]
+pub struct EsploraRelay {
+    /// Base URL of the API, e.g. `https://blockstream.info/testnet/api`.
+    base_url: String,
+    /// Underlying HTTP client.
+    client: reqwest::Client,
+}
+
+impl EsploraRelay {
+    /// Creates a new relay with the specified base URL of the Esplora API.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let mut base_url = base_url.into();
+        while base_url.ends_with('/') {
+            base_url.pop();
+        }
+
+        Self {
+            base_url,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn endpoint(&self, name: &str) -> String {
+        format!("{}/{}", self.base_url, name)
+    }
+
+    /// Returns the status of the transaction, or `None` if the transaction is unknown.
+    async fn tx_status(&self, id: btc::Sha256d) -> Result<Option<EsploraTxStatus>, EsploraError> {
+        let body = match self.get(&format!("tx/{}/status", id)).await? {
+            Some(body) => body,
+            None => return Ok(None),
+        };
+        serde_json::from_str(&body)
+            .map(Some)
+            .map_err(|_| EsploraError::InvalidResponse(body))
+    }
+
+    /// Returns the body of the successful response, or `None` if the requested object
+    /// is not found.
+    async fn handle_response(response: reqwest::Response) -> Result<Option<String>, EsploraError> {
+        let status = response.status();
+        let body = response.text().await?;
+        if status.is_success() {
+            Ok(Some(body))
+        } else if status == StatusCode::NOT_FOUND {
+            Ok(None)
+        } else {
+            Err(EsploraError::Api {
+                status,
+                message: body,
+            })
+        }
+    }
+
+    async fn get(&self, name: &str) -> Result<Option<String>, EsploraError> {
+        let response = self.client.get(&self.endpoint(name)).send().await?;
+        Self::handle_response(response).await
+    }
+
+    /// Returns the height of the latest block in the Bitcoin blockchain.
+    async fn tip_height(&self) -> Result<u32, EsploraError> {
+        let body = self.get("blocks/tip/height").await?.ok_or_else(|| {
+            EsploraError::InvalidResponse("The latest block is not found".to_owned())
+        })?;
+        body.trim()
+            .parse()
+            .map_err(|_| EsploraError::InvalidResponse(body))
+    }
+}
+
+#[async_trait]
+impl BitcoinRelay for EsploraRelay {
+    type Error = EsploraError;
+
+    fn is_rejection(error: &Self::Error) -> bool {
+        match error {
+            EsploraError::Api { status, .. } => *status == StatusCode::BAD_REQUEST,
+            _ => false,
+        }
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &btc::Transaction,
+    ) -> Result<btc::Sha256d, Self::Error> {
+        let response = self
+            .client
+            .post(&self.endpoint("tx"))
+            .body(transaction.to_string())
+            .send()
+            .await?;
+        let body = Self::handle_response(response)
+            .await?
+            .ok_or_else(|| EsploraError::InvalidResponse("Endpoint is not found".to_owned()))?;
+
+        body.trim()
+            .parse::<Txid>()
+            .map(btc::Sha256d::from)
+            .map_err(|_| EsploraError::InvalidResponse(body))
+    }
+
+    async fn transaction_status(&self, id: btc::Sha256d) -> Result<TransactionStatus, Self::Error> {
+        let status = match self.tx_status(id).await? {
+            Some(status) => status,
+            None => return Ok(TransactionStatus::Unknown),
+        };
+
+        match (status.confirmed, status.block_height) {
+            (false, _) => Ok(TransactionStatus::Mempool),
+            (true, Some(block_height)) => {
+                let tip_height = self.tip_height().await?;
+                // The block with the transaction is also counted as a confirmation.
+                let confirmations = tip_height.saturating_sub(block_height) + 1;
+                Ok(TransactionStatus::Committed(confirmations))
+            }
+            (true, None) => Err(EsploraError::InvalidResponse(format!(
+                "Confirmed transaction {} has no block height",
+                id
+            ))),
+        }
+    }
+
+    async fn transaction_block(
+        &self,
+        id: btc::Sha256d,
+    ) -> Result<Option<TransactionBlock>, Self::Error> {
+        let (block_hash, height) = match self.tx_status(id).await? {
+            Some(EsploraTxStatus {
+                confirmed: true,
+                block_hash: Some(block_hash),
+                block_height: Some(height),
+            }) => (block_hash, height),
+            _ => return Ok(None),
+        };
+        let hash = block_hash
+            .parse::<BlockHash>()
+            .map_err(|_| EsploraError::InvalidResponse(block_hash))?;
+        Ok(Some(TransactionBlock {
+            hash: btc::Sha256d(hash.into()),
+            height,
+        }))
+    }
+}
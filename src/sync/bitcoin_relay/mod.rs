@@ -14,12 +14,25 @@
 
 //! Collections of helpers for synchronization with the Bitcoin network.
 
+pub use self::{
+    composite::{
+        AgreementPolicy, CompositeRelay, CompositeRelayError, RelayHealth,
+        DEFAULT_MAX_RELAY_BACKOFF, DEFAULT_RELAY_BACKOFF,
+    },
+    electrum::{ElectrumError, ElectrumRelay},
+    esplora::{EsploraError, EsploraRelay},
+};
+
 use async_trait::async_trait;
 use bitcoincore_rpc::RpcApi;
 use jsonrpc::Error as JsonRpcError;
 
 use crate::btc;
 
+mod composite;
+mod electrum;
+mod esplora;
+
 /// Status of the transaction in the Bitcoin network.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TransactionStatus {
@@ -48,11 +61,29 @@ impl TransactionStatus {
     }
 }
 
+/// Bitcoin block which contains the transaction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TransactionBlock {
+    /// Hash of the block.
+    pub hash: btc::Sha256d,
+    /// Height of the block.
+    pub height: u32,
+}
+
 /// Describes communication with the Bitcoin network node.
 #[async_trait]
 pub trait BitcoinRelay {
     /// Error type for the current Bitcoin relay implementation.
     type Error;
+    /// Checks if the error means that the relay has rejected the request, for example
+    /// the invalid transaction, rather than that the relay is unavailable. By default
+    /// no errors are considered rejections.
+    fn is_rejection(_error: &Self::Error) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
     /// Sends a raw transaction to the Bitcoin network node.
     async fn send_transaction(
         &self,
@@ -60,12 +91,25 @@ pub trait BitcoinRelay {
     ) -> Result<btc::Sha256d, Self::Error>;
     /// Gets status for the transaction with the specified identifier.
     async fn transaction_status(&self, id: btc::Sha256d) -> Result<TransactionStatus, Self::Error>;
+    /// Gets the block which contains the transaction with the specified identifier.
+    /// Returns `None` if the transaction is not committed or the relay cannot determine
+    /// the block, which is the default behavior.
+    async fn transaction_block(
+        &self,
+        _id: btc::Sha256d,
+    ) -> Result<Option<TransactionBlock>, Self::Error> {
+        Ok(None)
+    }
 }
 
 #[async_trait]
 impl BitcoinRelay for bitcoincore_rpc::Client {
     type Error = bitcoincore_rpc::Error;
 
+    fn is_rejection(error: &Self::Error) -> bool {
+        matches!(error, bitcoincore_rpc::Error::JsonRpc(JsonRpcError::Rpc(_)))
+    }
+
     async fn send_transaction(
         &self,
         transaction: &btc::Transaction,
@@ -90,4 +134,25 @@ impl BitcoinRelay for bitcoincore_rpc::Client {
             Err(e) => Err(e),
         }
     }
+
+    async fn transaction_block(
+        &self,
+        id: btc::Sha256d,
+    ) -> Result<Option<TransactionBlock>, Self::Error> {
+        let (hash, confirmations) = match self.get_raw_transaction_verbose(&id.into(), None) {
+            Ok(info) => match (info.blockhash, info.confirmations) {
+                (Some(hash), Some(confirmations)) => (hash, confirmations),
+                _ => return Ok(None),
+            },
+            Err(bitcoincore_rpc::Error::JsonRpc(JsonRpcError::Rpc(_))) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // The verbose transaction has no block height, so it is calculated from
+        // the current height of the blockchain.
+        let tip_height = self.get_block_count()? as u32;
+        Ok(Some(TransactionBlock {
+            hash: btc::Sha256d(hash.into()),
+            height: (tip_height + 1).saturating_sub(confirmations),
+        }))
+    }
 }
@@ -14,27 +14,51 @@
 
 //! Building blocks of the anchoring sync utility.
 
-pub use self::bitcoin_relay::{BitcoinRelay, TransactionStatus};
+pub use self::{
+    bitcoin_relay::{
+        AgreementPolicy, BitcoinRelay, CompositeRelay, CompositeRelayError, ElectrumError,
+        ElectrumRelay, EsploraError, EsploraRelay, RelayHealth, TransactionBlock,
+        TransactionStatus, DEFAULT_MAX_RELAY_BACKOFF, DEFAULT_RELAY_BACKOFF,
+    },
+    policy::ProposalError,
+    signer::{
+        KeyPool, RemoteSigner, SignInputRequest, Signer, SignerDaemon, SignerRequest,
+        SignerResponse, SignerSecret, DEFAULT_LOOKAHEAD,
+    },
+    state::SyncState,
+};
 
 use anyhow::anyhow;
-use btc_transaction_utils::{p2wsh, TxInRef};
-
-use std::{collections::HashMap, fmt::Display, sync::Arc};
+use exonum::crypto::Hash;
+
+use std::{
+    cmp,
+    collections::BTreeMap,
+    fmt::{self, Display},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
 
+use self::policy::ProposalContext;
 use crate::{
     api::{AnchoringProposalState, PrivateApi},
-    blockchain::SignInput,
+    blockchain::{ReportConfirmation, ReportUnconfirmed, SignInput, SignInputs},
     btc,
     config::Config,
 };
 
+pub mod runner;
+
 mod bitcoin_relay;
+mod policy;
+mod signer;
+mod state;
 
 /// Anchoring transaction with its index in the anchoring chain.
 pub type TransactionWithIndex = (btc::Transaction, u64);
 
-type KeyPool = Arc<HashMap<btc::PublicKey, btc::PrivateKey>>;
-
 /// Errors that occur when updating the anchoring chain.
 #[derive(Debug)]
 pub enum ChainUpdateError<C: Display> {
@@ -49,6 +73,12 @@ pub enum ChainUpdateError<C: Display> {
     },
     /// Initial funding transaction is absent.
     NoInitialFunds,
+    /// Anchoring is paused by the actual configuration.
+    Paused,
+    /// Error occurred in the signer.
+    Signer(anyhow::Error),
+    /// Anchoring transaction proposal violates the policy, so it has not been signed.
+    InvalidProposal(ProposalError),
     /// Internal error.
     Internal(anyhow::Error),
 }
@@ -56,12 +86,14 @@ pub enum ChainUpdateError<C: Display> {
 /// Signs the inputs of the anchoring transaction proposal by the corresponding
 /// Bitcoin private keys.
 #[derive(Debug)]
-pub struct AnchoringChainUpdateTask<T>
+pub struct AnchoringChainUpdateTask<T, S = KeyPool>
 where
     T: PrivateApi + 'static,
+    S: Signer + 'static,
 {
-    key_pool: KeyPool,
+    signer: S,
     api_client: T,
+    max_fee: Option<u64>,
 }
 
 impl<T> AnchoringChainUpdateTask<T>
@@ -69,17 +101,39 @@ where
     T: PrivateApi + 'static,
     T::Error: Display,
 {
-    /// Creates a new anchoring chain updater instance.
+    /// Creates a new anchoring chain updater instance, which keeps the given Bitcoin keys
+    /// in memory.
     pub fn new(
         keys: impl IntoIterator<Item = (btc::PublicKey, btc::PrivateKey)>,
         api_client: T,
     ) -> Self {
+        Self::with_signer(KeyPool::new(keys), api_client)
+    }
+}
+
+impl<T, S> AnchoringChainUpdateTask<T, S>
+where
+    T: PrivateApi + 'static,
+    T::Error: Display,
+    S: Signer + 'static,
+    S::Error: Into<anyhow::Error>,
+{
+    /// Creates a new anchoring chain updater instance, which delegates signing
+    /// to the given signer.
+    pub fn with_signer(signer: S, api_client: T) -> Self {
         Self {
-            key_pool: Arc::new(keys.into_iter().collect()),
+            signer,
             api_client,
+            max_fee: None,
         }
     }
 
+    /// Sets the maximum fee in satoshis, which the signed anchoring transaction may pay.
+    pub fn with_max_fee(mut self, max_fee: u64) -> Self {
+        self.max_fee = Some(max_fee);
+        self
+    }
+
     /// Returns an actual anchoring configuration.
     pub async fn anchoring_config(&self) -> Result<Config, T::Error> {
         self.api_client.config().await
@@ -99,17 +153,26 @@ where
             AnchoringProposalState::Available {
                 transaction,
                 inputs,
+                payload,
+                input_commitments,
+                ..
             } => {
                 let config = self
                     .anchoring_config()
                     .await
                     .map_err(ChainUpdateError::Client)?;
-                self.handle_proposal(config, transaction, inputs).await
+                self.handle_proposal(config, transaction, inputs, payload, input_commitments)
+                    .await
             }
             AnchoringProposalState::InsufficientFunds { balance, total_fee } => {
                 Err(ChainUpdateError::InsufficientFunds { balance, total_fee })
             }
             AnchoringProposalState::NoInitialFunds => Err(ChainUpdateError::NoInitialFunds),
+            AnchoringProposalState::Paused => Err(ChainUpdateError::Paused),
+            AnchoringProposalState::Swept { txid } => {
+                log::trace!("Anchoring funds have been swept by transaction {}", txid);
+                Ok(())
+            }
         }
     }
 
@@ -118,73 +181,165 @@ where
         config: Config,
         proposal: btc::Transaction,
         inputs: Vec<btc::Transaction>,
+        payload: btc::Payload,
+        input_commitments: Vec<Option<Hash>>,
     ) -> Result<(), ChainUpdateError<T::Error>> {
         log::trace!("Got an anchoring proposal: {:?}", proposal);
-        // Find among the keys one from which we have a private part.
+        // Find among the keys one from which the signer has a private part.
         // TODO What we have to do if we find more than one key? [ECR-3222]
-        let keypair = if let Some(keypair) =
-            self.find_private_key(config.anchoring_keys.iter().map(|x| x.bitcoin_key))
+        let signer_keys = self
+            .signer
+            .public_keys()
+            .await
+            .map_err(|e| ChainUpdateError::Signer(e.into()))?;
+        let public_key = if let Some(public_key) = config
+            .anchoring_keys
+            .iter()
+            .map(|x| x.bitcoin_key)
+            .find(|public_key| signer_keys.contains(public_key))
         {
-            keypair
+            public_key
         } else {
             return Ok(());
         };
-        // Create `SignInput` transactions.
-        let redeem_script = config.redeem_script();
-        let block_height = match proposal.anchoring_payload() {
-            Some(payload) => payload.block_height,
-            None => {
-                return Err(ChainUpdateError::Internal(anyhow!(
-                    "Incorrect anchoring proposal found: {:?}",
-                    proposal
-                )))
-            }
-        };
-
         log::info!(
             "Found a new unfinished anchoring transaction proposal for height: {}",
-            block_height
+            payload.block_height
         );
-
-        let mut signer = p2wsh::InputSigner::new(redeem_script);
-        let sign_input_messages = inputs
-            .iter()
-            .enumerate()
-            .map(|(index, proposal_input)| {
-                let signature = signer.sign_input(
-                    TxInRef::new(proposal.as_ref(), index),
-                    proposal_input.as_ref(),
-                    &(keypair.1).0.key,
-                )?;
-
-                Ok(SignInput {
-                    input: index as u32,
-                    input_signature: signature.into(),
-                    txid: proposal.id(),
-                })
-            })
-            .collect::<anyhow::Result<Vec<_>>>()
-            .map_err(ChainUpdateError::Internal)?;
-        // Send sign input transactions to the Exonum node.
-        for sign_input in sign_input_messages {
-            self.api_client
-                .sign_input(sign_input)
+        let following_config = self
+            .api_client
+            .following_config()
+            .await
+            .map_err(ChainUpdateError::Client)?;
+        self.validate_proposal(&config, &following_config, &proposal, &inputs, &payload)
+            .await?;
+
+        // Sign each input of the proposal.
+        let mut input_signatures = Vec::with_capacity(inputs.len());
+        for (index, prev_tx) in inputs.into_iter().enumerate() {
+            // The input which spends the output committed in the pay-to-contract mode
+            // is signed by the tweaked key.
+            let request = SignInputRequest {
+                public_key,
+                config: config.clone(),
+                following_config: following_config.clone(),
+                proposal: proposal.clone(),
+                payload: payload.clone(),
+                input: index as u32,
+                prev_tx,
+                commitment: input_commitments.get(index).cloned().flatten(),
+            };
+            let input_signature = self
+                .signer
+                .sign_input(&request)
                 .await
-                .map_err(ChainUpdateError::Client)?;
+                .map_err(|e| ChainUpdateError::Signer(e.into()))?;
+
+            input_signatures.push(input_signature);
+        }
+
+        // Send all signatures to the Exonum node in the single `SignInputs` transaction.
+        let txid = proposal.id();
+        let sign_inputs = SignInputs {
+            txid,
+            input_signatures: input_signatures.clone(),
+        };
+        if let Err(e) = self.api_client.sign_inputs(sign_inputs).await {
+            // The node may not support the batched signing yet, so the signatures are sent
+            // by the separate `SignInput` transactions. Other errors are not related to
+            // the batching, so they are returned as is.
+            if !T::is_not_found(&e) {
+                return Err(ChainUpdateError::Client(e));
+            }
+            log::warn!(
+                "Unable to send the batched input signatures, sending them one by one: {}",
+                e
+            );
+            for (input, input_signature) in input_signatures.into_iter().enumerate() {
+                self.api_client
+                    .sign_input(SignInput {
+                        input: input as u32,
+                        input_signature,
+                        txid,
+                    })
+                    .await
+                    .map_err(ChainUpdateError::Client)?;
+            }
         }
         Ok(())
     }
 
-    fn find_private_key(
+    /// Checks the proposal against the anchoring chain state and the anchored block hash,
+    /// which are obtained independently of the proposal itself.
+    async fn validate_proposal(
         &self,
-        anchoring_keys: impl IntoIterator<Item = btc::PublicKey>,
-    ) -> Option<(btc::PublicKey, btc::PrivateKey)> {
-        anchoring_keys.into_iter().find_map(|public_key| {
-            self.key_pool
-                .get(&public_key)
-                .cloned()
-                .map(|private_key| (public_key, private_key))
-        })
+        config: &Config,
+        following_config: &Option<Config>,
+        proposal: &btc::Transaction,
+        inputs: &[btc::Transaction],
+        payload: &btc::Payload,
+    ) -> Result<(), ChainUpdateError<T::Error>> {
+        let (chain_tail, chain_tail_payload) = match self.transactions_count().await? {
+            0 => (None, None),
+            count => {
+                let chain_tail = self
+                    .api_client
+                    .transaction_with_index(count - 1)
+                    .await
+                    .map_err(ChainUpdateError::Client)?;
+                // The payload committed in the pay-to-contract mode is requested separately,
+                // the policy checks it against the anchoring output.
+                let chain_tail_payload = match &chain_tail {
+                    Some(tail) if tail.anchoring_payload().is_none() => self
+                        .api_client
+                        .transaction_payload(count - 1)
+                        .await
+                        .map_err(ChainUpdateError::Client)?,
+                    _ => None,
+                };
+                (chain_tail, chain_tail_payload)
+            }
+        };
+        let funding_transactions = self
+            .api_client
+            .funding_transactions()
+            .await
+            .map_err(ChainUpdateError::Client)?;
+
+        ProposalContext {
+            config: config.clone(),
+            following_config: following_config.clone(),
+            chain_tail,
+            chain_tail_payload,
+            funding_transactions,
+            max_fee: self.max_fee,
+        }
+        .validate(proposal, inputs, payload)
+        .map_err(ChainUpdateError::InvalidProposal)?;
+
+        let block_hash = self
+            .api_client
+            .block_hash(payload.block_height)
+            .await
+            .map_err(ChainUpdateError::Client)?;
+        if block_hash != payload.block_hash {
+            return Err(ChainUpdateError::InvalidProposal(
+                ProposalError::BlockHashMismatch {
+                    height: payload.block_height,
+                    expected: block_hash,
+                    actual: payload.block_hash,
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    async fn transactions_count(&self) -> Result<u64, ChainUpdateError<T::Error>> {
+        self.api_client
+            .transactions_count()
+            .await
+            .map(|count| count.value)
+            .map_err(ChainUpdateError::Client)
     }
 }
 
@@ -201,8 +356,53 @@ pub enum SyncWithBitcoinError<C: Display, R: Display> {
     UnconfirmedFundingTransaction(btc::Sha256d),
 }
 
+/// Default number of the latest anchoring transactions, which are watched for
+/// the Bitcoin blockchain reorganizations.
+pub const DEFAULT_REORG_DEPTH: u64 = 6;
+
+/// Default number of Bitcoin confirmations after which the anchoring transaction
+/// is considered final, so its confirmations are no longer reported.
+pub const DEFAULT_FINAL_CONFIRMATIONS: u32 = 6;
+
+/// Reorganization of the Bitcoin blockchain, which has affected the anchoring chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReorgEvent {
+    /// Index of the first affected anchoring transaction.
+    pub index: u64,
+    /// Identifier of the first affected anchoring transaction.
+    pub txid: btc::Sha256d,
+    /// Hash of the block which contained the transaction before the reorganization,
+    /// if it is known.
+    pub previous_block_hash: Option<btc::Sha256d>,
+    /// Status of the transaction after the reorganization.
+    pub status: TransactionStatus,
+    /// Hash of the block which contains the transaction after the reorganization, if any.
+    pub block_hash: Option<btc::Sha256d>,
+    /// Identifiers of the anchoring transactions, which have been sent to the Bitcoin
+    /// network again.
+    pub rebroadcast: Vec<btc::Sha256d>,
+}
+
+/// Anchoring transaction, which is watched for the Bitcoin blockchain reorganizations.
+#[derive(Debug, Clone)]
+struct WatchedTransaction {
+    transaction: btc::Transaction,
+    confirmed: bool,
+    block_hash: Option<btc::Sha256d>,
+}
+
+type ReorgListener = Box<dyn Fn(&ReorgEvent) + Send + Sync>;
+
 /// Pushes anchoring transactions to the Bitcoin blockchain.
-#[derive(Debug)]
+///
+/// If the [reorg depth](#method.with_reorg_depth) is set, the task also watches
+/// the latest confirmed anchoring transactions. Once one of them leaves its block, the task
+/// sends the affected transactions to the Bitcoin network again and reports the
+/// [`ReorgEvent`](struct.ReorgEvent.html).
+///
+/// If the [final confirmations](#method.with_confirmation_reports) are set, the task
+/// reports the Bitcoin confirmations of the latest anchoring transactions to the Exonum
+/// blockchain until they become final.
 pub struct SyncWithBitcoinTask<T, R>
 where
     T: PrivateApi + 'static,
@@ -210,6 +410,32 @@ where
 {
     btc_relay: R,
     api_client: T,
+    reorg_depth: u64,
+    watched: Mutex<BTreeMap<u64, WatchedTransaction>>,
+    reorg_listener: Option<ReorgListener>,
+    reorgs_detected: AtomicU64,
+    final_confirmations: u32,
+    reported_confirmations: Mutex<BTreeMap<u64, (btc::Sha256d, u32)>>,
+    reported_unconfirmed: Mutex<Option<btc::Sha256d>>,
+}
+
+impl<T, R> fmt::Debug for SyncWithBitcoinTask<T, R>
+where
+    T: PrivateApi + fmt::Debug + 'static,
+    R: BitcoinRelay + fmt::Debug + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncWithBitcoinTask")
+            .field("btc_relay", &self.btc_relay)
+            .field("api_client", &self.api_client)
+            .field("reorg_depth", &self.reorg_depth)
+            .field("watched", &self.watched)
+            .field("reorgs_detected", &self.reorgs_detected)
+            .field("final_confirmations", &self.final_confirmations)
+            .field("reported_confirmations", &self.reported_confirmations)
+            .field("reported_unconfirmed", &self.reported_unconfirmed)
+            .finish()
+    }
 }
 
 impl<T, R> SyncWithBitcoinTask<T, R>
@@ -224,9 +450,48 @@ where
         Self {
             api_client,
             btc_relay,
+            reorg_depth: 0,
+            watched: Mutex::default(),
+            reorg_listener: None,
+            reorgs_detected: AtomicU64::new(0),
+            final_confirmations: 0,
+            reported_confirmations: Mutex::default(),
+            reported_unconfirmed: Mutex::default(),
         }
     }
 
+    /// Watches the given number of the latest anchoring transactions for the Bitcoin
+    /// blockchain reorganizations. Zero depth disables the watching, which is the default.
+    pub fn with_reorg_depth(mut self, depth: u64) -> Self {
+        self.reorg_depth = depth;
+        self
+    }
+
+    /// Calls the given function for each detected reorganization.
+    pub fn with_reorg_listener<F>(mut self, listener: F) -> Self
+    where
+        F: Fn(&ReorgEvent) + Send + Sync + 'static,
+    {
+        self.reorg_listener = Some(Box::new(listener));
+        self
+    }
+
+    /// Reports the Bitcoin confirmations of the latest anchoring transactions until they get
+    /// the given number of confirmations. The transactions deeper than the reorg depth or
+    /// [`DEFAULT_REORG_DEPTH`] are not reported. Zero number disables the reports, which is
+    /// the default.
+    ///
+    /// [`DEFAULT_REORG_DEPTH`]: constant.DEFAULT_REORG_DEPTH.html
+    pub fn with_confirmation_reports(mut self, final_confirmations: u32) -> Self {
+        self.final_confirmations = final_confirmations;
+        self
+    }
+
+    /// Returns the number of reorganizations detected by this task.
+    pub fn reorgs_detected(&self) -> u64 {
+        self.reorgs_detected.load(Ordering::Relaxed)
+    }
+
     /// Performs one attempt to send the first uncommitted anchoring transaction into the Bitcoin network, if any.
     /// sign an anchoring proposal, if any. Returns an index of the last committed transaction.
     pub async fn process(
@@ -234,6 +499,11 @@ where
         latest_committed_tx_index: Option<u64>,
     ) -> Result<Option<u64>, SyncWithBitcoinError<T::Error, R::Error>> {
         log::trace!("Perform syncing with the Bitcoin network");
+        // Recheck the transactions affected by the reorganization, if any.
+        let latest_committed_tx_index = match self.detect_reorg().await? {
+            Some(index) => latest_committed_tx_index.map(|latest| cmp::min(latest, index)),
+            None => latest_committed_tx_index,
+        };
         // Try to find a suitable transaction for sending to the Bitcoin network.
         let (index, transaction) = if let Some(index) = latest_committed_tx_index {
             // Check that the latest committed transaction was really sent into
@@ -249,6 +519,11 @@ where
                     .value;
 
                 if index + 1 == chain_len {
+                    if status == TransactionStatus::Mempool {
+                        self.report_unconfirmed(&transaction).await?;
+                    }
+                    self.report_confirmations(index, transaction, status)
+                        .await?;
                     return Ok(Some(index));
                 }
                 let index = index + 1;
@@ -347,6 +622,297 @@ where
         }
     }
 
+    /// Checks the saved sync state against the anchoring chain and returns the index
+    /// of the latest synced transaction to resume the sync from. Returns `None` if the state
+    /// does not match the anchoring chain, so the sync should be started from scratch.
+    pub async fn restore_state(
+        &self,
+        state: &SyncState,
+    ) -> Result<Option<u64>, SyncWithBitcoinError<T::Error, R::Error>> {
+        let transaction = self
+            .api_client
+            .transaction_with_index(state.latest_synced_tx_index)
+            .await
+            .map_err(SyncWithBitcoinError::Client)?;
+
+        match transaction {
+            Some(transaction) if transaction.id() == state.txid => {
+                Ok(Some(state.latest_synced_tx_index))
+            }
+            _ => {
+                log::warn!(
+                    "Saved sync state does not match the anchoring chain, transaction {} \
+                     with index {} is not found",
+                    state.txid,
+                    state.latest_synced_tx_index
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the sync state for the given index of the latest synced transaction.
+    pub async fn sync_state(
+        &self,
+        latest_synced_tx_index: u64,
+    ) -> Result<SyncState, SyncWithBitcoinError<T::Error, R::Error>> {
+        let txid = self.get_transaction(latest_synced_tx_index).await?.id();
+        let status = self.transaction_status(txid).await?;
+        Ok(SyncState {
+            latest_synced_tx_index,
+            txid,
+            confirmations: status.confirmations().unwrap_or_default(),
+        })
+    }
+
+    /// Reports the given latest anchoring transaction as unconfirmed if the following
+    /// anchoring transaction proposal spends it, but does not accelerate it yet. If there is
+    /// no proposal at the moment, the transaction is reported once, so that it can be replaced
+    /// by the fee bump before the following anchoring height.
+    async fn report_unconfirmed(
+        &self,
+        transaction: &btc::Transaction,
+    ) -> Result<(), SyncWithBitcoinError<T::Error, R::Error>> {
+        let proposal = self
+            .api_client
+            .anchoring_proposal()
+            .await
+            .map_err(SyncWithBitcoinError::Client)?;
+
+        let txid = transaction.id();
+        let should_report = match proposal {
+            AnchoringProposalState::Available {
+                transaction: proposal,
+                package_fee_rate: None,
+                ..
+            } => proposal.prev_tx_id() == txid,
+            AnchoringProposalState::None => {
+                *self.reported_unconfirmed.lock().unwrap() != Some(txid)
+            }
+            _ => false,
+        };
+        if should_report {
+            self.api_client
+                .report_unconfirmed(ReportUnconfirmed { txid })
+                .await
+                .map_err(SyncWithBitcoinError::Client)?;
+            *self.reported_unconfirmed.lock().unwrap() = Some(txid);
+
+            log::info!("Reported unconfirmed anchoring transaction: {}", txid);
+        }
+        Ok(())
+    }
+
+    /// Reports the Bitcoin confirmations of the anchoring transactions starting from the given
+    /// latest one in the reverse order. The earlier transactions have at least the same number
+    /// of confirmations, so the reports stop at the transaction already reported as final.
+    async fn report_confirmations(
+        &self,
+        tail_index: u64,
+        tail: btc::Transaction,
+        tail_status: TransactionStatus,
+    ) -> Result<(), SyncWithBitcoinError<T::Error, R::Error>> {
+        if self.final_confirmations == 0 {
+            return Ok(());
+        }
+
+        let depth = cmp::max(self.reorg_depth, DEFAULT_REORG_DEPTH);
+        let first_index = (tail_index + 1).saturating_sub(depth);
+        {
+            let mut reported = self.reported_confirmations.lock().unwrap();
+            *reported = reported.split_off(&first_index);
+        }
+
+        let mut entry = Some((tail, tail_status));
+        for index in (first_index..=tail_index).rev() {
+            let (transaction, status) = match entry.take() {
+                Some(entry) => entry,
+                None => {
+                    let transaction = self.get_transaction(index).await?;
+                    let status = self.transaction_status(transaction.id()).await?;
+                    (transaction, status)
+                }
+            };
+
+            let is_reported_final = self.reported_confirmations.lock().unwrap().get(&index)
+                == Some(&(transaction.id(), self.final_confirmations));
+            if is_reported_final {
+                break;
+            }
+            if let TransactionStatus::Committed(confirmations) = status {
+                self.report_confirmation(index, &transaction, confirmations)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports the Bitcoin block and the number of confirmations of the given anchoring
+    /// transaction, unless the same number has been already reported.
+    async fn report_confirmation(
+        &self,
+        index: u64,
+        transaction: &btc::Transaction,
+        confirmations: u32,
+    ) -> Result<(), SyncWithBitcoinError<T::Error, R::Error>> {
+        let txid = transaction.id();
+        let confirmations = cmp::min(confirmations, self.final_confirmations);
+        if self.reported_confirmations.lock().unwrap().get(&index) == Some(&(txid, confirmations)) {
+            return Ok(());
+        }
+
+        let block = self
+            .btc_relay
+            .transaction_block(txid)
+            .await
+            .map_err(SyncWithBitcoinError::Relay)?;
+        // The relay may be unable to provide the block of the transaction.
+        let block = match block {
+            Some(block) => block,
+            None => return Ok(()),
+        };
+        self.api_client
+            .report_confirmation(ReportConfirmation {
+                index,
+                txid,
+                block_hash: block.hash,
+                block_height: block.height,
+                confirmations,
+            })
+            .await
+            .map_err(SyncWithBitcoinError::Client)?;
+        self.reported_confirmations
+            .lock()
+            .unwrap()
+            .insert(index, (txid, confirmations));
+
+        log::info!(
+            "Reported {} confirmations of anchoring transaction: {}",
+            confirmations,
+            txid
+        );
+        Ok(())
+    }
+
+    /// Checks whether the watched anchoring transactions are still in their blocks and returns
+    /// the index of the first transaction affected by the reorganization, if any.
+    async fn detect_reorg(&self) -> Result<Option<u64>, SyncWithBitcoinError<T::Error, R::Error>> {
+        if self.reorg_depth == 0 {
+            return Ok(None);
+        }
+
+        let chain_len = self
+            .api_client
+            .transactions_count()
+            .await
+            .map_err(SyncWithBitcoinError::Client)?
+            .value;
+        let first_index = chain_len.saturating_sub(self.reorg_depth);
+        {
+            let mut watched = self.watched.lock().unwrap();
+            *watched = watched.split_off(&first_index);
+        }
+        // The latest transactions may be replaced by the fee bumping, so the watched entries
+        // are refreshed on each pass and the stale ones are dropped.
+        for index in first_index..chain_len {
+            let transaction = self.get_transaction(index).await?;
+            let mut watched = self.watched.lock().unwrap();
+            let is_stale = watched
+                .get(&index)
+                .map_or(true, |entry| entry.transaction.id() != transaction.id());
+            if is_stale {
+                watched.insert(
+                    index,
+                    WatchedTransaction {
+                        transaction,
+                        confirmed: false,
+                        block_hash: None,
+                    },
+                );
+            }
+        }
+
+        let watched = self.watched.lock().unwrap().clone();
+        let mut statuses = Vec::with_capacity(watched.len());
+        let mut event = None;
+        for (index, entry) in watched {
+            let txid = entry.transaction.id();
+            let status = self.transaction_status(txid).await?;
+            let block_hash = if status.confirmations().is_some() {
+                self.btc_relay
+                    .transaction_block(txid)
+                    .await
+                    .map_err(SyncWithBitcoinError::Relay)?
+                    .map(|block| block.hash)
+                    .or(entry.block_hash)
+            } else {
+                None
+            };
+
+            // The transaction is affected if it has left the blockchain or has moved
+            // to another block.
+            let is_moved = match (entry.block_hash, block_hash) {
+                (Some(previous), Some(current)) => previous != current,
+                _ => false,
+            };
+            if entry.confirmed && (status.confirmations().is_none() || is_moved) && event.is_none()
+            {
+                event = Some(ReorgEvent {
+                    index,
+                    txid,
+                    previous_block_hash: entry.block_hash,
+                    status,
+                    block_hash,
+                    rebroadcast: Vec::new(),
+                });
+            }
+
+            if let Some(watched) = self.watched.lock().unwrap().get_mut(&index) {
+                watched.confirmed = status.confirmations().is_some();
+                watched.block_hash = block_hash;
+            }
+            statuses.push((index, entry.transaction, status));
+        }
+
+        let mut event = match event {
+            Some(event) => event,
+            None => return Ok(None),
+        };
+        log::warn!(
+            "Anchoring transaction {} with index {} has been affected by the Bitcoin \
+             blockchain reorganization, its current status is {:?}",
+            event.txid,
+            event.index,
+            event.status
+        );
+        // Send the dropped transactions to the Bitcoin network again in the chain order.
+        for (index, transaction, status) in statuses {
+            if index < event.index || status.is_known() {
+                continue;
+            }
+            if let Err(e) = self.btc_relay.send_transaction(&transaction).await {
+                // The following transactions spend this one, so they cannot be sent either.
+                log::warn!(
+                    "Unable to send the anchoring transaction {} again. {}",
+                    transaction.id(),
+                    e
+                );
+                break;
+            }
+            log::info!(
+                "Sent transaction to the Bitcoin network again: {}",
+                transaction.id()
+            );
+            event.rebroadcast.push(transaction.id());
+        }
+
+        self.reorgs_detected.fetch_add(1, Ordering::Relaxed);
+        if let Some(listener) = self.reorg_listener.as_ref() {
+            listener(&event);
+        }
+        Ok(Some(event.index))
+    }
+
     async fn get_transaction(
         &self,
         index: u64,
@@ -0,0 +1,334 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validation of the anchoring transaction proposals before signing.
+
+use bitcoin::blockdata::transaction::OutPoint;
+use exonum::{crypto::Hash, helpers::Height};
+use thiserror::Error;
+
+use crate::{
+    btc,
+    config::{CatchUpPolicy, Config},
+};
+
+/// Violations of the policy, which the anchoring transaction proposal should satisfy
+/// to be signed by the sync utility.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ProposalError {
+    /// Proposal has an unexpected number of outputs.
+    #[error("Proposal has an unexpected number of outputs: {0}.")]
+    UnexpectedOutputsCount(usize),
+    /// The first output of the proposal pays neither to the actual nor to the following
+    /// anchoring address, or it does not pay to the sweep address if the sweep has been
+    /// requested.
+    #[error("The first output of the proposal does not pay to the anchoring address.")]
+    UnexpectedRecipient,
+    /// Payload committed by the proposal does not correspond to the reported one.
+    #[error("Payload committed by the proposal does not correspond to the reported one.")]
+    PayloadMismatch,
+    /// Payload height differs from the following anchoring height, or it is not
+    /// an anchoring height following it in the catch-up mode.
+    #[error("Payload height {actual} differs from the expected height {expected}.")]
+    UnexpectedHeight {
+        /// Expected anchoring height.
+        expected: Height,
+        /// Height in the payload.
+        actual: Height,
+    },
+    /// Payload block hash differs from the one obtained from the explorer API.
+    #[error("Payload block hash {actual} differs from the hash {expected} of the block at height {height}.")]
+    BlockHashMismatch {
+        /// Anchored block height.
+        height: Height,
+        /// Block hash obtained from the explorer API.
+        expected: Hash,
+        /// Block hash in the payload.
+        actual: Hash,
+    },
+    /// Payload of the latest anchoring transaction committed in the pay-to-contract mode
+    /// is unknown or does not correspond to its anchoring output.
+    #[error("Payload of the latest anchoring transaction does not correspond to its output.")]
+    ChainTailPayloadMismatch,
+    /// Proposal does not spend the latest anchoring transaction, although its anchoring
+    /// output can be spent by the actual anchoring keys.
+    #[error("Proposal does not spend the latest anchoring transaction.")]
+    ChainTailNotSpent,
+    /// Input spends neither the latest anchoring transaction nor an output of the funding
+    /// transaction recorded by the anchoring service.
+    #[error("Input {0} spends neither the latest anchoring transaction nor a funding output.")]
+    UnknownInput(u32),
+    /// Proposal fee exceeds the configured ceiling.
+    #[error("Proposal fee {fee} exceeds the ceiling {max_fee}.")]
+    FeeTooHigh {
+        /// Fee paid by the proposal.
+        fee: u64,
+        /// Maximum fee allowed by the policy.
+        max_fee: u64,
+    },
+}
+
+/// State of the anchoring chain obtained independently of the proposal, against which
+/// the proposal is checked.
+#[derive(Debug)]
+pub(super) struct ProposalContext {
+    /// Actual anchoring configuration.
+    pub config: Config,
+    /// Following anchoring configuration, if any.
+    pub following_config: Option<Config>,
+    /// The latest anchoring transaction, if any.
+    pub chain_tail: Option<btc::Transaction>,
+    /// Payload of the latest anchoring transaction committed in the pay-to-contract mode,
+    /// if any. It is used only if it corresponds to the anchoring output of the transaction.
+    pub chain_tail_payload: Option<btc::Payload>,
+    /// Unspent funding transactions recorded by the anchoring service.
+    pub funding_transactions: Vec<btc::Transaction>,
+    /// Maximum fee, which the proposal may pay.
+    pub max_fee: Option<u64>,
+}
+
+impl ProposalContext {
+    /// Checks the proposal except its anchored block hash, which should be checked
+    /// by the caller.
+    pub fn validate(
+        &self,
+        proposal: &btc::Transaction,
+        inputs: &[btc::Transaction],
+        payload: &btc::Payload,
+    ) -> Result<(), ProposalError> {
+        self.validate_outputs(proposal, payload)?;
+        validate_spent_outputs(proposal, inputs)?;
+
+        match self.replaced_tx(proposal) {
+            // The replacement of the latest anchoring transaction commits the same payload.
+            Some(replaced_tx) => {
+                let same_payload = match replaced_tx.anchoring_payload() {
+                    Some(replaced_payload) => replaced_payload == *payload,
+                    None => {
+                        let replaced_script = &replaced_tx.0.output[0].script_pubkey;
+                        *replaced_script == proposal.0.output[0].script_pubkey
+                    }
+                };
+                if !same_payload {
+                    return Err(ProposalError::PayloadMismatch);
+                }
+            }
+            None => {
+                self.validate_inputs(proposal, inputs, payload)?;
+                self.validate_height(payload)?;
+            }
+        }
+
+        if let (Some(max_fee), Some(fee)) = (self.max_fee, proposal.fee(inputs)) {
+            if fee > max_fee {
+                return Err(ProposalError::FeeTooHigh { fee, max_fee });
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_outputs(
+        &self,
+        proposal: &btc::Transaction,
+        payload: &btc::Payload,
+    ) -> Result<(), ProposalError> {
+        let outputs = &proposal.0.output;
+        // In the pay-to-contract mode the payload is committed by the anchoring output,
+        // otherwise the anchoring transaction also has the `OP_RETURN` output. The sweep
+        // transaction always has it.
+        let sweep_script = self
+            .config
+            .sweep_address
+            .as_ref()
+            .map(|address| address.0.script_pubkey());
+        let (commitment, outputs_count) = if self.config.pay_to_contract && sweep_script.is_none() {
+            (Some(payload.commitment()), 1)
+        } else {
+            (None, 2)
+        };
+        if outputs.len() != outputs_count {
+            return Err(ProposalError::UnexpectedOutputsCount(outputs.len()));
+        }
+        if commitment.is_none() && proposal.anchoring_payload().as_ref() != Some(payload) {
+            return Err(ProposalError::PayloadMismatch);
+        }
+
+        let is_expected_output = match sweep_script {
+            Some(sweep_script) => sweep_script == outputs[0].script_pubkey,
+            None => std::iter::once(&self.config)
+                .chain(&self.following_config)
+                .map(|config| {
+                    config
+                        .committed_redeem_script(commitment.as_ref())
+                        .as_ref()
+                        .to_v0_p2wsh()
+                })
+                .any(|script_pubkey| script_pubkey == outputs[0].script_pubkey),
+        };
+        if is_expected_output {
+            Ok(())
+        } else {
+            Err(ProposalError::UnexpectedRecipient)
+        }
+    }
+
+    fn validate_inputs(
+        &self,
+        proposal: &btc::Transaction,
+        inputs: &[btc::Transaction],
+        payload: &btc::Payload,
+    ) -> Result<(), ProposalError> {
+        let mut txins = proposal.0.input.iter().enumerate();
+        if let Some(tail) = &self.chain_tail {
+            // The proposal which recovers the broken anchoring chain refers to the latest
+            // anchoring transaction in the payload instead of spending it. It is allowed only
+            // if the anchoring output of this transaction cannot be spent indeed.
+            let is_recovery = payload.prev_tx_chain == Some(tail.id());
+            if !is_recovery || self.is_tail_spendable(tail)? {
+                let tail_output = OutPoint::new(tail.0.txid(), 0);
+                if txins.next().map(|(_, txin)| txin.previous_output) != Some(tail_output) {
+                    return Err(ProposalError::ChainTailNotSpent);
+                }
+            }
+        }
+
+        // Other inputs should spend the outputs of the recorded funding transactions
+        // to the actual anchoring address.
+        let funding_script = self.config.anchoring_out_script();
+        for (index, txin) in txins {
+            let is_recorded = self
+                .funding_transactions
+                .iter()
+                .any(|tx| tx.0.txid() == txin.previous_output.txid);
+            let spent_output = &inputs[index].0.output[txin.previous_output.vout as usize];
+            if !is_recorded || spent_output.script_pubkey != funding_script {
+                return Err(ProposalError::UnknownInput(index as u32));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the anchoring output of the latest anchoring transaction can be spent
+    /// by the actual anchoring keys. The output of the sweep transaction, for example,
+    /// cannot be spent.
+    fn is_tail_spendable(&self, tail: &btc::Transaction) -> Result<bool, ProposalError> {
+        let commitment = match tail.anchoring_payload() {
+            Some(_) => None,
+            None => Some(self.tail_payload(tail)?.commitment()),
+        };
+        let script_pubkey = self
+            .config
+            .committed_redeem_script(commitment.as_ref())
+            .as_ref()
+            .to_v0_p2wsh();
+        Ok(tail.0.output[0].script_pubkey == script_pubkey)
+    }
+
+    fn validate_height(&self, payload: &btc::Payload) -> Result<(), ProposalError> {
+        let expected = match &self.chain_tail {
+            None => Height::zero(),
+            Some(tail) => {
+                let latest_height = self.tail_payload(tail)?.block_height;
+                if self.is_transition() {
+                    // Transition transaction anchors the same height once again.
+                    latest_height
+                } else {
+                    self.config.following_anchoring_height(latest_height)
+                }
+            }
+        };
+
+        // In the catch-up mode the missed anchoring heights may be skipped, but the anchored
+        // block hash is checked by the caller, so the height cannot exceed the actual one.
+        let is_skipped = self.config.catch_up_policy == CatchUpPolicy::LatestHeight
+            && self.chain_tail.is_some()
+            && !self.is_transition()
+            && payload.block_height > expected
+            && self.config.previous_anchoring_height(payload.block_height) == payload.block_height;
+        if payload.block_height == expected || is_skipped {
+            Ok(())
+        } else {
+            Err(ProposalError::UnexpectedHeight {
+                expected,
+                actual: payload.block_height,
+            })
+        }
+    }
+
+    /// Returns the payload of the latest anchoring transaction. The payload committed
+    /// in the pay-to-contract mode cannot be obtained from the transaction itself, so
+    /// it is checked against the anchoring output of the transaction.
+    fn tail_payload(&self, tail: &btc::Transaction) -> Result<btc::Payload, ProposalError> {
+        if let Some(payload) = tail.anchoring_payload() {
+            return Ok(payload);
+        }
+
+        let payload = self
+            .chain_tail_payload
+            .as_ref()
+            .ok_or(ProposalError::ChainTailPayloadMismatch)?;
+        let commitment = payload.commitment();
+        let is_committed = std::iter::once(&self.config)
+            .chain(&self.following_config)
+            .map(|config| {
+                config
+                    .committed_redeem_script(Some(&commitment))
+                    .as_ref()
+                    .to_v0_p2wsh()
+            })
+            .any(|script_pubkey| script_pubkey == tail.0.output[0].script_pubkey);
+        if is_committed {
+            Ok(payload.clone())
+        } else {
+            Err(ProposalError::ChainTailPayloadMismatch)
+        }
+    }
+
+    /// Returns the latest anchoring transaction if the proposal replaces it.
+    fn replaced_tx(&self, proposal: &btc::Transaction) -> Option<&btc::Transaction> {
+        self.chain_tail.as_ref().filter(|tail| {
+            tail.0
+                .input
+                .iter()
+                .map(|txin| txin.previous_output)
+                .eq(proposal.0.input.iter().map(|txin| txin.previous_output))
+        })
+    }
+
+    fn is_transition(&self) -> bool {
+        self.following_config.as_ref().map_or(false, |following| {
+            following.redeem_script() != self.config.redeem_script()
+        })
+    }
+}
+
+/// Checks that the given transactions contain the outputs spent by the proposal.
+fn validate_spent_outputs(
+    proposal: &btc::Transaction,
+    inputs: &[btc::Transaction],
+) -> Result<(), ProposalError> {
+    if inputs.len() != proposal.0.input.len() {
+        let index = inputs.len().min(proposal.0.input.len());
+        return Err(ProposalError::UnknownInput(index as u32));
+    }
+
+    for (index, (txin, prev_tx)) in proposal.0.input.iter().zip(inputs).enumerate() {
+        let is_spent = txin.previous_output.txid == prev_tx.0.txid()
+            && prev_tx.0.output.len() > txin.previous_output.vout as usize;
+        if !is_spent {
+            return Err(ProposalError::UnknownInput(index as u32));
+        }
+    }
+    Ok(())
+}
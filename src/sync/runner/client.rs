@@ -0,0 +1,234 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! HTTP client for the API of the anchoring service instance.
+
+use async_trait::async_trait;
+use exonum::{crypto::Hash, helpers::Height, merkledb::ObjectHash};
+use exonum_explorer::api::{BlockInfo, BlockQuery};
+use serde::{de::DeserializeOwned, ser::Serialize};
+
+use crate::{
+    api::{
+        AnchoringChainLength, AnchoringProposalState, BlockProof, BlockProofQuery,
+        FindTransactionQuery, IndexQuery, PrivateApi, PublicApi, TransactionProof,
+    },
+    blockchain::{BumpFee, ReportConfirmation, ReportUnconfirmed, SignInput, SignInputs},
+    btc,
+    config::Config,
+};
+
+/// Client implementation for the API of the anchoring service instance.
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    /// Complete prefix of the private API with the port and the anchoring instance name.
+    private_prefix: String,
+    /// Complete prefix of the public API with the port and the anchoring instance name.
+    public_prefix: String,
+    /// Complete prefix of the public explorer API.
+    explorer_prefix: String,
+    /// Underlying HTTP client.
+    client: reqwest::Client,
+}
+
+impl ApiClient {
+    /// Create a new anchoring API relay with the specified hosts of the private and public
+    /// APIs and name of instance. Hostnames should be in form `{http|https}://{address}:{port}`.
+    pub fn new(
+        private_hostname: impl AsRef<str>,
+        public_hostname: impl AsRef<str>,
+        instance_name: impl AsRef<str>,
+    ) -> Self {
+        Self {
+            private_prefix: format!(
+                "{}/api/services/{}",
+                private_hostname.as_ref(),
+                instance_name.as_ref()
+            ),
+            public_prefix: format!(
+                "{}/api/services/{}",
+                public_hostname.as_ref(),
+                instance_name.as_ref()
+            ),
+            explorer_prefix: format!("{}/api/explorer", public_hostname.as_ref()),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn get<R>(&self, prefix: &str, endpoint: &str) -> Result<R, reqwest::Error>
+    where
+        R: DeserializeOwned + Send + 'static,
+    {
+        self.client
+            .get(&format!("{}/{}", prefix, endpoint))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn get_query<Q, R>(
+        &self,
+        prefix: &str,
+        endpoint: &str,
+        query: &Q,
+    ) -> Result<R, reqwest::Error>
+    where
+        Q: Serialize,
+        R: DeserializeOwned + Send + 'static,
+    {
+        self.client
+            .get(&format!("{}/{}", prefix, endpoint))
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn post<Q, R>(&self, endpoint: &str, body: &Q) -> Result<R, reqwest::Error>
+    where
+        Q: Serialize,
+        R: DeserializeOwned + Send + 'static,
+    {
+        self.client
+            .post(&format!("{}/{}", self.private_prefix, endpoint))
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+#[async_trait]
+impl PublicApi for ApiClient {
+    type Error = reqwest::Error;
+
+    async fn actual_address(&self) -> Result<btc::Address, Self::Error> {
+        self.get(&self.public_prefix, "address/actual").await
+    }
+
+    async fn following_address(&self) -> Result<Option<btc::Address>, Self::Error> {
+        self.get(&self.public_prefix, "address/following").await
+    }
+
+    async fn find_transaction(
+        &self,
+        height: Option<Height>,
+    ) -> Result<TransactionProof, Self::Error> {
+        let query = FindTransactionQuery { height };
+        self.get_query(&self.public_prefix, "find-transaction", &query)
+            .await
+    }
+
+    async fn block_proof(&self, height: Height) -> Result<BlockProof, Self::Error> {
+        let query = BlockProofQuery { height };
+        self.get_query(&self.public_prefix, "block-proof", &query)
+            .await
+    }
+
+    async fn config(&self) -> Result<Config, Self::Error> {
+        self.get(&self.public_prefix, "config").await
+    }
+}
+
+#[async_trait]
+impl PrivateApi for ApiClient {
+    type Error = reqwest::Error;
+
+    fn is_not_found(error: &Self::Error) -> bool {
+        error.status() == Some(reqwest::StatusCode::NOT_FOUND)
+    }
+
+    async fn sign_input(&self, sign_input: SignInput) -> Result<Hash, Self::Error> {
+        self.post("sign-input", &sign_input).await
+    }
+
+    async fn sign_inputs(&self, sign_inputs: SignInputs) -> Result<Hash, Self::Error> {
+        self.post("sign-inputs", &sign_inputs).await
+    }
+
+    async fn add_funds(&self, transaction: btc::Transaction) -> Result<Hash, Self::Error> {
+        self.post("add-funds", &transaction).await
+    }
+
+    async fn bump_fee(&self, bump_fee: BumpFee) -> Result<Hash, Self::Error> {
+        self.post("bump-fee", &bump_fee).await
+    }
+
+    async fn report_unconfirmed(&self, report: ReportUnconfirmed) -> Result<Hash, Self::Error> {
+        self.post("report-unconfirmed", &report).await
+    }
+
+    async fn report_confirmation(&self, report: ReportConfirmation) -> Result<Hash, Self::Error> {
+        self.post("report-confirmation", &report).await
+    }
+
+    async fn anchoring_proposal(&self) -> Result<AnchoringProposalState, Self::Error> {
+        self.get(&self.private_prefix, "anchoring-proposal").await
+    }
+
+    async fn anchoring_proposal_psbt(&self) -> Result<Option<btc::Psbt>, Self::Error> {
+        self.get(&self.private_prefix, "anchoring-proposal/psbt")
+            .await
+    }
+
+    async fn sign_psbt(&self, psbt: btc::Psbt) -> Result<Vec<Hash>, Self::Error> {
+        self.post("sign-psbt", &psbt).await
+    }
+
+    async fn config(&self) -> Result<Config, Self::Error> {
+        self.get(&self.private_prefix, "config").await
+    }
+
+    async fn following_config(&self) -> Result<Option<Config>, Self::Error> {
+        self.get(&self.private_prefix, "config/following").await
+    }
+
+    async fn block_hash(&self, height: Height) -> Result<Hash, Self::Error> {
+        let block_info: BlockInfo = self
+            .get_query(&self.explorer_prefix, "v1/block", &BlockQuery::new(height))
+            .await?;
+        Ok(block_info.block.object_hash())
+    }
+
+    async fn transaction_with_index(
+        &self,
+        index: u64,
+    ) -> Result<Option<btc::Transaction>, Self::Error> {
+        self.get_query(&self.private_prefix, "transaction", &IndexQuery { index })
+            .await
+    }
+
+    async fn transaction_payload(&self, index: u64) -> Result<Option<btc::Payload>, Self::Error> {
+        self.get_query(
+            &self.private_prefix,
+            "transaction/payload",
+            &IndexQuery { index },
+        )
+        .await
+    }
+
+    async fn funding_transactions(&self) -> Result<Vec<btc::Transaction>, Self::Error> {
+        self.get(&self.private_prefix, "funding-transactions").await
+    }
+
+    async fn transactions_count(&self) -> Result<AnchoringChainLength, Self::Error> {
+        self.get(&self.private_prefix, "transactions-count").await
+    }
+}
@@ -0,0 +1,453 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Configuration of the anchoring sync utility.
+
+use anyhow::{anyhow, ensure};
+use bitcoin::util::bip32::ExtendedPrivKey;
+use bitcoincore_rpc::{Auth as BitcoinRpcAuth, Client as BitcoinRpcClient};
+use pwbox::{sodium::Sodium, ErasedPwBox, Eraser, Suite};
+use rand_core::OsRng;
+use secp256k1::Secp256k1;
+use serde_derive::{Deserialize, Serialize};
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    fs::{self, File},
+    io::prelude::*,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use super::ApiClient;
+use crate::{
+    btc,
+    sync::{
+        AgreementPolicy, CompositeRelay, ElectrumRelay, EsploraRelay, KeyPool, RemoteSigner,
+        SignerSecret, DEFAULT_LOOKAHEAD,
+    },
+};
+
+/// Configuration of the anchoring sync utility.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// Anchoring node private API url address.
+    pub exonum_private_api: String,
+    /// Anchoring node public API url address, which is used to check the anchored
+    /// block hashes.
+    #[serde(default = "default_exonum_public_api")]
+    pub exonum_public_api: String,
+    /// Name of the anchoring service instance.
+    pub instance_name: String,
+    /// Plaintext pool of Bitcoin keys. It is left for compatibility with the configuration
+    /// files created by the previous versions, use the `encrypt-keys` command to encrypt it.
+    #[serde(
+        default,
+        with = "flatten_keypairs",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub bitcoin_key_pool: HashMap<btc::PublicKey, btc::PrivateKey>,
+    /// Pool of Bitcoin keys encrypted by the passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_key_pool: Option<EncryptedKeyPool>,
+    /// BIP-32 extended key, from which the anchoring keys are derived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitcoin_extended_key: Option<ExtendedKeyConfig>,
+    /// Configuration of the Bitcoin node RPC, which is used to broadcast anchoring
+    /// transactions.
+    pub bitcoin_rpc_config: Option<BitcoinRpcConfig>,
+    /// Base URL of the Esplora API, which is used to broadcast anchoring transactions
+    /// instead of the Bitcoin node RPC.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub esplora_url: Option<String>,
+    /// Electrum server, which is used to broadcast anchoring transactions instead of
+    /// the Bitcoin node RPC.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub electrum: Option<ElectrumConfig>,
+    /// Agreement policy of the configured Bitcoin relays. If specified, all of them are used
+    /// together via the composite relay.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay_agreement: Option<AgreementPolicy>,
+    /// Signer daemon. If specified, the sync utility does not use its own key pool to sign
+    /// anchoring transactions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_signer: Option<RemoteSignerConfig>,
+    /// Maximum fee in satoshis, which the signed anchoring transaction may pay.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_fee: Option<u64>,
+    /// File with the persistent state of the sync with the Bitcoin network.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_state_file: Option<PathBuf>,
+}
+
+fn default_exonum_public_api() -> String {
+    "http://localhost:8080".to_owned()
+}
+
+impl SyncConfig {
+    /// Loads the configuration from the TOML file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut toml = String::new();
+        file.read_to_string(&mut toml)?;
+        toml::de::from_str(&toml).map_err(From::from)
+    }
+
+    /// Saves the configuration to the TOML file, creating the parent directories if necessary.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = File::create(path)?;
+        let value_toml = toml::Value::try_from(&self)?;
+        file.write_all(value_toml.to_string().as_bytes())?;
+        Ok(())
+    }
+
+    /// Returns the pool of Bitcoin keys in this config, decrypting it if necessary.
+    /// The passphrase is requested only if the config contains the encrypted key pool.
+    pub fn unlock_key_pool<F>(&self, passphrase: F) -> anyhow::Result<KeyPoolContent>
+    where
+        F: FnOnce() -> anyhow::Result<String>,
+    {
+        let mut content = if let Some(encrypted_key_pool) = &self.encrypted_key_pool {
+            encrypted_key_pool.decrypt(&passphrase()?)?
+        } else {
+            KeyPoolContent::default()
+        };
+
+        if !self.bitcoin_key_pool.is_empty() {
+            log::warn!(
+                "Bitcoin keys are stored in plaintext, \
+                 use the `encrypt-keys` command to encrypt them."
+            );
+            content.keys.extend(self.bitcoin_key_pool.clone());
+        }
+        Ok(content)
+    }
+
+    /// Encrypts the given pool of Bitcoin keys and replaces the one in this config by it.
+    pub fn lock_key_pool(
+        &mut self,
+        content: &KeyPoolContent,
+        passphrase: &str,
+    ) -> anyhow::Result<()> {
+        self.encrypted_key_pool = Some(EncryptedKeyPool::encrypt(content, passphrase)?);
+        self.bitcoin_key_pool.clear();
+        Ok(())
+    }
+
+    /// Creates the in-memory signer, which also derives the anchoring keys from the extended
+    /// private key if the config uses it.
+    pub fn key_pool(&self, content: KeyPoolContent) -> anyhow::Result<KeyPool> {
+        let xprv = content.extended_private_key()?;
+        let key_pool = KeyPool::new(content.keys);
+
+        let extended_key = match &self.bitcoin_extended_key {
+            Some(extended_key) => extended_key,
+            None => return Ok(key_pool),
+        };
+        let xprv =
+            xprv.ok_or_else(|| anyhow!("Extended private key is absent in the key pool."))?;
+        for (index, public_key) in extended_key.public_keys.iter().enumerate() {
+            ensure!(
+                btc::derive_public_key(&xprv, index as u32)? == *public_key,
+                "Extended private key does not correspond to the anchoring key {} in the config.",
+                public_key
+            );
+        }
+        Ok(key_pool.with_extended_key(xprv, extended_key.index(), DEFAULT_LOOKAHEAD))
+    }
+
+    /// Creates the client for the API of the anchoring node.
+    pub fn api_client(&self) -> ApiClient {
+        ApiClient::new(
+            &self.exonum_private_api,
+            &self.exonum_public_api,
+            &self.instance_name,
+        )
+    }
+
+    /// Creates the Esplora API client, if it is configured.
+    pub fn esplora_relay(&self) -> Option<EsploraRelay> {
+        self.esplora_url.as_ref().map(EsploraRelay::new)
+    }
+
+    /// Creates the Electrum server client, if it is configured.
+    pub fn electrum_relay(&self) -> Option<ElectrumRelay> {
+        self.electrum.as_ref().map(|electrum| {
+            if electrum.tls {
+                ElectrumRelay::with_tls(&electrum.address)
+            } else {
+                ElectrumRelay::new(&electrum.address)
+            }
+        })
+    }
+
+    /// Creates the Bitcoin node RPC client, if it is configured.
+    pub fn bitcoin_relay(&self) -> anyhow::Result<Option<BitcoinRpcClient>> {
+        self.bitcoin_rpc_config
+            .clone()
+            .map(BitcoinRpcClient::try_from)
+            .transpose()
+            .map_err(From::from)
+    }
+
+    /// Creates the client of the signer daemon, if it is configured.
+    pub fn remote_signer(&self) -> anyhow::Result<Option<RemoteSigner>> {
+        self.remote_signer
+            .as_ref()
+            .map(|config| {
+                let secret = SignerSecret::load(&config.secret_file)?;
+                Ok(RemoteSigner::new(config.address, secret))
+            })
+            .transpose()
+    }
+
+    /// Creates the composite relay of all configured Bitcoin relays, if the agreement
+    /// policy is specified.
+    pub fn composite_relay(&self) -> anyhow::Result<Option<CompositeRelay>> {
+        let policy = match self.relay_agreement {
+            Some(policy) => policy,
+            None => return Ok(None),
+        };
+
+        let mut relay = CompositeRelay::new(policy);
+        if let Some(esplora) = self.esplora_relay() {
+            relay = relay.with_relay("esplora", esplora);
+        }
+        if let Some(electrum) = self.electrum_relay() {
+            relay = relay.with_relay("electrum", electrum);
+        }
+        if let Some(bitcoin_rpc) = self.bitcoin_relay()? {
+            relay = relay.with_relay("bitcoin_rpc", bitcoin_rpc);
+        }
+        ensure!(
+            !relay.health().is_empty(),
+            "Agreement policy is specified, but no Bitcoin relays are configured"
+        );
+        Ok(Some(relay))
+    }
+}
+
+/// Pool of Bitcoin keys encrypted by the key derived from the passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncryptedKeyPool {
+    /// Public keys of the pool, they are kept in plaintext to check the decrypted keys.
+    public_keys: Vec<btc::PublicKey>,
+    /// Private keys of the pool, sealed by the authenticated encryption with the key
+    /// derived from the passphrase by the `scrypt` function.
+    private_keys: ErasedPwBox,
+}
+
+impl EncryptedKeyPool {
+    /// Encrypts the pool of Bitcoin keys by the given passphrase.
+    pub fn encrypt(content: &KeyPoolContent, passphrase: &str) -> anyhow::Result<Self> {
+        let public_keys = content.keys.keys().copied().collect();
+        let content = serde_json::to_vec(content)?;
+        let pwbox = Sodium::build_box(&mut OsRng)
+            .seal(passphrase, content)
+            .map_err(|e| anyhow!("Unable to encrypt the key pool: {}", e))?;
+        let private_keys = Self::eraser()
+            .erase(&pwbox)
+            .map_err(|e| anyhow!("Unable to encrypt the key pool: {}", e))?;
+
+        Ok(Self {
+            public_keys,
+            private_keys,
+        })
+    }
+
+    /// Decrypts the pool of Bitcoin keys by the given passphrase and checks that
+    /// the decrypted keys correspond to the public ones.
+    pub fn decrypt(&self, passphrase: &str) -> anyhow::Result<KeyPoolContent> {
+        let content = Self::eraser()
+            .restore(&self.private_keys)
+            .map_err(|e| anyhow!("Unable to read the encrypted key pool: {}", e))?
+            .open(passphrase)
+            .map_err(|_| {
+                anyhow!("Unable to decrypt the key pool, perhaps the passphrase is wrong.")
+            })?;
+        let content = serde_json::from_slice::<KeyPoolContent>(&content)?;
+
+        ensure!(
+            content.keys.len() == self.public_keys.len(),
+            "Decrypted key pool does not correspond to its public keys."
+        );
+        let context = Secp256k1::signing_only();
+        for (public_key, private_key) in &content.keys {
+            ensure!(
+                self.public_keys.contains(public_key)
+                    && private_key.0.public_key(&context) == public_key.0,
+                "Decrypted private key does not correspond to the public key {}.",
+                public_key
+            );
+        }
+        Ok(content)
+    }
+
+    fn eraser() -> Eraser {
+        let mut eraser = Eraser::new();
+        eraser.add_suite::<Sodium>();
+        eraser
+    }
+}
+
+/// Plaintext content of the encrypted key pool.
+#[derive(Default, Serialize, Deserialize)]
+pub struct KeyPoolContent {
+    /// Bitcoin key pairs.
+    #[serde(with = "flatten_keypairs")]
+    pub keys: HashMap<btc::PublicKey, btc::PrivateKey>,
+    /// BIP-32 extended private key, from which the anchoring keys are derived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extended_private_key: Option<String>,
+}
+
+impl fmt::Debug for KeyPoolContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't reveal the private keys.
+        f.debug_struct("KeyPoolContent")
+            .field("public_keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl KeyPoolContent {
+    /// Checks that the key pool contains neither key pairs nor the extended private key.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.extended_private_key.is_none()
+    }
+
+    /// Returns the parsed BIP-32 extended private key, if any.
+    pub fn extended_private_key(&self) -> anyhow::Result<Option<ExtendedPrivKey>> {
+        self.extended_private_key
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(From::from)
+    }
+}
+
+/// Anchoring keys derived from the BIP-32 extended private key, which is kept in
+/// the encrypted key pool. The extended public key is not exported, since the anchoring
+/// keys are derived by the hardened derivation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExtendedKeyConfig {
+    /// Derived anchoring public keys ordered by the rotation index. The last one is
+    /// the actual anchoring key.
+    pub public_keys: Vec<btc::PublicKey>,
+}
+
+impl ExtendedKeyConfig {
+    /// Returns the rotation index of the actual anchoring key.
+    pub fn index(&self) -> u32 {
+        self.public_keys.len().saturating_sub(1) as u32
+    }
+}
+
+/// `Bitcoind` rpc configuration.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct BitcoinRpcConfig {
+    /// Bitcoin RPC url.
+    pub host: String,
+    /// Bitcoin RPC username.
+    pub user: Option<String>,
+    /// Bitcoin RPC password.
+    pub password: Option<String>,
+}
+
+/// Electrum server configuration.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ElectrumConfig {
+    /// Address of the server in the form `{host}:{port}`.
+    pub address: String,
+    /// Whether the connection to the server should be wrapped into TLS.
+    #[serde(default)]
+    pub tls: bool,
+}
+
+/// Signer daemon configuration.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RemoteSignerConfig {
+    /// Address of the signer daemon.
+    pub address: SocketAddr,
+    /// File with the secret shared with the signer daemon, which authenticates the sync
+    /// utility to it.
+    pub secret_file: PathBuf,
+}
+
+impl TryFrom<BitcoinRpcConfig> for BitcoinRpcClient {
+    type Error = bitcoincore_rpc::Error;
+
+    fn try_from(value: BitcoinRpcConfig) -> Result<Self, Self::Error> {
+        let auth = BitcoinRpcAuth::UserPass(
+            value.user.unwrap_or_default(),
+            value.password.unwrap_or_default(),
+        );
+        Self::new(value.host, auth)
+    }
+}
+
+mod flatten_keypairs {
+    use crate::btc::{PrivateKey, PublicKey};
+
+    use serde_derive::{Deserialize, Serialize};
+
+    use std::collections::HashMap;
+
+    /// The structure for storing the bitcoin keypair.
+    /// It is required for reading data from the .toml file into memory.
+    #[derive(Deserialize, Serialize)]
+    struct BitcoinKeypair {
+        /// Bitcoin public key.
+        public_key: PublicKey,
+        /// Corresponding private key.
+        private_key: PrivateKey,
+    }
+
+    pub fn serialize<S>(keys: &HashMap<PublicKey, PrivateKey>, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::Serialize;
+
+        let keypairs = keys
+            .iter()
+            .map(|(&public_key, private_key)| BitcoinKeypair {
+                public_key,
+                private_key: private_key.clone(),
+            })
+            .collect::<Vec<_>>();
+        keypairs.serialize(ser)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<HashMap<PublicKey, PrivateKey>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::Deserialize;
+        Vec::<BitcoinKeypair>::deserialize(deserializer).map(|keypairs| {
+            keypairs
+                .into_iter()
+                .map(|keypair| (keypair.public_key, keypair.private_key))
+                .collect()
+        })
+    }
+}
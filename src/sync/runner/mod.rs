@@ -0,0 +1,381 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reusable runner of the anchoring sync utility.
+//!
+//! The `btc_anchoring_sync` binary is a thin command line wrapper around this module,
+//! so the same sync loop can be embedded into another application:
+//!
+//! ```no_run
+//! use exonum_btc_anchoring::sync::runner::{AnchoringSyncRunner, SyncConfig};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let config = SyncConfig::load("btc_anchoring_sync.toml")?;
+//! let key_pool = config.key_pool(config.unlock_key_pool(|| Ok("passphrase".to_owned()))?)?;
+//! let (stop_sender, stop_receiver) = futures::channel::oneshot::channel::<()>();
+//! let mut runner = AnchoringSyncRunner::new(config.api_client(), key_pool)
+//!     .with_shutdown(async {
+//!         stop_receiver.await.ok();
+//!     });
+//! if let Some(relay) = config.bitcoin_relay()? {
+//!     runner = runner.with_bitcoin_relay(relay);
+//! }
+//! runner.run().await
+//! # }
+//! ```
+
+pub use self::{
+    client::ApiClient,
+    config::{
+        BitcoinRpcConfig, ElectrumConfig, EncryptedKeyPool, ExtendedKeyConfig, KeyPoolContent,
+        RemoteSignerConfig, SyncConfig,
+    },
+};
+
+use anyhow::{anyhow, bail};
+use futures::future::{self, BoxFuture, FutureExt};
+use tokio::time::delay_for;
+
+use std::{
+    fmt,
+    fmt::Display,
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::{
+    AnchoringChainUpdateTask, BitcoinRelay, ChainUpdateError, KeyPool, Signer, SyncState,
+    SyncWithBitcoinError, SyncWithBitcoinTask, DEFAULT_FINAL_CONFIRMATIONS, DEFAULT_REORG_DEPTH,
+};
+use crate::api::PrivateApi;
+
+mod client;
+mod config;
+
+/// Default interval between the iterations of the sync loop.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Runs the anchoring sync loop: signs the anchoring proposals and, if the Bitcoin relay
+/// is specified, pushes the anchoring transactions to the Bitcoin network.
+pub struct AnchoringSyncRunner<T, S = KeyPool, R = bitcoincore_rpc::Client>
+where
+    T: PrivateApi + Clone + 'static,
+    S: Signer + 'static,
+    R: BitcoinRelay + 'static,
+{
+    api_client: T,
+    chain_updater: AnchoringChainUpdateTask<T, S>,
+    bitcoin_relay: Option<SyncWithBitcoinTask<T, R>>,
+    poll_interval: Duration,
+    state_file: Option<PathBuf>,
+    shutdown: Option<BoxFuture<'static, ()>>,
+}
+
+impl<T, S, R> fmt::Debug for AnchoringSyncRunner<T, S, R>
+where
+    T: PrivateApi + Clone + fmt::Debug + 'static,
+    S: Signer + fmt::Debug + 'static,
+    R: BitcoinRelay + fmt::Debug + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnchoringSyncRunner")
+            .field("chain_updater", &self.chain_updater)
+            .field("bitcoin_relay", &self.bitcoin_relay)
+            .field("poll_interval", &self.poll_interval)
+            .field("state_file", &self.state_file)
+            .field("has_shutdown_signal", &self.shutdown.is_some())
+            .finish()
+    }
+}
+
+impl<T, S> AnchoringSyncRunner<T, S>
+where
+    T: PrivateApi + Clone + 'static,
+    S: Signer + 'static,
+{
+    /// Creates a new runner, which signs the anchoring proposals by the given signer
+    /// and does not push the anchoring transactions to the Bitcoin network.
+    pub fn new(api_client: T, signer: S) -> Self {
+        Self {
+            chain_updater: AnchoringChainUpdateTask::with_signer(signer, api_client.clone()),
+            api_client,
+            bitcoin_relay: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            state_file: None,
+            shutdown: None,
+        }
+    }
+}
+
+impl<T, S, R> AnchoringSyncRunner<T, S, R>
+where
+    T: PrivateApi + Clone + 'static,
+    T::Error: Display,
+    S: Signer + 'static,
+    S::Error: Into<anyhow::Error>,
+    R: BitcoinRelay + 'static,
+    R::Error: Display,
+{
+    /// Pushes the anchoring transactions to the Bitcoin network via the given relay.
+    /// The latest [`DEFAULT_REORG_DEPTH`] anchoring transactions are watched
+    /// for the Bitcoin blockchain reorganizations, and their confirmations are reported
+    /// until they get [`DEFAULT_FINAL_CONFIRMATIONS`].
+    ///
+    /// [`DEFAULT_REORG_DEPTH`]: ../constant.DEFAULT_REORG_DEPTH.html
+    /// [`DEFAULT_FINAL_CONFIRMATIONS`]: ../constant.DEFAULT_FINAL_CONFIRMATIONS.html
+    pub fn with_bitcoin_relay<R2>(self, relay: R2) -> AnchoringSyncRunner<T, S, R2>
+    where
+        R2: BitcoinRelay + 'static,
+        R2::Error: Display,
+    {
+        let sync_task = SyncWithBitcoinTask::new(relay, self.api_client.clone())
+            .with_reorg_depth(DEFAULT_REORG_DEPTH)
+            .with_confirmation_reports(DEFAULT_FINAL_CONFIRMATIONS);
+        AnchoringSyncRunner {
+            bitcoin_relay: Some(sync_task),
+            api_client: self.api_client,
+            chain_updater: self.chain_updater,
+            poll_interval: self.poll_interval,
+            state_file: self.state_file,
+            shutdown: self.shutdown,
+        }
+    }
+
+    /// Sets the interval between the iterations of the sync loop.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the maximum fee in satoshis, which the signed anchoring transaction may pay.
+    pub fn with_max_fee(mut self, max_fee: u64) -> Self {
+        self.chain_updater = self.chain_updater.with_max_fee(max_fee);
+        self
+    }
+
+    /// Persists the cursor of the sync with the Bitcoin network in the given file, so
+    /// the sync is resumed from the saved cursor after a restart instead of looking up
+    /// the first uncommitted transaction in the whole anchoring chain.
+    pub fn with_state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_file = Some(path.into());
+        self
+    }
+
+    /// Stops the sync loop once the given future is completed. The signal is checked
+    /// between the iterations of the loop, so the current iteration is always finished.
+    pub fn with_shutdown<F>(mut self, signal: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown = Some(signal.boxed());
+        self
+    }
+
+    /// Runs the sync loop until the shutdown signal is received or an unrecoverable
+    /// error occurs.
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        let mut shutdown = self
+            .shutdown
+            .take()
+            .unwrap_or_else(|| future::pending().boxed());
+
+        let mut latest_synced_tx_index = match (&self.bitcoin_relay, &self.state_file) {
+            (Some(relay), Some(path)) => restore_sync_state(relay, path).await,
+            _ => None,
+        };
+        loop {
+            self.update_chain().await?;
+            if let Some(relay) = self.bitcoin_relay.as_ref() {
+                let previous_index = latest_synced_tx_index;
+                sync_with_bitcoin(relay, &mut latest_synced_tx_index).await?;
+
+                if let (Some(index), Some(path)) = (latest_synced_tx_index, &self.state_file) {
+                    if previous_index != latest_synced_tx_index {
+                        save_sync_state(relay, index, path).await;
+                    }
+                }
+            }
+
+            // Don't perform this actions too frequent to avoid DOS attack.
+            tokio::select! {
+                _ = delay_for(self.poll_interval) => {}
+                _ = &mut shutdown => {
+                    log::info!("Anchoring sync has been stopped.");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Performs one attempt to sign the anchoring proposal. Only the internal errors
+    /// are returned, the other ones are logged.
+    async fn update_chain(&self) -> anyhow::Result<()> {
+        match self.chain_updater.process().await {
+            Ok(_) => {}
+            // Client problems most often occurs due to network problems.
+            Err(ChainUpdateError::Client(e)) => {
+                log::error!("An error in the anchoring API client occurred. {}", e)
+            }
+            // Sometimes Bitcoin end in the anchoring wallet.
+            Err(ChainUpdateError::InsufficientFunds { total_fee, balance }) => log::warn!(
+                "Insufficient funds to construct a new anchoring transaction, \
+                 total fee is {}, total balance is {}",
+                total_fee,
+                balance
+            ),
+            // For the work of anchoring you need to replenish anchoring wallet.
+            Err(ChainUpdateError::NoInitialFunds) => {
+                let address = match self.chain_updater.anchoring_config().await {
+                    Ok(config) => config.anchoring_address(),
+                    Err(e) => {
+                        log::error!("An error in the anchoring API client occurred. {}", e);
+                        return Ok(());
+                    }
+                };
+
+                log::warn!(
+                    "Initial funding transaction is absent, you should send some \
+                     Bitcoins to the address {}",
+                    address
+                );
+                log::warn!(
+                    "And then confirm this transaction using the private \
+                     `add-funds` API method."
+                )
+            }
+            // Anchoring may be halted via the supervisor, e.g. during a fee spike.
+            Err(ChainUpdateError::Paused) => log::info!(
+                "Anchoring is paused by the actual configuration, \
+                 no anchoring transactions are proposed"
+            ),
+            // Signer daemon may be unavailable or refuse to sign the proposal.
+            Err(ChainUpdateError::Signer(e)) => {
+                log::error!("An error in the signer occurred. {}", e)
+            }
+            // The proposal may be forged by the compromised anchoring node.
+            Err(ChainUpdateError::InvalidProposal(e)) => {
+                log::error!("Refused to sign the anchoring proposal. {}", e)
+            }
+            // Stop execution if an internal error occurred.
+            Err(ChainUpdateError::Internal(e)) => return Err(e),
+        }
+        Ok(())
+    }
+}
+
+/// Performs one attempt to push the anchoring transaction to the Bitcoin network and updates
+/// the index of the latest synced transaction. Only the unrecoverable errors are returned,
+/// the other ones are logged.
+async fn sync_with_bitcoin<T, R>(
+    relay: &SyncWithBitcoinTask<T, R>,
+    latest_synced_tx_index: &mut Option<u64>,
+) -> anyhow::Result<()>
+where
+    T: PrivateApi + 'static,
+    T::Error: Display,
+    R: BitcoinRelay + 'static,
+    R::Error: Display,
+{
+    match relay.process(*latest_synced_tx_index).await {
+        Ok(index) => *latest_synced_tx_index = index,
+
+        Err(SyncWithBitcoinError::Client(e)) => {
+            log::error!("An error in the anchoring API client occurred. {}", e)
+        }
+
+        Err(SyncWithBitcoinError::Relay(e)) => {
+            log::error!("An error in the Bitcoin relay occurred. {}", e)
+        }
+
+        Err(SyncWithBitcoinError::UnconfirmedFundingTransaction(id)) => bail!(
+            "Funding transaction with id {} is unconfirmed by Bitcoin network. \
+             This is a serious mistake that can break anchoring process.",
+            id
+        ),
+
+        // Stop execution if an internal error occurred.
+        Err(SyncWithBitcoinError::Internal(e)) => return Err(e),
+    }
+    Ok(())
+}
+
+/// Loads the saved sync state and checks it against the anchoring chain. Errors are logged,
+/// since the sync can always be started from scratch.
+async fn restore_sync_state<T, R>(relay: &SyncWithBitcoinTask<T, R>, path: &Path) -> Option<u64>
+where
+    T: PrivateApi + 'static,
+    T::Error: Display,
+    R: BitcoinRelay + 'static,
+    R::Error: Display,
+{
+    let state = match SyncState::load(path) {
+        Ok(state) => state?,
+        Err(e) => {
+            log::error!("Unable to load the sync state from {:?}. {}", path, e);
+            return None;
+        }
+    };
+
+    match relay.restore_state(&state).await {
+        Ok(index) => {
+            if index.is_some() {
+                log::info!(
+                    "Resumed sync with the Bitcoin network from transaction {} with index {}",
+                    state.txid,
+                    state.latest_synced_tx_index
+                );
+            }
+            index
+        }
+        Err(e) => {
+            log::error!(
+                "Unable to check the saved sync state. {}",
+                describe_error(e)
+            );
+            None
+        }
+    }
+}
+
+/// Saves the state of the sync with the latest synced transaction. Errors are logged,
+/// since they do not affect the anchoring.
+async fn save_sync_state<T, R>(relay: &SyncWithBitcoinTask<T, R>, index: u64, path: &Path)
+where
+    T: PrivateApi + 'static,
+    T::Error: Display,
+    R: BitcoinRelay + 'static,
+    R::Error: Display,
+{
+    let result = match relay.sync_state(index).await {
+        Ok(state) => state.save(path),
+        Err(e) => Err(anyhow!(describe_error(e))),
+    };
+    if let Err(e) = result {
+        log::error!("Unable to save the sync state to {:?}. {}", path, e);
+    }
+}
+
+fn describe_error<C: Display, R: Display>(e: SyncWithBitcoinError<C, R>) -> String {
+    match e {
+        SyncWithBitcoinError::Client(e) => {
+            format!("An error in the anchoring API client occurred. {}", e)
+        }
+        SyncWithBitcoinError::Relay(e) => format!("An error in the Bitcoin relay occurred. {}", e),
+        SyncWithBitcoinError::Internal(e) => e.to_string(),
+        SyncWithBitcoinError::UnconfirmedFundingTransaction(id) => {
+            format!("Funding transaction with id {} is unconfirmed", id)
+        }
+    }
+}
@@ -0,0 +1,565 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signers of the anchoring transaction proposals.
+//!
+//! The sync utility may keep the Bitcoin private keys in its memory by using the [`KeyPool`]
+//! signer, or delegate signing to a separate [`SignerDaemon`] by using the [`RemoteSigner`].
+//!
+//! # Remote signer protocol
+//!
+//! The signer daemon listens on a TCP socket. Each request is a single line containing
+//! the JSON-serialized [`SignerRequest`], and the daemon replies to each request with
+//! a single line containing the JSON-serialized [`SignerResponse`].
+//!
+//! Once the connection is accepted, the daemon sends a random challenge, and the remote
+//! signer should authenticate itself by the HMAC-SHA256 of this challenge keyed by
+//! the [`SignerSecret`] shared with the daemon. The daemon closes the connection if
+//! the authentication fails:
+//!
+//! ```text
+//! <- {"challenge":"8f1e..."}
+//! -> {"method":"authenticate","params":"5b2c..."}
+//! <- "authenticated"
+//! -> {"method":"public_keys"}
+//! <- {"public_keys":["03b9...","02a1..."]}
+//! -> {"method":"sign_input","params":{"public_key":"03b9...","config":{...},"proposal":"0200...",
+//!     "payload":{...},"input":0,"prev_tx":"0200...","commitment":"5f3a..."}}
+//! <- {"input_signature":"3044..."}
+//! -> {"method":"sign_input","params":{...}}
+//! <- {"error":"Payload height goes backwards: ..."}
+//! ```
+//!
+//! [`KeyPool`]: struct.KeyPool.html
+//! [`SignerDaemon`]: struct.SignerDaemon.html
+//! [`RemoteSigner`]: struct.RemoteSigner.html
+//! [`SignerRequest`]: enum.SignerRequest.html
+//! [`SignerResponse`]: enum.SignerResponse.html
+//! [`SignerSecret`]: struct.SignerSecret.html
+
+use anyhow::{anyhow, bail, ensure};
+use async_trait::async_trait;
+use bitcoin::util::bip32::ExtendedPrivKey;
+use bitcoin_hashes::{hmac, sha256, Hash as BitcoinHash, HashEngine};
+use btc_transaction_utils::{p2wsh, TxInRef};
+use exonum::{crypto::Hash, helpers::Height};
+use rand_core::{OsRng, RngCore};
+use serde_derive::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{btc, config::Config};
+
+/// Request to sign the single input of the anchoring transaction proposal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignInputRequest {
+    /// Bitcoin key of the anchoring node which should sign the input.
+    pub public_key: btc::PublicKey,
+    /// Actual anchoring configuration.
+    pub config: Config,
+    /// Following anchoring configuration, if the proposal transits the anchoring funds
+    /// to its address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub following_config: Option<Config>,
+    /// Anchoring transaction proposal.
+    pub proposal: btc::Transaction,
+    /// Payload committed by the proposal.
+    pub payload: btc::Payload,
+    /// Index of the signed input.
+    pub input: u32,
+    /// Transaction which output is spent by the signed input.
+    pub prev_tx: btc::Transaction,
+    /// Pay-to-contract commitment of the output spent by the signed input, if any.
+    /// Such input should be signed by the key tweaked by the commitment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commitment: Option<Hash>,
+}
+
+impl SignInputRequest {
+    /// Signs the input by the given private key, which corresponds to the requested
+    /// public key.
+    pub fn sign(&self, private_key: &btc::PrivateKey) -> anyhow::Result<btc::InputSignature> {
+        ensure!(
+            (self.input as usize) < self.proposal.0.input.len(),
+            "Missing input with index: {}",
+            self.input
+        );
+
+        let private_key = match self.commitment {
+            Some(commitment) => btc::contract_private_key(private_key, &commitment),
+            None => private_key.clone(),
+        };
+        let signature = p2wsh::InputSigner::new(
+            self.config
+                .committed_redeem_script(self.commitment.as_ref()),
+        )
+        .sign_input(
+            TxInRef::new(self.proposal.as_ref(), self.input as usize),
+            self.prev_tx.as_ref(),
+            &private_key.0.key,
+        )?;
+        Ok(signature.into())
+    }
+}
+
+/// Describes signing of the anchoring transaction proposals by the Bitcoin keys.
+#[async_trait]
+pub trait Signer {
+    /// Error type for the current signer implementation.
+    type Error;
+    /// Returns the Bitcoin public keys which private parts are available to the signer.
+    async fn public_keys(&self) -> Result<Vec<btc::PublicKey>, Self::Error>;
+    /// Signs the input of the anchoring transaction proposal by the requested key.
+    async fn sign_input(
+        &self,
+        request: &SignInputRequest,
+    ) -> Result<btc::InputSignature, Self::Error>;
+}
+
+/// Default number of the anchoring keys derived from the extended key beyond the actual
+/// rotation index.
+pub const DEFAULT_LOOKAHEAD: u32 = 20;
+
+/// In-memory pool of the Bitcoin keys.
+///
+/// Besides the given keys, the pool may contain the anchoring keys derived from the BIP-32
+/// extended private key. Such keys are derived on demand for the rotation indexes up to
+/// the actual one plus the lookahead window, so the pool can sign the proposals of
+/// the configurations that use any of the keys already announced by the node.
+#[derive(Debug, Clone, Default)]
+pub struct KeyPool {
+    keys: Arc<HashMap<btc::PublicKey, btc::PrivateKey>>,
+    extended_key: Option<ExtendedKey>,
+}
+
+/// Extended private key along with the public keys derived for the window of the rotation
+/// indexes.
+#[derive(Clone)]
+struct ExtendedKey {
+    xprv: ExtendedPrivKey,
+    public_keys: Vec<btc::PublicKey>,
+}
+
+impl fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't reveal the extended private key.
+        f.debug_struct("ExtendedKey")
+            .field("public_keys", &self.public_keys)
+            .finish()
+    }
+}
+
+impl KeyPool {
+    /// Creates a new key pool with the given keys.
+    pub fn new(keys: impl IntoIterator<Item = (btc::PublicKey, btc::PrivateKey)>) -> Self {
+        Self {
+            keys: Arc::new(keys.into_iter().collect()),
+            extended_key: None,
+        }
+    }
+
+    /// Adds to the pool the anchoring keys derived from the given extended private key
+    /// with the rotation indexes up to `index + lookahead`.
+    ///
+    /// See [`btc::derive_private_key`] for details.
+    ///
+    /// [`btc::derive_private_key`]: ../btc/fn.derive_private_key.html
+    pub fn with_extended_key(mut self, xprv: ExtendedPrivKey, index: u32, lookahead: u32) -> Self {
+        // The public keys of the hardened children cannot be derived on demand without
+        // the private ones, so they are derived once.
+        let public_keys = (0..=index.saturating_add(lookahead))
+            .filter_map(|index| btc::derive_public_key(&xprv, index).ok())
+            .collect();
+        self.extended_key = Some(ExtendedKey { xprv, public_keys });
+        self
+    }
+
+    /// Returns the private key corresponding to the given public key, deriving it from
+    /// the extended key if necessary.
+    pub fn find_private_key(&self, public_key: &btc::PublicKey) -> Option<btc::PrivateKey> {
+        if let Some(private_key) = self.keys.get(public_key) {
+            return Some(private_key.clone());
+        }
+
+        let extended_key = self.extended_key.as_ref()?;
+        let index = extended_key
+            .public_keys
+            .iter()
+            .position(|derived_key| derived_key == public_key)?;
+        btc::derive_private_key(&extended_key.xprv, index as u32).ok()
+    }
+
+    /// Returns the public keys derived from the extended key, ordered by the rotation index.
+    fn derived_public_keys(&self) -> Vec<btc::PublicKey> {
+        self.extended_key
+            .as_ref()
+            .map_or_else(Vec::new, |key| key.public_keys.clone())
+    }
+
+    fn all_public_keys(&self) -> Vec<btc::PublicKey> {
+        self.keys
+            .keys()
+            .copied()
+            .chain(self.derived_public_keys())
+            .collect()
+    }
+
+    fn private_key(&self, public_key: &btc::PublicKey) -> anyhow::Result<btc::PrivateKey> {
+        self.find_private_key(public_key)
+            .ok_or_else(|| anyhow!("Private key for {} is absent.", public_key))
+    }
+}
+
+#[async_trait]
+impl Signer for KeyPool {
+    type Error = anyhow::Error;
+
+    async fn public_keys(&self) -> Result<Vec<btc::PublicKey>, Self::Error> {
+        Ok(self.all_public_keys())
+    }
+
+    async fn sign_input(
+        &self,
+        request: &SignInputRequest,
+    ) -> Result<btc::InputSignature, Self::Error> {
+        request.sign(&self.private_key(&request.public_key)?)
+    }
+}
+
+/// Request to the signer daemon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum SignerRequest {
+    /// Authenticates the remote signer by the HMAC-SHA256 of the challenge keyed by
+    /// the shared secret, in hex.
+    Authenticate(String),
+    /// Requests the Bitcoin public keys available to the signer daemon.
+    PublicKeys,
+    /// Requests to sign the input of the anchoring transaction proposal.
+    SignInput(SignInputRequest),
+}
+
+/// Response of the signer daemon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerResponse {
+    /// Random challenge in hex, which should be authenticated by the remote signer.
+    Challenge(String),
+    /// The remote signer has been authenticated.
+    Authenticated,
+    /// Bitcoin public keys available to the signer daemon.
+    PublicKeys(Vec<btc::PublicKey>),
+    /// Signature of the requested input.
+    InputSignature(btc::InputSignature),
+    /// The request has been refused or failed.
+    Error(String),
+}
+
+/// Secret shared by the signer daemon and the remote signers, which authenticates
+/// the latter.
+#[derive(Clone)]
+pub struct SignerSecret(Vec<u8>);
+
+impl SignerSecret {
+    /// Creates a shared secret from the given bytes.
+    pub fn new(secret: impl Into<Vec<u8>>) -> anyhow::Result<Self> {
+        let secret = secret.into();
+        ensure!(!secret.is_empty(), "Signer secret should not be empty.");
+        Ok(Self(secret))
+    }
+
+    /// Loads the shared secret from the given file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        // Ignore the trailing newline, which is added by most text editors.
+        let secret = fs::read_to_string(path)?;
+        Self::new(secret.trim_end_matches(&['\r', '\n'][..]))
+    }
+
+    /// Computes the HMAC-SHA256 of the given challenge keyed by this secret.
+    fn authenticate(&self, challenge: &[u8]) -> [u8; 32] {
+        let mut engine = hmac::HmacEngine::<sha256::Hash>::new(&self.0);
+        engine.input(challenge);
+        hmac::Hmac::<sha256::Hash>::from_engine(engine).into_inner()
+    }
+
+    /// Checks the response of the remote signer to the given challenge.
+    fn verify(&self, challenge: &[u8], response: &str) -> bool {
+        let response = match hex::decode(response) {
+            Ok(response) => response,
+            Err(_) => return false,
+        };
+        let expected = self.authenticate(challenge);
+        // Compare in the constant time to not reveal the expected response.
+        response.len() == expected.len()
+            && response
+                .iter()
+                .zip(&expected)
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+impl fmt::Debug for SignerSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't reveal the secret.
+        f.debug_tuple("SignerSecret").finish()
+    }
+}
+
+/// Client of the signer daemon.
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    address: SocketAddr,
+    secret: SignerSecret,
+}
+
+impl RemoteSigner {
+    /// Creates a client of the signer daemon listening on the given address, which
+    /// authenticates itself by the given shared secret.
+    pub fn new(address: SocketAddr, secret: SignerSecret) -> Self {
+        Self { address, secret }
+    }
+
+    async fn request(&self, request: &SignerRequest) -> anyhow::Result<SignerResponse> {
+        let mut stream = BufReader::new(TcpStream::connect(self.address).await?);
+        let challenge = match read_message::<SignerResponse, _>(&mut stream).await? {
+            SignerResponse::Challenge(challenge) => hex::decode(challenge)?,
+            response => bail!("Unexpected signer daemon response: {:?}", response),
+        };
+        let auth = SignerRequest::Authenticate(hex::encode(self.secret.authenticate(&challenge)));
+        write_message(stream.get_mut(), &auth).await?;
+        match read_message(&mut stream).await? {
+            SignerResponse::Authenticated => {}
+            SignerResponse::Error(e) => bail!("Signer daemon refused the authentication: {}", e),
+            response => bail!("Unexpected signer daemon response: {:?}", response),
+        }
+
+        write_message(stream.get_mut(), request).await?;
+        match read_message(&mut stream).await? {
+            SignerResponse::Error(e) => bail!("Signer daemon refused the request: {}", e),
+            response => Ok(response),
+        }
+    }
+}
+
+/// Reads the single line containing the JSON-serialized message.
+async fn read_message<T, R>(reader: &mut R) -> anyhow::Result<T>
+where
+    T: serde::de::DeserializeOwned,
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = String::new();
+    ensure!(
+        reader.read_line(&mut line).await? != 0,
+        "Connection has been closed."
+    );
+    serde_json::from_str(&line).map_err(From::from)
+}
+
+/// Writes the message as the single line containing its JSON serialization.
+async fn write_message<T, W>(writer: &mut W, message: &T) -> anyhow::Result<()>
+where
+    T: serde::Serialize,
+    W: AsyncWrite + Unpin,
+{
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    type Error = anyhow::Error;
+
+    async fn public_keys(&self) -> Result<Vec<btc::PublicKey>, Self::Error> {
+        match self.request(&SignerRequest::PublicKeys).await? {
+            SignerResponse::PublicKeys(keys) => Ok(keys),
+            response => bail!("Unexpected signer daemon response: {:?}", response),
+        }
+    }
+
+    async fn sign_input(
+        &self,
+        request: &SignInputRequest,
+    ) -> Result<btc::InputSignature, Self::Error> {
+        let request = SignerRequest::SignInput(request.clone());
+        match self.request(&request).await? {
+            SignerResponse::InputSignature(signature) => Ok(signature),
+            response => bail!("Unexpected signer daemon response: {:?}", response),
+        }
+    }
+}
+
+/// Signer daemon, which signs the anchoring transaction proposals by the keys from the key
+/// pool on requests of the remote signers.
+///
+/// The daemon serves only the remote signers authenticated by the shared secret.
+/// Unlike the sync utility, the daemon does not trust the requests. It refuses to sign
+/// the input if the payload height is lower than the height of the previously signed
+/// payload, or if the request is inconsistent with the proposal.
+#[derive(Debug)]
+pub struct SignerDaemon {
+    key_pool: KeyPool,
+    secret: SignerSecret,
+    latest_signed_height: Mutex<Option<Height>>,
+}
+
+impl SignerDaemon {
+    /// Creates a new signer daemon with the given keys, which serves the remote signers
+    /// authenticated by the given shared secret.
+    pub fn new(key_pool: KeyPool, secret: SignerSecret) -> Self {
+        Self {
+            key_pool,
+            secret,
+            latest_signed_height: Mutex::default(),
+        }
+    }
+
+    /// Handles the single request of the authenticated remote signer.
+    pub fn handle_request(&self, request: SignerRequest) -> SignerResponse {
+        let response = match request {
+            SignerRequest::Authenticate(_) => Err(anyhow!("Already authenticated.")),
+            SignerRequest::PublicKeys => {
+                Ok(SignerResponse::PublicKeys(self.key_pool.all_public_keys()))
+            }
+            SignerRequest::SignInput(request) => self
+                .sign_input(&request)
+                .map(SignerResponse::InputSignature),
+        };
+        response.unwrap_or_else(|e| SignerResponse::Error(e.to_string()))
+    }
+
+    /// Listens to the requests of the remote signers on the given address. The address
+    /// should be a loopback one unless the network between the daemon and the remote
+    /// signers is trusted, since the requests are not encrypted.
+    pub async fn listen(self, address: SocketAddr) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        self.serve(listener).await
+    }
+
+    /// Serves the requests of the remote signers accepted by the given listener.
+    pub async fn serve(self, mut listener: TcpListener) -> anyhow::Result<()> {
+        let daemon = Arc::new(self);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let daemon = daemon.clone();
+            tokio::spawn(async move {
+                if let Err(e) = daemon.handle_connection(stream).await {
+                    log::warn!("Connection with the remote signer {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        let (reader, mut writer) = stream.split();
+        let mut reader = BufReader::new(reader);
+
+        // Authenticate the remote signer before serving its requests.
+        let mut challenge = [0_u8; 32];
+        OsRng.fill_bytes(&mut challenge);
+        let challenge_response = SignerResponse::Challenge(hex::encode(&challenge));
+        write_message(&mut writer, &challenge_response).await?;
+        let is_authenticated = match read_message(&mut reader).await {
+            Ok(SignerRequest::Authenticate(response)) => self.secret.verify(&challenge, &response),
+            _ => false,
+        };
+        if !is_authenticated {
+            let response = SignerResponse::Error("Authentication failed.".to_owned());
+            write_message(&mut writer, &response).await?;
+            bail!("Authentication failed");
+        }
+        write_message(&mut writer, &SignerResponse::Authenticated).await?;
+
+        let mut line = String::new();
+        while reader.read_line(&mut line).await? != 0 {
+            let response = match serde_json::from_str(&line) {
+                Ok(request) => self.handle_request(request),
+                Err(e) => SignerResponse::Error(format!("Malformed request: {}", e)),
+            };
+            write_message(&mut writer, &response).await?;
+            line.clear();
+        }
+        Ok(())
+    }
+
+    fn sign_input(&self, request: &SignInputRequest) -> anyhow::Result<btc::InputSignature> {
+        let mut latest_signed_height = self.latest_signed_height.lock().unwrap();
+        if let Some(height) = *latest_signed_height {
+            ensure!(
+                request.payload.block_height >= height,
+                "Payload height goes backwards: {}, the latest signed one is {}.",
+                request.payload.block_height,
+                height
+            );
+        }
+
+        let txin = request
+            .proposal
+            .0
+            .input
+            .get(request.input as usize)
+            .ok_or_else(|| anyhow!("Missing input with index: {}", request.input))?;
+        ensure!(
+            btc::Sha256d::from(txin.previous_output.txid) == request.prev_tx.id(),
+            "Signed input does not spend the given transaction."
+        );
+        match request.proposal.anchoring_payload() {
+            Some(payload) => ensure!(
+                payload == request.payload,
+                "Payload does not correspond to the proposal."
+            ),
+            // In the pay-to-contract mode the payload is committed by the anchoring output.
+            None => {
+                let commitment = request.payload.commitment();
+                let is_committed = std::iter::once(&request.config)
+                    .chain(&request.following_config)
+                    .map(|config| {
+                        config
+                            .committed_redeem_script(Some(&commitment))
+                            .as_ref()
+                            .to_v0_p2wsh()
+                    })
+                    .any(|script_pubkey| {
+                        request
+                            .proposal
+                            .0
+                            .output
+                            .get(0)
+                            .map(|out| &out.script_pubkey)
+                            == Some(&script_pubkey)
+                    });
+                ensure!(
+                    is_committed,
+                    "Payload is not committed by the anchoring output of the proposal."
+                );
+            }
+        }
+
+        let signature = request.sign(&self.key_pool.private_key(&request.public_key)?)?;
+        *latest_signed_height = Some(request.payload.block_height);
+        Ok(signature)
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistent state of the sync with Bitcoin task.
+
+use serde_derive::{Deserialize, Serialize};
+
+use std::{fs, io, path::Path};
+
+use crate::btc;
+
+/// Cursor of the sync with Bitcoin task, which allows to resume the sync after a restart
+/// without looking up the first uncommitted transaction in the whole anchoring chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncState {
+    /// Index of the latest anchoring transaction sent to the Bitcoin network.
+    pub latest_synced_tx_index: u64,
+    /// Identifier of this transaction, which is checked against the anchoring chain
+    /// of the node on startup.
+    pub txid: btc::Sha256d,
+    /// Number of confirmations of this transaction when the state has been saved.
+    pub confirmations: u32,
+}
+
+impl SyncState {
+    /// Loads the state from the TOML file. Returns `None` if the file does not exist.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Option<Self>> {
+        let toml = match fs::read_to_string(path) {
+            Ok(toml) => toml,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        toml::de::from_str(&toml).map(Some).map_err(From::from)
+    }
+
+    /// Saves the state to the TOML file. The file is replaced atomically, so the state
+    /// is not corrupted if the process is interrupted.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, toml::to_string(self)?)?;
+        fs::rename(temp_path, path)?;
+        Ok(())
+    }
+}
@@ -25,7 +25,10 @@ use exonum::{
     messages::{AnyTx, Verified},
     runtime::{InstanceId, SnapshotExt, SUPERVISOR_INSTANCE_ID},
 };
-use exonum_merkledb::{access::Access, Snapshot};
+use exonum_explorer::api::{BlockInfo, BlockQuery};
+#[cfg(feature = "test-helpers")]
+use exonum_explorer_service::ExplorerFactory;
+use exonum_merkledb::{access::Access, ObjectHash, Snapshot};
 use exonum_rust_runtime::api;
 use exonum_supervisor::{ConfigPropose, Supervisor, SupervisorInterface};
 use exonum_testkit::{ApiKind, Spec, TestKit, TestKitApiClient, TestKitBuilder, TestNode};
@@ -35,10 +38,14 @@ use std::collections::BTreeMap;
 
 use crate::{
     api::{
-        AnchoringChainLength, AnchoringProposalState, FindTransactionQuery, IndexQuery, PrivateApi,
-        PublicApi, TransactionProof,
+        AnchoringChainLength, AnchoringProposalState, BlockProof, BlockProofQuery,
+        ContractPayloadProof, FindTransactionQuery, IndexQuery, PrivateApi, PublicApi,
+        TransactionProof,
+    },
+    blockchain::{
+        AddFunds, BtcAnchoringInterface, BumpFee, ReportConfirmation, ReportUnconfirmed, Schema,
+        SignInput, SignInputs,
     },
-    blockchain::{AddFunds, BtcAnchoringInterface, Schema, SignInput},
     btc,
     config::Config,
     proto::AnchoringKeys,
@@ -159,9 +166,13 @@ impl AnchoringTestKit {
             ..Config::default()
         };
 
-        let inner = TestKitBuilder::validator()
+        let builder = TestKitBuilder::validator()
             .with_keys(validator_keys)
-            .with(Supervisor::simple())
+            .with(Supervisor::simple());
+        // The explorer service provides the block hashes to the `PrivateApi` clients.
+        #[cfg(feature = "test-helpers")]
+        let builder = builder.with(Spec::new(ExplorerFactory).with_default_instance());
+        let inner = builder
             .with(Spec::new(BtcAnchoringService).with_instance(
                 ANCHORING_INSTANCE_ID,
                 ANCHORING_INSTANCE_NAME,
@@ -202,6 +213,58 @@ impl AnchoringTestKit {
         &self,
         node: &TestNode,
     ) -> Result<Vec<Verified<AnyTx>>, btc::BuilderError> {
+        let service_keypair = node.service_keypair();
+        Ok(self
+            .input_signatures_for_node(node)?
+            .into_iter()
+            .map(|sign_input| service_keypair.sign_input(ANCHORING_INSTANCE_ID, sign_input))
+            .collect())
+    }
+
+    /// Creates the transaction with signatures for all inputs of the proposed anchoring
+    /// transaction signed by the specified node, if there is a proposal.
+    pub fn create_batched_signature_tx_for_node(
+        &self,
+        node: &TestNode,
+    ) -> Result<Option<Verified<AnyTx>>, btc::BuilderError> {
+        let sign_inputs = self.input_signatures_for_node(node)?;
+        let txid = match sign_inputs.first() {
+            Some(sign_input) => sign_input.txid,
+            None => return Ok(None),
+        };
+        let sign_inputs = SignInputs {
+            txid,
+            input_signatures: sign_inputs
+                .into_iter()
+                .map(|sign_input| sign_input.input_signature)
+                .collect(),
+        };
+        Ok(Some(
+            node.service_keypair()
+                .sign_inputs(ANCHORING_INSTANCE_ID, sign_inputs),
+        ))
+    }
+
+    /// Creates the transactions with signatures for all inputs of the proposed anchoring
+    /// transaction signed by all of anchoring nodes.
+    pub fn create_batched_signature_txs(&self) -> Vec<Verified<AnyTx>> {
+        self.actual_anchoring_config()
+            .anchoring_keys
+            .into_iter()
+            .filter_map(|anchoring_keys| {
+                let node = self
+                    .find_node_by_service_key(anchoring_keys.service_key)
+                    .unwrap();
+                self.create_batched_signature_tx_for_node(node).unwrap()
+            })
+            .collect()
+    }
+
+    /// Signs each input of the proposed anchoring transaction by the specified node.
+    fn input_signatures_for_node(
+        &self,
+        node: &TestNode,
+    ) -> Result<Vec<SignInput>, btc::BuilderError> {
         let service_keypair = node.service_keypair();
         let snapshot = self.inner.snapshot();
         let schema = get_anchoring_schema(&snapshot);
@@ -217,25 +280,26 @@ impl AnchoringTestKit {
                 .1;
             let btc_private_key = self.anchoring_nodes.private_key(&bitcoin_key);
 
-            let redeem_script = actual_config.redeem_script();
-            let mut signer = p2wsh::InputSigner::new(redeem_script);
             for (index, proposal_input) in proposal_inputs.iter().enumerate() {
-                let signature = signer
+                let commitment = schema.contract_commitment(proposal_input);
+                let private_key = match commitment {
+                    Some(commitment) => btc::contract_private_key(&btc_private_key, &commitment),
+                    None => btc_private_key.clone(),
+                };
+                let redeem_script = actual_config.committed_redeem_script(commitment.as_ref());
+                let signature = p2wsh::InputSigner::new(redeem_script)
                     .sign_input(
                         TxInRef::new(proposal.as_ref(), index),
                         proposal_input.as_ref(),
-                        &btc_private_key.0.key,
+                        &private_key.0.key,
                     )
                     .unwrap();
 
-                signatures.push(service_keypair.sign_input(
-                    ANCHORING_INSTANCE_ID,
-                    SignInput {
-                        input: index as u32,
-                        input_signature: signature.into(),
-                        txid: proposal.id(),
-                    },
-                ));
+                signatures.push(SignInput {
+                    input: index as u32,
+                    input_signature: signature.into(),
+                    txid: proposal.id(),
+                });
             }
         }
         Ok(signatures)
@@ -292,6 +356,62 @@ impl AnchoringTestKit {
             .collect()
     }
 
+    /// Creates the transactions with a request to replace the anchoring transaction
+    /// with the specified identifier by the one with a given fee per virtual byte.
+    pub fn create_fee_bump_txs(&self, txid: btc::Sha256d, fee: u64) -> Vec<Verified<AnyTx>> {
+        let bump_fee = BumpFee { txid, fee };
+        self.actual_anchoring_config()
+            .anchoring_keys
+            .into_iter()
+            .map(move |anchoring_keys| {
+                let node_keypair = self
+                    .find_node_by_service_key(anchoring_keys.service_key)
+                    .expect("Unable to find node by service key")
+                    .service_keypair();
+
+                node_keypair.bump_fee(ANCHORING_INSTANCE_ID, bump_fee.clone())
+            })
+            .collect()
+    }
+
+    /// Creates reports that the given anchoring transaction is unconfirmed for all
+    /// anchoring nodes.
+    pub fn create_unconfirmed_report_txs(&self, txid: btc::Sha256d) -> Vec<Verified<AnyTx>> {
+        let report = ReportUnconfirmed { txid };
+        self.actual_anchoring_config()
+            .anchoring_keys
+            .into_iter()
+            .map(move |anchoring_keys| {
+                let node_keypair = self
+                    .find_node_by_service_key(anchoring_keys.service_key)
+                    .expect("Unable to find node by service key")
+                    .service_keypair();
+
+                node_keypair.report_unconfirmed(ANCHORING_INSTANCE_ID, report.clone())
+            })
+            .collect()
+    }
+
+    /// Creates the reports that the anchoring transaction has been committed to the Bitcoin
+    /// blockchain from the actual anchoring nodes.
+    pub fn create_confirmation_report_txs(
+        &self,
+        report: ReportConfirmation,
+    ) -> Vec<Verified<AnyTx>> {
+        self.actual_anchoring_config()
+            .anchoring_keys
+            .into_iter()
+            .map(move |anchoring_keys| {
+                let node_keypair = self
+                    .find_node_by_service_key(anchoring_keys.service_key)
+                    .expect("Unable to find node by service key")
+                    .service_keypair();
+
+                node_keypair.report_confirmation(ANCHORING_INSTANCE_ID, report.clone())
+            })
+            .collect()
+    }
+
     /// Creates configuration change transaction for simple supervisor.
     pub fn create_config_change_tx(&self, proposal: ConfigPropose) -> Verified<AnyTx> {
         let initiator_id = self.inner.network().us().validator_id().unwrap();
@@ -407,6 +527,13 @@ impl PublicApi for TestKitApiClient {
             .await
     }
 
+    async fn block_proof(&self, height: Height) -> api::Result<BlockProof> {
+        self.public(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .query(&BlockProofQuery { height })
+            .get("block-proof")
+            .await
+    }
+
     async fn config(&self) -> api::Result<Config> {
         self.public(ApiKind::Service(ANCHORING_INSTANCE_NAME))
             .get("config")
@@ -418,6 +545,10 @@ impl PublicApi for TestKitApiClient {
 impl PrivateApi for TestKitApiClient {
     type Error = api::Error;
 
+    fn is_not_found(error: &Self::Error) -> bool {
+        error.http_code == api::HttpStatusCode::NOT_FOUND
+    }
+
     async fn sign_input(&self, sign_input: SignInput) -> api::Result<Hash> {
         self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
             .query(&sign_input)
@@ -425,6 +556,13 @@ impl PrivateApi for TestKitApiClient {
             .await
     }
 
+    async fn sign_inputs(&self, sign_inputs: SignInputs) -> api::Result<Hash> {
+        self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .query(&sign_inputs)
+            .post("sign-inputs")
+            .await
+    }
+
     async fn add_funds(&self, transaction: btc::Transaction) -> api::Result<Hash> {
         self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
             .query(&transaction)
@@ -432,18 +570,66 @@ impl PrivateApi for TestKitApiClient {
             .await
     }
 
+    async fn bump_fee(&self, bump_fee: BumpFee) -> api::Result<Hash> {
+        self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .query(&bump_fee)
+            .post("bump-fee")
+            .await
+    }
+
+    async fn report_unconfirmed(&self, report: ReportUnconfirmed) -> api::Result<Hash> {
+        self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .query(&report)
+            .post("report-unconfirmed")
+            .await
+    }
+
+    async fn report_confirmation(&self, report: ReportConfirmation) -> api::Result<Hash> {
+        self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .query(&report)
+            .post("report-confirmation")
+            .await
+    }
+
     async fn anchoring_proposal(&self) -> api::Result<AnchoringProposalState> {
         self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
             .get("anchoring-proposal")
             .await
     }
 
+    async fn anchoring_proposal_psbt(&self) -> api::Result<Option<btc::Psbt>> {
+        self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .get("anchoring-proposal/psbt")
+            .await
+    }
+
+    async fn sign_psbt(&self, psbt: btc::Psbt) -> api::Result<Vec<Hash>> {
+        self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .query(&psbt)
+            .post("sign-psbt")
+            .await
+    }
+
     async fn config(&self) -> api::Result<Config> {
         self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
             .get("config")
             .await
     }
 
+    async fn following_config(&self) -> api::Result<Option<Config>> {
+        self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .get("config/following")
+            .await
+    }
+
+    async fn block_hash(&self, height: Height) -> api::Result<Hash> {
+        self.public(ApiKind::Explorer)
+            .query(&BlockQuery::new(height))
+            .get::<BlockInfo>("v1/block")
+            .await
+            .map(|info| info.block.object_hash())
+    }
+
     async fn transaction_with_index(&self, index: u64) -> api::Result<Option<btc::Transaction>> {
         self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
             .query(&IndexQuery { index })
@@ -451,6 +637,19 @@ impl PrivateApi for TestKitApiClient {
             .await
     }
 
+    async fn transaction_payload(&self, index: u64) -> api::Result<Option<btc::Payload>> {
+        self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .query(&IndexQuery { index })
+            .get("transaction/payload")
+            .await
+    }
+
+    async fn funding_transactions(&self) -> api::Result<Vec<btc::Transaction>> {
+        self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
+            .get("funding-transactions")
+            .await
+    }
+
     async fn transactions_count(&self) -> api::Result<AnchoringChainLength> {
         self.private(ApiKind::Service(ANCHORING_INSTANCE_NAME))
             .get("transactions-count")
@@ -482,3 +681,19 @@ impl ValidateProof for TransactionProof {
         Ok(entry)
     }
 }
+
+impl ValidateProof for ContractPayloadProof {
+    type Output = Option<(btc::Sha256d, btc::Payload)>;
+
+    fn validate(self, validator_keys: &[PublicKey]) -> anyhow::Result<Self::Output> {
+        self.index_proof.verify(validator_keys)?;
+
+        let entry = self
+            .payload_proof
+            .check()?
+            .entries()
+            .map(|(txid, payload)| (*txid, payload.clone()))
+            .next();
+        Ok(entry)
+    }
+}
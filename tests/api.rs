@@ -12,10 +12,10 @@
 // limitations under the License.
 
 use btc_transaction_utils::{p2wsh, TxInRef};
-use exonum::helpers::Height;
+use exonum::{helpers::Height, merkledb::ObjectHash, runtime::SnapshotExt};
 use exonum_btc_anchoring::{
     api::{AnchoringProposalState, PrivateApi, PublicApi},
-    blockchain::SignInput,
+    blockchain::{BitcoinConfirmation, ReportConfirmation, SignInput, SignInputs},
     btc,
     test_helpers::{
         create_fake_funding_transaction, get_anchoring_schema, AnchoringTestKit, ValidateProof,
@@ -307,6 +307,234 @@ async fn find_transaction_configuration_change() {
     );
 }
 
+#[tokio::test]
+async fn block_proof() {
+    let (mut anchoring_testkit, anchoring_api) = init_testkit();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // Create a several anchoring transactions
+    for i in 1..=3 {
+        anchoring_testkit.inner.create_block_with_transactions(
+            anchoring_testkit
+                .create_signature_txs()
+                .into_iter()
+                .flatten(),
+        );
+        anchoring_testkit
+            .inner
+            .create_blocks_until(Height(anchoring_interval * i));
+    }
+
+    let snapshot = anchoring_testkit.inner.snapshot();
+    let block_hashes = snapshot.for_core().block_hashes_by_height();
+    let latest_anchored_height = get_anchoring_schema(&snapshot)
+        .latest_anchored_height()
+        .unwrap();
+    // The first anchoring transaction commits to the anchoring configuration, so the
+    // genesis block is committed by the following anchoring transaction.
+    for height in 0..=latest_anchored_height.0 {
+        let proof = anchoring_api
+            .client()
+            .block_proof(Height(height))
+            .await
+            .unwrap();
+        let payload = proof.payload;
+        assert_eq!(
+            proof.anchoring_transaction.anchoring_payload(),
+            Some(payload.clone())
+        );
+        assert!(proof.blocks_start.0 <= height && height <= payload.block_height.0);
+
+        let checked_proof = proof
+            .block_hash_proof
+            .check_against_hash(payload.blocks_root.unwrap())
+            .unwrap();
+        assert_eq!(
+            checked_proof.entries(),
+            [(
+                height - proof.blocks_start.0,
+                block_hashes.get(height).unwrap()
+            )]
+        );
+    }
+    // Check that the proof for the not yet anchored block is unavailable.
+    anchoring_api
+        .client()
+        .block_proof(latest_anchored_height.next())
+        .await
+        .unwrap_err();
+}
+
+#[tokio::test]
+async fn block_proof_config_change() {
+    let (mut anchoring_testkit, anchoring_api) = init_testkit();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+
+    // Change the configuration without changing the anchoring address.
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.transaction_fee += 1;
+    anchoring_testkit.inner.create_block_with_transaction(
+        anchoring_testkit.create_config_change_tx(
+            ConfigPropose::new(0, anchoring_testkit.inner.height().next())
+                .service_config(ANCHORING_INSTANCE_ID, config.clone()),
+        ),
+    );
+    // Anchor the heights across the configuration change.
+    for i in 2..=3 {
+        anchoring_testkit
+            .inner
+            .create_blocks_until(Height(anchoring_interval * i));
+        anchoring_testkit.inner.create_block_with_transactions(
+            anchoring_testkit
+                .create_signature_txs()
+                .into_iter()
+                .flatten(),
+        );
+    }
+
+    let snapshot = anchoring_testkit.inner.snapshot();
+    let block_hashes = snapshot.for_core().block_hashes_by_height();
+    let anchoring_schema = get_anchoring_schema(&snapshot);
+    let config_change_tx = anchoring_schema.transactions_chain.get(2).unwrap();
+    let config_change_payload = config_change_tx.anchoring_payload().unwrap();
+    assert_eq!(
+        config_change_payload.config_hash,
+        Some(config.object_hash())
+    );
+    assert_eq!(config_change_payload.blocks_root, None);
+
+    // The blocks anchored by the transaction with the configuration hash are committed
+    // by the following anchoring transaction.
+    let following_tx = anchoring_schema.transactions_chain.get(3).unwrap();
+    for height in anchoring_interval + 1..=anchoring_interval * 3 {
+        let proof = anchoring_api
+            .client()
+            .block_proof(Height(height))
+            .await
+            .unwrap();
+        assert_eq!(proof.anchoring_transaction, following_tx);
+        assert_eq!(proof.blocks_start, Height(anchoring_interval + 1));
+
+        let checked_proof = proof
+            .block_hash_proof
+            .check_against_hash(proof.payload.blocks_root.unwrap())
+            .unwrap();
+        assert_eq!(
+            checked_proof.entries(),
+            [(
+                height - proof.blocks_start.0,
+                block_hashes.get(height).unwrap()
+            )]
+        );
+    }
+}
+
+#[tokio::test]
+async fn find_transaction_pay_to_contract() {
+    let (mut anchoring_testkit, anchoring_api) = init_testkit();
+
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.pay_to_contract = true;
+    anchoring_testkit.inner.create_block_with_transaction(
+        anchoring_testkit.create_config_change_tx(
+            ConfigPropose::new(0, anchoring_testkit.inner.height().next())
+                .service_config(ANCHORING_INSTANCE_ID, config),
+        ),
+    );
+    anchoring_testkit.inner.create_block();
+
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx = anchoring_testkit.last_anchoring_tx().unwrap();
+    assert_eq!(tx.anchoring_payload(), None);
+
+    let validator_keys = anchoring_testkit
+        .inner
+        .consensus_config()
+        .validator_keys
+        .into_iter()
+        .map(|key| key.consensus_key)
+        .collect::<Vec<_>>();
+    let mut proof = anchoring_api.client().find_transaction(None).await.unwrap();
+    // The payload committed by the anchoring output is proven separately.
+    let contract_payload = proof
+        .contract_payload_proof
+        .take()
+        .unwrap()
+        .validate(&validator_keys)
+        .unwrap();
+    assert_eq!(
+        proof.validate(&validator_keys).unwrap().map(|(_, tx)| tx),
+        Some(tx.clone())
+    );
+
+    let snapshot = anchoring_testkit.inner.snapshot();
+    let payload = get_anchoring_schema(&snapshot)
+        .anchoring_payload(&tx)
+        .unwrap();
+    assert_eq!(contract_payload, Some((tx.id(), payload)));
+}
+
+#[tokio::test]
+async fn find_transaction_bitcoin_confirmation() {
+    let (mut anchoring_testkit, anchoring_api) = init_testkit();
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx = anchoring_testkit.last_anchoring_tx().unwrap();
+
+    let proof = anchoring_api.client().find_transaction(None).await.unwrap();
+    assert_eq!(proof.bitcoin_confirmation, None);
+
+    let report = ReportConfirmation {
+        index: 0,
+        txid: tx.id(),
+        block_hash: btc::Sha256d::new([1; 32]),
+        block_height: 100,
+        confirmations: 6,
+    };
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_confirmation_report_txs(report));
+
+    let proof = anchoring_api.client().find_transaction(None).await.unwrap();
+    assert_eq!(
+        proof.bitcoin_confirmation,
+        Some(BitcoinConfirmation {
+            block_hash: btc::Sha256d::new([1; 32]),
+            block_height: 100,
+            confirmations: 6,
+        })
+    );
+}
+
 #[tokio::test]
 async fn actual_config() {
     let (anchoring_testkit, anchoring_api) = init_testkit();
@@ -326,8 +554,12 @@ async fn anchoring_proposal_ok() {
     assert_eq!(
         anchoring_api.client().anchoring_proposal().await.unwrap(),
         AnchoringProposalState::Available {
+            payload: proposal.0.anchoring_payload().unwrap(),
+            input_commitments: vec![None],
             transaction: proposal.0,
             inputs: proposal.1,
+            package_fee_rate: None,
+            sweep_address: None,
         }
     );
 }
@@ -350,6 +582,68 @@ async fn anchoring_proposal_none() {
     );
 }
 
+#[tokio::test]
+async fn anchoring_proposal_sweep() {
+    let (mut anchoring_testkit, anchoring_api) = init_testkit();
+
+    // Request the sweep of the anchoring funds.
+    let public_key = btc::gen_keypair(bitcoin::Network::Testnet).0;
+    let sweep_address = btc::Address(bitcoin::Address::p2wpkh(
+        &public_key.0,
+        bitcoin::Network::Testnet,
+    ));
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.sweep_address = Some(sweep_address.clone());
+    anchoring_testkit.inner.create_block_with_transaction(
+        anchoring_testkit.create_config_change_tx(
+            ConfigPropose::new(0, anchoring_testkit.inner.height().next())
+                .service_config(ANCHORING_INSTANCE_ID, config),
+        ),
+    );
+    anchoring_testkit.inner.create_block();
+
+    match anchoring_api.client().anchoring_proposal().await.unwrap() {
+        AnchoringProposalState::Available {
+            sweep_address: address,
+            ..
+        } => assert_eq!(address, Some(sweep_address)),
+        state => panic!("Unexpected proposal state: {:?}", state),
+    }
+
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let txid = anchoring_testkit.last_anchoring_tx().unwrap().id();
+    assert_eq!(
+        anchoring_api.client().anchoring_proposal().await.unwrap(),
+        AnchoringProposalState::Swept { txid }
+    );
+}
+
+#[tokio::test]
+async fn anchoring_proposal_paused() {
+    let (mut anchoring_testkit, anchoring_api) = init_testkit();
+
+    // Pause the anchoring.
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.paused = true;
+    anchoring_testkit.inner.create_block_with_transaction(
+        anchoring_testkit.create_config_change_tx(
+            ConfigPropose::new(0, anchoring_testkit.inner.height().next())
+                .service_config(ANCHORING_INSTANCE_ID, config),
+        ),
+    );
+    anchoring_testkit.inner.create_block();
+
+    assert_eq!(
+        anchoring_api.client().anchoring_proposal().await.unwrap(),
+        AnchoringProposalState::Paused
+    );
+}
+
 #[tokio::test]
 async fn anchoring_proposal_err_without_initial_funds() {
     let mut anchoring_testkit = AnchoringTestKit::new(4, 5);
@@ -373,7 +667,7 @@ async fn anchoring_proposal_err_insufficient_funds() {
     assert_eq!(
         state,
         AnchoringProposalState::InsufficientFunds {
-            total_fee: 1530,
+            total_fee: 2780,
             balance: 20
         }
     );
@@ -429,6 +723,105 @@ async fn sign_input() {
         .expect("Transaction should be successful");
 }
 
+#[tokio::test]
+async fn sign_inputs() {
+    let (mut anchoring_testkit, anchoring_api) = init_testkit();
+
+    let config = anchoring_testkit.actual_anchoring_config();
+    let bitcoin_public_key = config
+        .find_bitcoin_key(&anchoring_testkit.inner.us().service_keypair().public_key())
+        .unwrap()
+        .1;
+    let bitcoin_private_key = anchoring_testkit.node_private_key(&bitcoin_public_key);
+
+    let (proposal, proposal_inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    let input_signatures = proposal_inputs
+        .iter()
+        .enumerate()
+        .map(|(index, proposal_input)| {
+            p2wsh::InputSigner::new(config.redeem_script())
+                .sign_input(
+                    TxInRef::new(proposal.as_ref(), index),
+                    proposal_input.as_ref(),
+                    &bitcoin_private_key.0.key,
+                )
+                .unwrap()
+                .into()
+        })
+        .collect::<Vec<_>>();
+
+    // Signatures of the other transaction are refused.
+    anchoring_api
+        .client()
+        .sign_inputs(SignInputs {
+            txid: proposal_inputs[0].id(),
+            input_signatures: input_signatures.clone(),
+        })
+        .await
+        .unwrap_err();
+
+    let tx_hash = anchoring_api
+        .client()
+        .sign_inputs(SignInputs {
+            txid: proposal.id(),
+            input_signatures,
+        })
+        .await
+        .unwrap();
+
+    anchoring_testkit
+        .inner
+        .create_block_with_tx_hashes(&[tx_hash])[0]
+        .status()
+        .expect("Transaction should be successful");
+}
+
+#[tokio::test]
+async fn sign_psbt() {
+    let (mut anchoring_testkit, anchoring_api) = init_testkit();
+
+    let config = anchoring_testkit.actual_anchoring_config();
+    let bitcoin_public_key = config
+        .find_bitcoin_key(&anchoring_testkit.inner.us().service_keypair().public_key())
+        .unwrap()
+        .1;
+    let bitcoin_private_key = anchoring_testkit.node_private_key(&bitcoin_public_key);
+
+    let (proposal, proposal_inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    let mut psbt = anchoring_api
+        .client()
+        .anchoring_proposal_psbt()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(psbt.id(), proposal.id());
+    assert_eq!(psbt.0.inputs.len(), proposal_inputs.len());
+    assert_eq!(
+        psbt.0.inputs[0].witness_script,
+        Some(config.redeem_script().as_ref().clone())
+    );
+
+    // The PSBT without signatures of the node key is rejected.
+    anchoring_api
+        .client()
+        .sign_psbt(psbt.clone())
+        .await
+        .unwrap_err();
+
+    // Sign the PSBT offline.
+    psbt.sign(&config.redeem_script(), &bitcoin_private_key)
+        .unwrap();
+    let tx_hashes = anchoring_api.client().sign_psbt(psbt).await.unwrap();
+    assert_eq!(tx_hashes.len(), proposal_inputs.len());
+
+    anchoring_testkit
+        .inner
+        .create_block_with_tx_hashes(&tx_hashes)
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful");
+}
+
 #[tokio::test]
 async fn add_funds_ok() {
     let anchoring_interval = 5;
@@ -0,0 +1,586 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bitcoin::Network;
+use bitcoin_hashes::{sha256d, Hash};
+use exonum_btc_anchoring::{
+    btc,
+    sync::{
+        AgreementPolicy, BitcoinRelay, CompositeRelay, CompositeRelayError, ElectrumError,
+        ElectrumRelay, EsploraError, EsploraRelay, TransactionBlock, TransactionStatus,
+    },
+    test_helpers::create_fake_funding_transaction,
+};
+use mockito::mock;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+fn fake_transaction() -> btc::Transaction {
+    let (public_key, _) = btc::gen_keypair(Network::Testnet);
+    let address = btc::Address(bitcoin::Address::p2wpkh(&public_key.0, Network::Testnet));
+    create_fake_funding_transaction(&address, 10_000)
+}
+
+/// Spawns the fake Electrum server, which responds to each request by the given handler,
+/// and returns its address. The handler returns either `result` or `error` field
+/// of the response.
+async fn spawn_electrum_server(handler: fn(&str, &Value) -> Value) -> String {
+    let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let mut response = handler(request["method"].as_str().unwrap(), &request["params"]);
+            response["jsonrpc"] = json!("2.0");
+            response["id"] = request["id"].clone();
+
+            let mut response = response.to_string();
+            response.push('\n');
+            stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+        }
+    });
+    address
+}
+
+/// Creates the deterministic transaction, since the handler of the fake Electrum server
+/// cannot capture the environment.
+fn electrum_transaction(value: u64) -> btc::Transaction {
+    bitcoin::Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![bitcoin::TxIn {
+            previous_output: bitcoin::OutPoint::null(),
+            script_sig: bitcoin::Script::new(),
+            sequence: 0,
+            witness: vec![],
+        }],
+        output: vec![bitcoin::TxOut {
+            value,
+            script_pubkey: bitcoin::Script::new(),
+        }],
+    }
+    .into()
+}
+
+#[tokio::test]
+async fn esplora_send_transaction() {
+    let transaction = fake_transaction();
+    let _mock = mock("POST", "/tx")
+        .match_body(transaction.to_string().as_str())
+        .with_body(transaction.id().to_string())
+        .create();
+
+    let relay = EsploraRelay::new(mockito::server_url());
+    let txid = relay.send_transaction(&transaction).await.unwrap();
+    assert_eq!(txid, transaction.id());
+}
+
+#[tokio::test]
+async fn esplora_send_transaction_rejected() {
+    let transaction = fake_transaction();
+    let _mock = mock("POST", "/tx")
+        .with_status(400)
+        .with_body("sendrawtransaction RPC error: bad-txns-inputs-missingorspent")
+        .create();
+
+    let relay = EsploraRelay::new(format!("{}/", mockito::server_url()));
+    match relay.send_transaction(&transaction).await.unwrap_err() {
+        EsploraError::Api { status, message } => {
+            assert_eq!(status.as_u16(), 400);
+            assert!(message.contains("missingorspent"));
+        }
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn esplora_transaction_status() {
+    let relay = EsploraRelay::new(mockito::server_url());
+    let unknown_id = fake_transaction().id();
+    let mempool_id = fake_transaction().id();
+    let committed_id = fake_transaction().id();
+
+    let _mocks = vec![
+        mock("GET", format!("/tx/{}/status", unknown_id).as_str())
+            .with_status(404)
+            .with_body("Transaction not found")
+            .create(),
+        mock("GET", format!("/tx/{}/status", mempool_id).as_str())
+            .with_body(r#"{"confirmed":false}"#)
+            .create(),
+        mock("GET", format!("/tx/{}/status", committed_id).as_str())
+            .with_body(r#"{"confirmed":true,"block_height":100,"block_hash":"00","block_time":0}"#)
+            .create(),
+        mock("GET", "/blocks/tip/height").with_body("105").create(),
+    ];
+
+    assert_eq!(
+        relay.transaction_status(unknown_id).await.unwrap(),
+        TransactionStatus::Unknown
+    );
+    assert_eq!(
+        relay.transaction_status(mempool_id).await.unwrap(),
+        TransactionStatus::Mempool
+    );
+    assert_eq!(
+        relay.transaction_status(committed_id).await.unwrap(),
+        TransactionStatus::Committed(6)
+    );
+}
+
+#[tokio::test]
+async fn electrum_send_transaction() {
+    let address = spawn_electrum_server(|method, params| {
+        assert_eq!(method, "blockchain.transaction.broadcast");
+        let transaction: btc::Transaction = params[0].as_str().unwrap().parse().unwrap();
+        json!({ "result": transaction.id().to_string() })
+    })
+    .await;
+
+    let transaction = fake_transaction();
+    let relay = ElectrumRelay::new(address);
+    let txid = relay.send_transaction(&transaction).await.unwrap();
+    assert_eq!(txid, transaction.id());
+}
+
+#[tokio::test]
+async fn electrum_send_transaction_rejected() {
+    let address = spawn_electrum_server(|_, _| {
+        json!({
+            "error": {
+                "code": 1,
+                "message": "the transaction was rejected by network rules.",
+            }
+        })
+    })
+    .await;
+
+    let relay = ElectrumRelay::new(address);
+    match relay
+        .send_transaction(&fake_transaction())
+        .await
+        .unwrap_err()
+    {
+        ElectrumError::Rpc { code, message } => {
+            assert_eq!(code, 1);
+            assert!(message.contains("rejected"));
+        }
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn electrum_transaction_status() {
+    let address = spawn_electrum_server(|method, params| {
+        let unknown = electrum_transaction(1_000);
+        let mempool = electrum_transaction(2_000);
+        let committed = electrum_transaction(3_000);
+
+        assert_eq!(method, "blockchain.transaction.get");
+        assert_eq!(params[1], json!(true));
+        let txid = params[0].as_str().unwrap();
+        if txid == unknown.id().to_string() {
+            json!({
+                "error": { "code": 2, "message": "daemon error: DaemonError({'code': -5, \
+                    'message': 'No such mempool or blockchain transaction.'})" }
+            })
+        } else if txid == mempool.id().to_string() {
+            json!({ "result": { "hex": mempool.to_string() } })
+        } else if txid == committed.id().to_string() {
+            json!({
+                "result": { "hex": committed.to_string(), "blockhash": "00", "confirmations": 6 }
+            })
+        } else {
+            json!({ "error": { "code": 2, "message": "daemon error: connection refused" } })
+        }
+    })
+    .await;
+
+    let relay = ElectrumRelay::new(address);
+    assert_eq!(
+        relay
+            .transaction_status(electrum_transaction(1_000).id())
+            .await
+            .unwrap(),
+        TransactionStatus::Unknown
+    );
+    assert_eq!(
+        relay
+            .transaction_status(electrum_transaction(2_000).id())
+            .await
+            .unwrap(),
+        TransactionStatus::Mempool
+    );
+    assert_eq!(
+        relay
+            .transaction_status(electrum_transaction(3_000).id())
+            .await
+            .unwrap(),
+        TransactionStatus::Committed(6)
+    );
+    // Other errors of the server should not be treated as the unknown transaction.
+    match relay
+        .transaction_status(electrum_transaction(4_000).id())
+        .await
+        .unwrap_err()
+    {
+        ElectrumError::Rpc { code, message } => {
+            assert_eq!(code, 2);
+            assert!(message.contains("connection refused"));
+        }
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn electrum_transaction_block() {
+    let address = spawn_electrum_server(|method, params| {
+        let committed = electrum_transaction(3_000);
+        let block_hash = "000000000000000000093ce5d2e6df3f9b0c4e1e7a0a1c8d9d3a3a6a1c0b3a2f";
+
+        match method {
+            "blockchain.transaction.get" => json!({
+                "result": {
+                    "hex": committed.to_string(),
+                    "blockhash": block_hash,
+                    "confirmations": 6,
+                }
+            }),
+            "blockchain.headers.subscribe" => json!({ "result": { "height": 105, "hex": "00" } }),
+            "blockchain.transaction.get_merkle" => {
+                assert_eq!(params[0].as_str().unwrap(), committed.id().to_string());
+                assert_eq!(params[1], json!(100));
+                json!({ "result": { "block_height": 100, "merkle": [], "pos": 1 } })
+            }
+            _ => panic!("Unexpected method: {}", method),
+        }
+    })
+    .await;
+
+    let relay = ElectrumRelay::new(address);
+    let block = relay
+        .transaction_block(electrum_transaction(3_000).id())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(block.height, 100);
+    let block_hash: bitcoin::hash_types::BlockHash =
+        "000000000000000000093ce5d2e6df3f9b0c4e1e7a0a1c8d9d3a3a6a1c0b3a2f"
+            .parse()
+            .unwrap();
+    assert_eq!(block.hash, btc::Sha256d(block_hash.into()));
+}
+
+/// Relay which reports the same status for any transaction or fails if the status is absent.
+#[derive(Debug, Clone)]
+struct FakeRelay {
+    status: Option<TransactionStatus>,
+    block: Option<TransactionBlock>,
+    requests: Arc<AtomicUsize>,
+}
+
+impl FakeRelay {
+    fn new(status: impl Into<Option<TransactionStatus>>) -> Self {
+        Self {
+            status: status.into(),
+            block: None,
+            requests: Arc::default(),
+        }
+    }
+
+    /// Makes the relay report the committed transaction in the block with the given height.
+    fn with_block(height: u32) -> Self {
+        Self {
+            block: Some(fake_block(height)),
+            ..Self::new(TransactionStatus::Committed(1))
+        }
+    }
+
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl BitcoinRelay for FakeRelay {
+    type Error = anyhow::Error;
+
+    async fn send_transaction(
+        &self,
+        transaction: &btc::Transaction,
+    ) -> Result<btc::Sha256d, Self::Error> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.status
+            .map(|_| transaction.id())
+            .ok_or_else(|| anyhow!("Relay is unavailable"))
+    }
+
+    async fn transaction_status(
+        &self,
+        _id: btc::Sha256d,
+    ) -> Result<TransactionStatus, Self::Error> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.status.ok_or_else(|| anyhow!("Relay is unavailable"))
+    }
+
+    async fn transaction_block(
+        &self,
+        _id: btc::Sha256d,
+    ) -> Result<Option<TransactionBlock>, Self::Error> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.status
+            .map(|_| self.block)
+            .ok_or_else(|| anyhow!("Relay is unavailable"))
+    }
+}
+
+/// Relay which rejects any transaction in the same way as the Electrum server.
+#[derive(Debug, Clone, Default)]
+struct RejectingRelay {
+    requests: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl BitcoinRelay for RejectingRelay {
+    type Error = ElectrumError;
+
+    fn is_rejection(error: &Self::Error) -> bool {
+        ElectrumRelay::is_rejection(error)
+    }
+
+    async fn send_transaction(
+        &self,
+        _transaction: &btc::Transaction,
+    ) -> Result<btc::Sha256d, Self::Error> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        Err(ElectrumError::Rpc {
+            code: 1,
+            message: "the transaction was rejected by network rules".to_owned(),
+        })
+    }
+
+    async fn transaction_status(
+        &self,
+        _id: btc::Sha256d,
+    ) -> Result<TransactionStatus, Self::Error> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        Ok(TransactionStatus::Unknown)
+    }
+}
+
+fn fake_block(height: u32) -> TransactionBlock {
+    TransactionBlock {
+        hash: btc::Sha256d(sha256d::Hash::hash(&height.to_le_bytes())),
+        height,
+    }
+}
+
+#[tokio::test]
+async fn composite_relay_majority() {
+    let id = fake_transaction().id();
+    let relay = CompositeRelay::new(AgreementPolicy::Majority)
+        .with_relay("a", FakeRelay::new(TransactionStatus::Committed(5)))
+        .with_relay("b", FakeRelay::new(TransactionStatus::Unknown))
+        .with_relay("c", FakeRelay::new(TransactionStatus::Committed(3)));
+    assert_eq!(
+        relay.transaction_status(id).await.unwrap(),
+        TransactionStatus::Committed(3)
+    );
+
+    // The failed relay is counted as the disagreed one.
+    let relay = CompositeRelay::new(AgreementPolicy::Majority)
+        .with_relay("a", FakeRelay::new(TransactionStatus::Mempool))
+        .with_relay("b", FakeRelay::new(TransactionStatus::Committed(1)))
+        .with_relay("c", FakeRelay::new(None));
+    match relay.transaction_status(id).await.unwrap_err() {
+        CompositeRelayError::NoAgreement { agreed, required } => {
+            assert_eq!(agreed, 1);
+            assert_eq!(required, 2);
+        }
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn composite_relay_min_confirmations() {
+    let id = fake_transaction().id();
+    let relay = CompositeRelay::new(AgreementPolicy::MinConfirmations)
+        .with_relay("a", FakeRelay::new(TransactionStatus::Committed(5)))
+        .with_relay("b", FakeRelay::new(None))
+        .with_relay("c", FakeRelay::new(TransactionStatus::Committed(2)));
+    assert_eq!(
+        relay.transaction_status(id).await.unwrap(),
+        TransactionStatus::Committed(2)
+    );
+
+    let relay = CompositeRelay::new(AgreementPolicy::MinConfirmations)
+        .with_relay("a", FakeRelay::new(TransactionStatus::Committed(5)))
+        .with_relay("b", FakeRelay::new(TransactionStatus::Mempool));
+    assert_eq!(
+        relay.transaction_status(id).await.unwrap(),
+        TransactionStatus::Mempool
+    );
+}
+
+#[tokio::test]
+async fn composite_relay_transaction_block() {
+    let id = fake_transaction().id();
+    let relay = CompositeRelay::new(AgreementPolicy::Majority)
+        .with_relay("a", FakeRelay::with_block(100))
+        .with_relay("b", FakeRelay::with_block(101))
+        .with_relay("c", FakeRelay::with_block(100));
+    assert_eq!(
+        relay.transaction_block(id).await.unwrap(),
+        Some(fake_block(100))
+    );
+
+    // The block reported by the only relay is not trusted by the majority policy.
+    let relay = CompositeRelay::new(AgreementPolicy::Majority)
+        .with_relay("a", FakeRelay::with_block(100))
+        .with_relay("b", FakeRelay::new(TransactionStatus::Mempool))
+        .with_relay("c", FakeRelay::new(None));
+    match relay.transaction_block(id).await.unwrap_err() {
+        CompositeRelayError::NoAgreement { agreed, required } => {
+            assert_eq!(agreed, 1);
+            assert_eq!(required, 2);
+        }
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+
+    let relay = CompositeRelay::new(AgreementPolicy::MinConfirmations)
+        .with_relay("a", FakeRelay::with_block(100))
+        .with_relay("b", FakeRelay::new(TransactionStatus::Mempool));
+    assert_eq!(relay.transaction_block(id).await.unwrap(), None);
+
+    let relay = CompositeRelay::new(AgreementPolicy::MinConfirmations)
+        .with_relay("a", FakeRelay::with_block(100))
+        .with_relay("b", FakeRelay::with_block(101));
+    match relay.transaction_block(id).await.unwrap_err() {
+        CompositeRelayError::NoAgreement { agreed, required } => {
+            assert_eq!(agreed, 1);
+            assert_eq!(required, 2);
+        }
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn composite_relay_failover_with_backoff() {
+    let id = fake_transaction().id();
+    let failed = FakeRelay::new(None);
+    let healthy = FakeRelay::new(TransactionStatus::Mempool);
+    let relay = CompositeRelay::new(AgreementPolicy::FirstSuccess)
+        .with_relay("failed", failed.clone())
+        .with_relay("healthy", healthy.clone())
+        .with_backoff(Duration::from_secs(60), Duration::from_secs(600));
+
+    assert_eq!(
+        relay.transaction_status(id).await.unwrap(),
+        TransactionStatus::Mempool
+    );
+    let health = relay.health();
+    assert_eq!(health[0].consecutive_failures, 1);
+    assert!(health[0].backoff_until.is_some());
+    assert_eq!(health[1].consecutive_failures, 0);
+
+    // The failed relay is skipped until its backoff expires.
+    assert_eq!(
+        relay.transaction_status(id).await.unwrap(),
+        TransactionStatus::Mempool
+    );
+    assert_eq!(failed.requests(), 1);
+    assert_eq!(healthy.requests(), 2);
+
+    // The transaction is considered sent, since one of the relays has accepted it.
+    let transaction = fake_transaction();
+    assert_eq!(
+        relay.send_transaction(&transaction).await.unwrap(),
+        transaction.id()
+    );
+    assert_eq!(failed.requests(), 1);
+}
+
+#[tokio::test]
+async fn composite_relay_rejection_does_not_affect_health() {
+    let rejecting = RejectingRelay::default();
+    let relay = CompositeRelay::new(AgreementPolicy::FirstSuccess)
+        .with_relay("rejecting", rejecting.clone())
+        .with_backoff(Duration::from_secs(60), Duration::from_secs(600));
+
+    let transaction = fake_transaction();
+    let e = relay.send_transaction(&transaction).await.unwrap_err();
+    assert!(CompositeRelay::is_rejection(&e));
+    let health = relay.health();
+    assert_eq!(health[0].consecutive_failures, 0);
+    assert!(health[0].backoff_until.is_none());
+
+    // The relay which has rejected the transaction is queried without the backoff.
+    relay.send_transaction(&transaction).await.unwrap_err();
+    assert_eq!(rejecting.requests.load(Ordering::SeqCst), 2);
+
+    // The failed relay is not a rejecting one.
+    let relay = CompositeRelay::new(AgreementPolicy::FirstSuccess)
+        .with_relay("failed", FakeRelay::new(None))
+        .with_relay("rejecting", rejecting);
+    let e = relay.send_transaction(&transaction).await.unwrap_err();
+    assert!(!CompositeRelay::is_rejection(&e));
+}
+
+#[tokio::test]
+async fn composite_relay_all_failed() {
+    let relay = CompositeRelay::new(AgreementPolicy::FirstSuccess)
+        .with_relay("a", FakeRelay::new(None))
+        .with_relay("b", FakeRelay::new(None));
+
+    let e = relay
+        .send_transaction(&fake_transaction())
+        .await
+        .unwrap_err();
+    match e {
+        CompositeRelayError::AllRelaysFailed(failures) => assert_eq!(failures.len(), 2),
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+
+    let e = relay
+        .transaction_status(fake_transaction().id())
+        .await
+        .unwrap_err();
+    match e {
+        CompositeRelayError::NoAvailableRelays => {}
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+}
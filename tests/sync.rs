@@ -12,6 +12,7 @@
 // limitations under the License.
 
 use async_trait::async_trait;
+use bitcoin::util::bip32::ExtendedPrivKey;
 use exonum::{
     blockchain::ApiSender,
     crypto::{Hash, KeyPair},
@@ -21,20 +22,29 @@ use exonum::{
 };
 use exonum_btc_anchoring::{
     api::{AnchoringChainLength, AnchoringProposalState, PrivateApi},
-    blockchain::{AddFunds, BtcAnchoringInterface, SignInput},
+    blockchain::{
+        AddFunds, BtcAnchoringInterface, BumpFee, ReportConfirmation, ReportUnconfirmed, SignInput,
+        SignInputs,
+    },
     btc,
     config::Config,
     sync::{
-        AnchoringChainUpdateTask, BitcoinRelay, ChainUpdateError, SyncWithBitcoinError,
-        SyncWithBitcoinTask, TransactionStatus,
+        runner::{AnchoringSyncRunner, ApiClient},
+        AnchoringChainUpdateTask, BitcoinRelay, ChainUpdateError, KeyPool, ProposalError,
+        RemoteSigner, ReorgEvent, SignInputRequest, Signer, SignerDaemon, SignerRequest,
+        SignerResponse, SignerSecret, SyncState, SyncWithBitcoinError, SyncWithBitcoinTask,
+        TransactionBlock, TransactionStatus,
     },
     test_helpers::{get_anchoring_schema, AnchoringTestKit, ANCHORING_INSTANCE_ID},
 };
 use exonum_rust_runtime::api;
+use exonum_supervisor::ConfigPropose;
 use exonum_testkit::TestKitApiClient;
+use mockito::mock;
+use tokio::net::TcpListener;
 
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     sync::{Arc, Mutex},
 };
 
@@ -48,6 +58,10 @@ enum FakeRelayRequest {
         request: btc::Sha256d,
         response: TransactionStatus,
     },
+    TransactionBlock {
+        request: btc::Sha256d,
+        response: Option<TransactionBlock>,
+    },
 }
 
 impl FakeRelayRequest {
@@ -72,6 +86,17 @@ impl FakeRelayRequest {
             )
         }
     }
+
+    fn into_transaction_block(self) -> (btc::Sha256d, Option<TransactionBlock>) {
+        if let FakeRelayRequest::TransactionBlock { request, response } = self {
+            (request, response)
+        } else {
+            panic!(
+                "Expected response for the `transaction_block` request. But got {:?}",
+                self
+            )
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -123,14 +148,27 @@ impl BitcoinRelay for FakeBitcoinRelay {
         assert_eq!(expected_request, id, "Unexpected data in request");
         Ok(response)
     }
+
+    async fn transaction_block(
+        &self,
+        id: btc::Sha256d,
+    ) -> Result<Option<TransactionBlock>, Self::Error> {
+        let (expected_request, response) = self.dequeue_request().into_transaction_block();
+        assert_eq!(expected_request, id, "Unexpected data in request");
+        Ok(response)
+    }
 }
 
 /// TODO Implement creating TestkitApi for an arbitrary TestNode. [ECR-3222]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct FakePrivateApi {
     service_keypair: KeyPair,
     client: TestKitApiClient,
     broadcaster: ApiSender,
+    proposal_mutator: Option<fn(&mut btc::Transaction)>,
+    payload_mutator: Option<fn(&mut btc::Payload)>,
+    funding_mutator: Option<fn(&mut Vec<btc::Transaction>)>,
+    sign_inputs_error: Option<fn() -> api::Error>,
 }
 
 impl FakePrivateApi {
@@ -148,9 +186,37 @@ impl FakePrivateApi {
             service_keypair,
             client,
             broadcaster: testkit.inner.blockchain().sender().clone(),
+            proposal_mutator: None,
+            payload_mutator: None,
+            funding_mutator: None,
+            sign_inputs_error: None,
         }
     }
 
+    /// Makes the API return the anchoring proposal modified by the given function.
+    fn with_proposal_mutator(mut self, mutator: fn(&mut btc::Transaction)) -> Self {
+        self.proposal_mutator = Some(mutator);
+        self
+    }
+
+    /// Makes the API return the anchoring transaction payloads modified by the given function.
+    fn with_payload_mutator(mut self, mutator: fn(&mut btc::Payload)) -> Self {
+        self.payload_mutator = Some(mutator);
+        self
+    }
+
+    /// Makes the API return the funding transactions modified by the given function.
+    fn with_funding_mutator(mut self, mutator: fn(&mut Vec<btc::Transaction>)) -> Self {
+        self.funding_mutator = Some(mutator);
+        self
+    }
+
+    /// Makes the API fail the batched input signatures with the given error.
+    fn with_sign_inputs_error(mut self, error: fn() -> api::Error) -> Self {
+        self.sign_inputs_error = Some(error);
+        self
+    }
+
     async fn send<T>(&self, transaction: T)
     where
         T: Into<Verified<AnyTx>>,
@@ -166,6 +232,10 @@ impl FakePrivateApi {
 impl PrivateApi for FakePrivateApi {
     type Error = api::Error;
 
+    fn is_not_found(error: &Self::Error) -> bool {
+        TestKitApiClient::is_not_found(error)
+    }
+
     async fn sign_input(&self, sign_input: SignInput) -> Result<Hash, Self::Error> {
         let signed_tx = self
             .service_keypair
@@ -175,6 +245,18 @@ impl PrivateApi for FakePrivateApi {
         Ok(hash)
     }
 
+    async fn sign_inputs(&self, sign_inputs: SignInputs) -> Result<Hash, Self::Error> {
+        if let Some(error) = self.sign_inputs_error {
+            return Err(error());
+        }
+        let signed_tx = self
+            .service_keypair
+            .sign_inputs(ANCHORING_INSTANCE_ID, sign_inputs);
+        let hash = signed_tx.object_hash();
+        self.send(signed_tx).await;
+        Ok(hash)
+    }
+
     async fn add_funds(&self, transaction: btc::Transaction) -> Result<Hash, Self::Error> {
         let signed_tx = self
             .service_keypair
@@ -184,14 +266,83 @@ impl PrivateApi for FakePrivateApi {
         Ok(hash)
     }
 
+    async fn bump_fee(&self, bump_fee: BumpFee) -> Result<Hash, Self::Error> {
+        let signed_tx = self
+            .service_keypair
+            .bump_fee(ANCHORING_INSTANCE_ID, bump_fee);
+        let hash = signed_tx.object_hash();
+        self.send(signed_tx).await;
+        Ok(hash)
+    }
+
+    async fn report_unconfirmed(&self, report: ReportUnconfirmed) -> Result<Hash, Self::Error> {
+        let signed_tx = self
+            .service_keypair
+            .report_unconfirmed(ANCHORING_INSTANCE_ID, report);
+        let hash = signed_tx.object_hash();
+        self.send(signed_tx).await;
+        Ok(hash)
+    }
+
+    async fn report_confirmation(&self, report: ReportConfirmation) -> Result<Hash, Self::Error> {
+        let signed_tx = self
+            .service_keypair
+            .report_confirmation(ANCHORING_INSTANCE_ID, report);
+        let hash = signed_tx.object_hash();
+        self.send(signed_tx).await;
+        Ok(hash)
+    }
+
     async fn anchoring_proposal(&self) -> Result<AnchoringProposalState, Self::Error> {
-        self.client.anchoring_proposal().await
+        let mut state = self.client.anchoring_proposal().await?;
+        if let (AnchoringProposalState::Available { transaction, .. }, Some(mutator)) =
+            (&mut state, self.proposal_mutator)
+        {
+            mutator(transaction);
+        }
+        Ok(state)
+    }
+
+    async fn anchoring_proposal_psbt(&self) -> Result<Option<btc::Psbt>, Self::Error> {
+        self.client.anchoring_proposal_psbt().await
+    }
+
+    async fn sign_psbt(&self, psbt: btc::Psbt) -> Result<Vec<Hash>, Self::Error> {
+        let bitcoin_key = self
+            .client
+            .config()
+            .await?
+            .find_bitcoin_key(&self.service_keypair.public_key())
+            .ok_or_else(|| api::Error::bad_request().title("Not an anchoring node"))?
+            .1;
+        let input_signatures = psbt
+            .input_signatures(&bitcoin_key)
+            .map_err(|e| api::Error::bad_request().detail(e.to_string()))?;
+
+        let mut tx_hashes = Vec::new();
+        for (input, input_signature) in input_signatures {
+            let sign_input = SignInput {
+                txid: psbt.id(),
+                input,
+                input_signature,
+            };
+            tx_hashes.push(self.sign_input(sign_input).await?);
+        }
+        Ok(tx_hashes)
     }
 
     async fn config(&self) -> Result<Config, Self::Error> {
         self.client.config().await
     }
 
+    async fn following_config(&self) -> Result<Option<Config>, Self::Error> {
+        self.client.following_config().await
+    }
+
+    async fn block_hash(&self, height: Height) -> Result<Hash, Self::Error> {
+        self.client.block_hash(height).await
+    }
+
     async fn transaction_with_index(
         &self,
         index: u64,
@@ -199,6 +350,22 @@ impl PrivateApi for FakePrivateApi {
         self.client.transaction_with_index(index).await
     }
 
+    async fn transaction_payload(&self, index: u64) -> Result<Option<btc::Payload>, Self::Error> {
+        let mut payload = self.client.transaction_payload(index).await?;
+        if let (Some(payload), Some(mutator)) = (&mut payload, self.payload_mutator) {
+            mutator(payload);
+        }
+        Ok(payload)
+    }
+
+    async fn funding_transactions(&self) -> Result<Vec<btc::Transaction>, Self::Error> {
+        let mut transactions = self.client.funding_transactions().await?;
+        if let Some(mutator) = self.funding_mutator {
+            mutator(&mut transactions);
+        }
+        Ok(transactions)
+    }
+
     async fn transactions_count(&self) -> Result<AnchoringChainLength, Self::Error> {
         self.client.transactions_count().await
     }
@@ -243,6 +410,448 @@ async fn chain_updater_normal() {
     }
 }
 
+#[tokio::test]
+async fn chain_updater_batched_signatures() {
+    let mut testkit = AnchoringTestKit::new(4, 5);
+    for satoshis in &[2000, 2400] {
+        let (txs, _) = testkit.create_funding_confirmation_txs(*satoshis);
+        testkit.inner.create_block_with_transactions(txs);
+    }
+    let (proposal, inputs) = testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(inputs.len(), 2);
+
+    let api = testkit.inner.api();
+    for keypair in testkit.anchoring_keypairs() {
+        let private_api =
+            FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0);
+
+        AnchoringChainUpdateTask::new(vec![keypair], private_api)
+            .process()
+            .await
+            .unwrap();
+    }
+    // Each anchoring node signs both inputs by the single transaction.
+    let block = testkit.inner.create_block();
+    assert_eq!(block.transactions.len(), 4);
+    assert_eq!(testkit.last_anchoring_tx().unwrap(), proposal);
+}
+
+#[tokio::test]
+async fn chain_updater_batched_signatures_not_found() {
+    let mut testkit = AnchoringTestKit::new(4, 5);
+    for satoshis in &[2000, 2400] {
+        let (txs, _) = testkit.create_funding_confirmation_txs(*satoshis);
+        testkit.inner.create_block_with_transactions(txs);
+    }
+    let (proposal, _) = testkit.anchoring_transaction_proposal().unwrap();
+
+    let api = testkit.inner.api();
+    for keypair in testkit.anchoring_keypairs() {
+        let private_api =
+            FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0)
+                .with_sign_inputs_error(api::Error::not_found);
+
+        AnchoringChainUpdateTask::new(vec![keypair], private_api)
+            .process()
+            .await
+            .unwrap();
+    }
+    // The node does not support the batched signatures, so each input is signed
+    // by the separate transaction.
+    let block = testkit.inner.create_block();
+    assert_eq!(block.transactions.len(), 8);
+    assert_eq!(testkit.last_anchoring_tx().unwrap(), proposal);
+}
+
+#[tokio::test]
+async fn chain_updater_batched_signatures_error() {
+    let mut testkit = AnchoringTestKit::default();
+    let api = testkit.inner.api();
+
+    let keypair = testkit.anchoring_keypairs()[0].clone();
+    let private_api =
+        FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0)
+            .with_sign_inputs_error(|| api::Error::internal("Unable to broadcast transaction"));
+    let e = AnchoringChainUpdateTask::new(vec![keypair], private_api)
+        .process()
+        .await
+        .unwrap_err();
+
+    match e {
+        ChainUpdateError::Client(_) => {}
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+    // The signatures are not sent by the separate transactions.
+    let block = testkit.inner.create_block();
+    assert!(block.transactions.is_empty());
+}
+
+#[tokio::test]
+async fn api_client_error_status() {
+    let client = ApiClient::new(mockito::server_url(), mockito::server_url(), "anchoring");
+    let sign_inputs = SignInputs {
+        txid: btc::Sha256d::new([1; 32]),
+        input_signatures: Vec::new(),
+    };
+
+    // The node which runs the previous version of the service has no such endpoint.
+    let not_found = mock("POST", "/api/services/anchoring/sign-inputs")
+        .with_status(404)
+        .with_body(r#"{"title":"Not found"}"#)
+        .create();
+    let e = client.sign_inputs(sign_inputs.clone()).await.unwrap_err();
+    assert!(ApiClient::is_not_found(&e));
+    drop(not_found);
+
+    let _mock = mock("POST", "/api/services/anchoring/sign-inputs")
+        .with_status(500)
+        .with_body(r#"{"title":"Unable to broadcast transaction"}"#)
+        .create();
+    let e = client.sign_inputs(sign_inputs).await.unwrap_err();
+    assert!(!ApiClient::is_not_found(&e));
+    assert_eq!(e.status(), Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR));
+}
+
+#[tokio::test]
+async fn chain_updater_remote_signer() {
+    let mut testkit = AnchoringTestKit::default();
+    let api = testkit.inner.api();
+
+    // Run signer daemon for each anchoring node.
+    let secret = SignerSecret::new("correct horse battery staple").unwrap();
+    let mut signer_addresses = HashMap::new();
+    for keypair in testkit.anchoring_keypairs() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        signer_addresses.insert(keypair.0, listener.local_addr().unwrap());
+        tokio::spawn(
+            SignerDaemon::new(KeyPool::new(vec![keypair]), secret.clone()).serve(listener),
+        );
+    }
+
+    let anchoring_interval = testkit.actual_anchoring_config().anchoring_interval;
+    testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    for i in 0..2 {
+        for keypair in testkit.anchoring_keypairs() {
+            let private_api =
+                FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0);
+            let signer = RemoteSigner::new(signer_addresses[&keypair.0], secret.clone());
+
+            AnchoringChainUpdateTask::with_signer(signer, private_api)
+                .process()
+                .await
+                .unwrap();
+        }
+        testkit.inner.create_block();
+        // Make sure the anchoring proposal has been finalized.
+        assert_eq!(
+            anchoring_transaction_payload(&testkit, i)
+                .unwrap()
+                .block_height,
+            Height(i * anchoring_interval)
+        );
+    }
+}
+
+#[tokio::test]
+async fn remote_signer_wrong_secret() {
+    let testkit = AnchoringTestKit::default();
+    let keypair = testkit.anchoring_keypairs().into_iter().next().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let secret = SignerSecret::new("correct horse battery staple").unwrap();
+    tokio::spawn(SignerDaemon::new(KeyPool::new(vec![keypair]), secret).serve(listener));
+
+    let signer = RemoteSigner::new(address, SignerSecret::new("wrong secret").unwrap());
+    let e = signer.public_keys().await.unwrap_err();
+    assert!(
+        e.to_string().contains("refused the authentication"),
+        "{}",
+        e
+    );
+}
+
+#[tokio::test]
+async fn sync_runner_stops_on_shutdown() {
+    let mut testkit = AnchoringTestKit::default();
+    let api = testkit.inner.api();
+
+    for keypair in testkit.anchoring_keypairs() {
+        let private_api =
+            FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0);
+        // The shutdown signal is already received, so the runner stops after
+        // the first iteration.
+        AnchoringSyncRunner::new(private_api, KeyPool::new(vec![keypair]))
+            .with_shutdown(futures::future::ready(()))
+            .run()
+            .await
+            .unwrap();
+    }
+    testkit.inner.create_block();
+    // Make sure the anchoring proposal has been finalized.
+    assert_eq!(
+        anchoring_transaction_payload(&testkit, 0)
+            .unwrap()
+            .block_height,
+        Height(0)
+    );
+}
+
+#[tokio::test]
+async fn chain_updater_refuses_proposal_to_unknown_address() {
+    let mut testkit = AnchoringTestKit::default();
+    let api = testkit.inner.api();
+    let keypair = testkit.anchoring_keypairs().into_iter().next().unwrap();
+
+    // Redirect the anchoring output to the foreign address.
+    let private_api =
+        FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0)
+            .with_proposal_mutator(|proposal| {
+                let (public_key, _) = btc::gen_keypair(bitcoin::Network::Testnet);
+                proposal.0.output[0].script_pubkey =
+                    bitcoin::Address::p2wpkh(&public_key.0, bitcoin::Network::Testnet)
+                        .script_pubkey();
+            });
+    let e = AnchoringChainUpdateTask::new(vec![keypair], private_api)
+        .process()
+        .await
+        .unwrap_err();
+
+    match e {
+        ChainUpdateError::InvalidProposal(ProposalError::UnexpectedRecipient) => {}
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+    // No signatures have been sent.
+    testkit.inner.create_block();
+    assert!(anchoring_transaction_payload(&testkit, 0).is_none());
+}
+
+#[tokio::test]
+async fn chain_updater_refuses_proposal_spending_unknown_funds() {
+    let mut testkit = AnchoringTestKit::default();
+    let api = testkit.inner.api();
+    let keypair = testkit.anchoring_keypairs().into_iter().next().unwrap();
+
+    // Conceal the initial funding transaction spent by the first proposal.
+    let private_api =
+        FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0)
+            .with_funding_mutator(Vec::clear);
+    let e = AnchoringChainUpdateTask::new(vec![keypair], private_api)
+        .process()
+        .await
+        .unwrap_err();
+
+    match e {
+        ChainUpdateError::InvalidProposal(ProposalError::UnknownInput(0)) => {}
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+    // No signatures have been sent.
+    testkit.inner.create_block();
+    assert!(anchoring_transaction_payload(&testkit, 0).is_none());
+}
+
+#[tokio::test]
+async fn chain_updater_refuses_proposal_with_high_fee() {
+    let mut testkit = AnchoringTestKit::default();
+    let api = testkit.inner.api();
+    let keypair = testkit.anchoring_keypairs().into_iter().next().unwrap();
+
+    let private_api =
+        FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0);
+    let e = AnchoringChainUpdateTask::new(vec![keypair], private_api)
+        .with_max_fee(1)
+        .process()
+        .await
+        .unwrap_err();
+
+    match e {
+        ChainUpdateError::InvalidProposal(ProposalError::FeeTooHigh { fee, max_fee }) => {
+            assert!(fee > max_fee);
+            assert_eq!(max_fee, 1);
+        }
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn chain_updater_pay_to_contract() {
+    let mut testkit = AnchoringTestKit::default();
+    let api = testkit.inner.api();
+    let anchoring_interval = testkit.actual_anchoring_config().anchoring_interval;
+    let mut config = testkit.actual_anchoring_config();
+    config.pay_to_contract = true;
+    testkit.inner.create_block_with_transaction(
+        testkit.create_config_change_tx(
+            ConfigPropose::new(0, testkit.inner.height().next())
+                .service_config(ANCHORING_INSTANCE_ID, config),
+        ),
+    );
+    testkit.inner.create_block();
+    // Establish anchoring transactions chain.
+    testkit
+        .inner
+        .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+
+    // The payload of the latest anchoring transaction, which does not correspond
+    // to its anchoring output, is refused.
+    let keypair = testkit.anchoring_keypairs().into_iter().next().unwrap();
+    let private_api =
+        FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0)
+            .with_payload_mutator(|payload| payload.block_height = payload.block_height.next());
+    let e = AnchoringChainUpdateTask::new(vec![keypair], private_api)
+        .process()
+        .await
+        .unwrap_err();
+    match e {
+        ChainUpdateError::InvalidProposal(ProposalError::ChainTailPayloadMismatch) => {}
+        e => panic!("Unexpected error occurred: {:?}", e),
+    }
+
+    // The height of the proposal is checked against the committed payload.
+    for keypair in testkit.anchoring_keypairs() {
+        let private_api =
+            FakePrivateApi::for_anchoring_node(&testkit, api.client().clone(), &keypair.0);
+        AnchoringChainUpdateTask::new(vec![keypair], private_api)
+            .process()
+            .await
+            .unwrap();
+    }
+    testkit.inner.create_block();
+    let snapshot = testkit.inner.snapshot();
+    let schema = get_anchoring_schema(&snapshot);
+    let tx1 = schema.transactions_chain.get(1).unwrap();
+    assert_eq!(tx1.anchoring_payload(), None);
+    assert_eq!(
+        schema.anchoring_payload(&tx1).unwrap().block_height,
+        Height(anchoring_interval)
+    );
+}
+
+#[test]
+fn signer_daemon_refuses_payload_height_going_backwards() {
+    let mut testkit = AnchoringTestKit::default();
+    let config = testkit.actual_anchoring_config();
+    let keypair = testkit.anchoring_keypairs().into_iter().next().unwrap();
+    let daemon = SignerDaemon::new(
+        KeyPool::new(vec![keypair.clone()]),
+        SignerSecret::new("secret").unwrap(),
+    );
+
+    let sign_input_request = |(proposal, inputs): (btc::Transaction, Vec<btc::Transaction>)| {
+        SignerRequest::SignInput(SignInputRequest {
+            public_key: keypair.0,
+            config: config.clone(),
+            following_config: None,
+            payload: proposal.anchoring_payload().unwrap(),
+            proposal,
+            input: 0,
+            prev_tx: inputs[0].clone(),
+            commitment: None,
+        })
+    };
+
+    // Keep the request for the first proposal and then anchor it.
+    let first_request = sign_input_request(testkit.anchoring_transaction_proposal().unwrap());
+    testkit
+        .inner
+        .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    testkit
+        .inner
+        .create_blocks_until(Height(config.anchoring_interval));
+    // Sign the proposal for the next anchoring height.
+    let second_request = sign_input_request(testkit.anchoring_transaction_proposal().unwrap());
+    match daemon.handle_request(second_request.clone()) {
+        SignerResponse::InputSignature(_) => {}
+        response => panic!("Unexpected response: {:?}", response),
+    }
+
+    // The proposal for the lower height is refused.
+    match daemon.handle_request(first_request) {
+        SignerResponse::Error(e) => assert!(e.contains("goes backwards")),
+        response => panic!("Unexpected response: {:?}", response),
+    }
+    // But the same proposal can be signed again.
+    match daemon.handle_request(second_request) {
+        SignerResponse::InputSignature(_) => {}
+        response => panic!("Unexpected response: {:?}", response),
+    }
+}
+
+#[test]
+fn signer_daemon_refuses_uncommitted_payload() {
+    let mut testkit = AnchoringTestKit::default();
+    let mut config = testkit.actual_anchoring_config();
+    config.pay_to_contract = true;
+    testkit.inner.create_block_with_transaction(
+        testkit.create_config_change_tx(
+            ConfigPropose::new(0, testkit.inner.height().next())
+                .service_config(ANCHORING_INSTANCE_ID, config.clone()),
+        ),
+    );
+    testkit.inner.create_block();
+
+    let keypair = testkit.anchoring_keypairs().into_iter().next().unwrap();
+    let daemon = SignerDaemon::new(
+        KeyPool::new(vec![keypair.clone()]),
+        SignerSecret::new("secret").unwrap(),
+    );
+    let (proposal, inputs, payload) = {
+        let snapshot = testkit.inner.snapshot();
+        get_anchoring_schema(&snapshot)
+            .actual_anchoring_proposal(snapshot.for_core())
+            .unwrap()
+            .unwrap()
+    };
+    let sign_input_request = |payload: btc::Payload| {
+        SignerRequest::SignInput(SignInputRequest {
+            public_key: keypair.0,
+            config: config.clone(),
+            following_config: None,
+            proposal: proposal.clone(),
+            payload,
+            input: 0,
+            prev_tx: inputs[0].clone(),
+            commitment: None,
+        })
+    };
+
+    // The payload, which is not committed by the anchoring output, is refused.
+    let mut wrong_payload = payload.clone();
+    wrong_payload.block_height = wrong_payload.block_height.next();
+    match daemon.handle_request(sign_input_request(wrong_payload)) {
+        SignerResponse::Error(e) => assert!(e.contains("not committed")),
+        response => panic!("Unexpected response: {:?}", response),
+    }
+    match daemon.handle_request(sign_input_request(payload)) {
+        SignerResponse::InputSignature(_) => {}
+        response => panic!("Unexpected response: {:?}", response),
+    }
+}
+
+#[tokio::test]
+async fn key_pool_derives_extended_keys() {
+    let xprv = ExtendedPrivKey::new_master(bitcoin::Network::Testnet, &[1; 32]).unwrap();
+    let key_pool = KeyPool::default().with_extended_key(xprv, 2, 3);
+
+    // Keys are derived up to the actual rotation index plus the lookahead window.
+    let derived_keys = (0..=5)
+        .map(|index| btc::derive_public_key(&xprv, index).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(key_pool.public_keys().await.unwrap(), derived_keys);
+    assert_eq!(
+        key_pool.find_private_key(&derived_keys[5]),
+        Some(btc::derive_private_key(&xprv, 5).unwrap())
+    );
+
+    // The key beyond the lookahead window is not found.
+    let public_key = btc::derive_public_key(&xprv, 6).unwrap();
+    assert_eq!(key_pool.find_private_key(&public_key), None);
+}
+
 #[tokio::test]
 async fn chain_updater_no_initial_funds() {
     let anchoring_interval = 5;
@@ -288,7 +897,7 @@ async fn chain_updater_insufficient_funds() {
     match e {
         ChainUpdateError::InsufficientFunds { balance, total_fee } => {
             assert_eq!(balance, 200);
-            assert_eq!(total_fee, 1530);
+            assert_eq!(total_fee, 2150);
         }
         e => panic!("Unexpected error occurred: {:?}", e),
     }
@@ -374,6 +983,440 @@ async fn sync_with_bitcoin_normal() {
     assert_eq!(latest_committed_tx_index, 1);
 }
 
+#[tokio::test]
+async fn sync_with_bitcoin_detects_reorg() {
+    let mut testkit = AnchoringTestKit::default();
+    let anchoring_interval = testkit.actual_anchoring_config().anchoring_interval;
+    // Create a several anchoring transactions
+    for i in 0..2 {
+        testkit
+            .inner
+            .create_blocks_until(Height(anchoring_interval * i));
+
+        testkit
+            .inner
+            .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    }
+    let snapshot = testkit.inner.snapshot();
+    let tx_chain = get_anchoring_schema(&snapshot).transactions_chain;
+    let (tx0, tx1) = (tx_chain.get(0).unwrap(), tx_chain.get(1).unwrap());
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let fake_relay = FakeBitcoinRelay::default();
+    let api = testkit.inner.api();
+    let sync = SyncWithBitcoinTask::new(fake_relay.clone(), api.client().clone())
+        .with_reorg_depth(2)
+        .with_reorg_listener({
+            let events = events.clone();
+            move |event: &ReorgEvent| events.lock().unwrap().push(event.clone())
+        });
+
+    // Both anchoring transactions are confirmed.
+    fake_relay.enqueue_requests(vec![
+        FakeRelayRequest::TransactionStatus {
+            request: tx0.id(),
+            response: TransactionStatus::Committed(2),
+        },
+        FakeRelayRequest::TransactionBlock {
+            request: tx0.id(),
+            response: None,
+        },
+        FakeRelayRequest::TransactionStatus {
+            request: tx1.id(),
+            response: TransactionStatus::Committed(1),
+        },
+        FakeRelayRequest::TransactionBlock {
+            request: tx1.id(),
+            response: None,
+        },
+        FakeRelayRequest::TransactionStatus {
+            request: tx1.id(),
+            response: TransactionStatus::Committed(1),
+        },
+    ]);
+    assert_eq!(sync.process(Some(1)).await.unwrap(), Some(1));
+    assert_eq!(sync.reorgs_detected(), 0);
+
+    // The latest anchoring transaction is dropped by the reorganization, so it should
+    // be sent again.
+    fake_relay.enqueue_requests(vec![
+        FakeRelayRequest::TransactionStatus {
+            request: tx0.id(),
+            response: TransactionStatus::Committed(3),
+        },
+        FakeRelayRequest::TransactionBlock {
+            request: tx0.id(),
+            response: None,
+        },
+        FakeRelayRequest::TransactionStatus {
+            request: tx1.id(),
+            response: TransactionStatus::Unknown,
+        },
+        FakeRelayRequest::SendTransaction {
+            request: tx1.clone(),
+            response: tx1.id(),
+        },
+        FakeRelayRequest::TransactionStatus {
+            request: tx1.id(),
+            response: TransactionStatus::Mempool,
+        },
+    ]);
+    assert_eq!(sync.process(Some(1)).await.unwrap(), Some(1));
+    assert_eq!(sync.reorgs_detected(), 1);
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![ReorgEvent {
+            index: 1,
+            txid: tx1.id(),
+            previous_block_hash: None,
+            status: TransactionStatus::Unknown,
+            block_hash: None,
+            rebroadcast: vec![tx1.id()],
+        }]
+    );
+}
+
+#[tokio::test]
+async fn sync_with_bitcoin_detects_reorg_after_fee_bump() {
+    let mut testkit = AnchoringTestKit::default();
+    let mut config = testkit.actual_anchoring_config();
+    config.replace_by_fee = true;
+    testkit.inner.create_block_with_transaction(
+        testkit.create_config_change_tx(
+            ConfigPropose::new(0, testkit.inner.height().next())
+                .service_config(ANCHORING_INSTANCE_ID, config.clone()),
+        ),
+    );
+    testkit.inner.create_block();
+    // Establish anchoring transactions chain.
+    testkit
+        .inner
+        .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    let tx0 = testkit.last_anchoring_tx().unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let fake_relay = FakeBitcoinRelay::default();
+    let api = testkit.inner.api();
+    let sync = SyncWithBitcoinTask::new(fake_relay.clone(), api.client().clone())
+        .with_reorg_depth(2)
+        .with_reorg_listener({
+            let events = events.clone();
+            move |event: &ReorgEvent| events.lock().unwrap().push(event.clone())
+        });
+
+    // The anchoring transaction is stuck in the mempool.
+    fake_relay.enqueue_requests(vec![
+        FakeRelayRequest::TransactionStatus {
+            request: tx0.id(),
+            response: TransactionStatus::Mempool,
+        },
+        FakeRelayRequest::TransactionStatus {
+            request: tx0.id(),
+            response: TransactionStatus::Mempool,
+        },
+    ]);
+    assert_eq!(sync.process(None).await.unwrap(), None);
+
+    // Replace the anchoring transaction with the one paying a higher fee.
+    testkit
+        .inner
+        .create_block_with_transactions(testkit.create_unconfirmed_report_txs(tx0.id()));
+    testkit.inner.create_block_with_transactions(
+        testkit.create_fee_bump_txs(tx0.id(), config.transaction_fee * 2),
+    );
+    testkit
+        .inner
+        .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    let tx0_replacement = testkit.last_anchoring_tx().unwrap();
+    assert_ne!(tx0_replacement.id(), tx0.id());
+
+    // The replacement is watched instead of the replaced transaction.
+    let block_hash = btc::Sha256d::new([1; 32]);
+    fake_relay.enqueue_requests(vec![
+        FakeRelayRequest::TransactionStatus {
+            request: tx0_replacement.id(),
+            response: TransactionStatus::Committed(1),
+        },
+        FakeRelayRequest::TransactionBlock {
+            request: tx0_replacement.id(),
+            response: Some(TransactionBlock {
+                hash: block_hash,
+                height: 100,
+            }),
+        },
+        FakeRelayRequest::TransactionStatus {
+            request: tx0_replacement.id(),
+            response: TransactionStatus::Committed(1),
+        },
+    ]);
+    assert_eq!(sync.process(None).await.unwrap(), None);
+    assert_eq!(sync.reorgs_detected(), 0);
+
+    // The replacement is dropped by the reorganization, so it should be sent again.
+    fake_relay.enqueue_requests(vec![
+        FakeRelayRequest::TransactionStatus {
+            request: tx0_replacement.id(),
+            response: TransactionStatus::Unknown,
+        },
+        FakeRelayRequest::SendTransaction {
+            request: tx0_replacement.clone(),
+            response: tx0_replacement.id(),
+        },
+        FakeRelayRequest::TransactionStatus {
+            request: tx0_replacement.id(),
+            response: TransactionStatus::Mempool,
+        },
+    ]);
+    assert_eq!(sync.process(None).await.unwrap(), None);
+    assert_eq!(sync.reorgs_detected(), 1);
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![ReorgEvent {
+            index: 0,
+            txid: tx0_replacement.id(),
+            previous_block_hash: Some(block_hash),
+            status: TransactionStatus::Unknown,
+            block_hash: None,
+            rebroadcast: vec![tx0_replacement.id()],
+        }]
+    );
+}
+
+#[tokio::test]
+async fn sync_with_bitcoin_restores_state() {
+    let mut testkit = AnchoringTestKit::default();
+    testkit
+        .inner
+        .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    let tx0 = testkit.last_anchoring_tx().unwrap();
+
+    let fake_relay = FakeBitcoinRelay::default();
+    let api = testkit.inner.api();
+    let sync = SyncWithBitcoinTask::new(fake_relay.clone(), api.client().clone());
+
+    // Save the state of the sync and load it back.
+    fake_relay.enqueue_requests(vec![FakeRelayRequest::TransactionStatus {
+        request: tx0.id(),
+        response: TransactionStatus::Committed(3),
+    }]);
+    let state = sync.sync_state(0).await.unwrap();
+    assert_eq!(
+        state,
+        SyncState {
+            latest_synced_tx_index: 0,
+            txid: tx0.id(),
+            confirmations: 3,
+        }
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sync_state.toml");
+    assert_eq!(SyncState::load(&path).unwrap(), None);
+    state.save(&path).unwrap();
+    let state = SyncState::load(&path).unwrap().unwrap();
+
+    // The state is checked against the anchoring chain without the relay requests.
+    assert_eq!(sync.restore_state(&state).await.unwrap(), Some(0));
+    let forged_state = SyncState {
+        txid: tx0.prev_tx_id(),
+        ..state.clone()
+    };
+    assert_eq!(sync.restore_state(&forged_state).await.unwrap(), None);
+    let stale_state = SyncState {
+        latest_synced_tx_index: 1,
+        ..state
+    };
+    assert_eq!(sync.restore_state(&stale_state).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn sync_with_bitcoin_report_unconfirmed() {
+    let mut testkit = AnchoringTestKit::default();
+    let anchoring_interval = testkit.actual_anchoring_config().anchoring_interval;
+    // Establish anchoring transactions chain.
+    testkit
+        .inner
+        .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    let tx0 = testkit.last_anchoring_tx().unwrap();
+    // Wait for the proposal of the following anchoring transaction.
+    testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+
+    let fake_relay = FakeBitcoinRelay::default();
+    let api = testkit.inner.api();
+    let sync = SyncWithBitcoinTask::new(fake_relay.clone(), api.client().clone());
+    // Relay should report the latest anchoring transaction which stays in the mempool.
+    fake_relay.enqueue_requests(vec![FakeRelayRequest::TransactionStatus {
+        request: tx0.id(),
+        response: TransactionStatus::Mempool,
+    }]);
+    let latest_committed_tx_index = sync
+        .process(Some(0))
+        .await
+        .unwrap()
+        .expect("Transaction should be committed");
+    assert_eq!(latest_committed_tx_index, 0);
+
+    // Make sure that the report has been successfully executed.
+    let block = testkit.inner.create_block();
+    assert_eq!(block.transactions.len(), 1);
+    block.transactions[0].status().unwrap();
+}
+
+#[tokio::test]
+async fn sync_with_bitcoin_report_unconfirmed_without_proposal() {
+    let mut testkit = AnchoringTestKit::default();
+    // Establish anchoring transactions chain.
+    testkit
+        .inner
+        .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    let tx0 = testkit.last_anchoring_tx().unwrap();
+
+    let fake_relay = FakeBitcoinRelay::default();
+    let api = testkit.inner.api();
+    let sync = SyncWithBitcoinTask::new(fake_relay.clone(), api.client().clone());
+    // Relay should report the transaction even if there is no following proposal,
+    // so that it can be replaced by the fee bump.
+    fake_relay.enqueue_requests(vec![FakeRelayRequest::TransactionStatus {
+        request: tx0.id(),
+        response: TransactionStatus::Mempool,
+    }]);
+    assert_eq!(sync.process(Some(0)).await.unwrap(), Some(0));
+
+    let block = testkit.inner.create_block();
+    assert_eq!(block.transactions.len(), 1);
+    block.transactions[0].status().unwrap();
+
+    // The transaction is reported only once.
+    fake_relay.enqueue_requests(vec![FakeRelayRequest::TransactionStatus {
+        request: tx0.id(),
+        response: TransactionStatus::Mempool,
+    }]);
+    assert_eq!(sync.process(Some(0)).await.unwrap(), Some(0));
+    assert!(testkit.inner.create_block().transactions.is_empty());
+}
+
+#[tokio::test]
+async fn sync_with_bitcoin_report_confirmation() {
+    let mut testkit = AnchoringTestKit::default();
+    // Establish anchoring transactions chain.
+    testkit
+        .inner
+        .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    let tx0 = testkit.last_anchoring_tx().unwrap();
+
+    let fake_relay = FakeBitcoinRelay::default();
+    let api = testkit.inner.api();
+    let sync = SyncWithBitcoinTask::new(fake_relay.clone(), api.client().clone())
+        .with_confirmation_reports(2);
+    // Relay should report the confirmations of the latest anchoring transaction.
+    fake_relay.enqueue_requests(vec![
+        FakeRelayRequest::TransactionStatus {
+            request: tx0.id(),
+            response: TransactionStatus::Committed(3),
+        },
+        FakeRelayRequest::TransactionBlock {
+            request: tx0.id(),
+            response: Some(TransactionBlock {
+                hash: btc::Sha256d::new([1; 32]),
+                height: 100,
+            }),
+        },
+    ]);
+    assert_eq!(sync.process(Some(0)).await.unwrap(), Some(0));
+
+    // Make sure that the report has been successfully executed.
+    let block = testkit.inner.create_block();
+    assert_eq!(block.transactions.len(), 1);
+    block.transactions[0].status().unwrap();
+
+    // The final number of confirmations is reported only once.
+    fake_relay.enqueue_requests(vec![FakeRelayRequest::TransactionStatus {
+        request: tx0.id(),
+        response: TransactionStatus::Committed(4),
+    }]);
+    assert_eq!(sync.process(Some(0)).await.unwrap(), Some(0));
+    assert!(testkit.inner.create_block().transactions.is_empty());
+}
+
+#[tokio::test]
+async fn sync_with_bitcoin_report_confirmations_of_previous_transactions() {
+    let mut testkit = AnchoringTestKit::default();
+    let anchoring_interval = testkit.actual_anchoring_config().anchoring_interval;
+    // Create a several anchoring transactions.
+    for i in 0..2 {
+        testkit
+            .inner
+            .create_blocks_until(Height(anchoring_interval * i));
+        testkit
+            .inner
+            .create_block_with_transactions(testkit.create_signature_txs().into_iter().flatten());
+    }
+    let tx_chain = get_anchoring_schema(&testkit.inner.snapshot()).transactions_chain;
+    let (tx0, tx1) = (tx_chain.get(0).unwrap(), tx_chain.get(1).unwrap());
+    let block = TransactionBlock {
+        hash: btc::Sha256d::new([1; 32]),
+        height: 100,
+    };
+
+    let fake_relay = FakeBitcoinRelay::default();
+    let api = testkit.inner.api();
+    let sync = SyncWithBitcoinTask::new(fake_relay.clone(), api.client().clone())
+        .with_confirmation_reports(2);
+    // Both transactions have been included into the same block.
+    fake_relay.enqueue_requests(vec![
+        FakeRelayRequest::TransactionStatus {
+            request: tx1.id(),
+            response: TransactionStatus::Committed(1),
+        },
+        FakeRelayRequest::TransactionBlock {
+            request: tx1.id(),
+            response: Some(block),
+        },
+        FakeRelayRequest::TransactionStatus {
+            request: tx0.id(),
+            response: TransactionStatus::Committed(1),
+        },
+        FakeRelayRequest::TransactionBlock {
+            request: tx0.id(),
+            response: Some(block),
+        },
+    ]);
+    assert_eq!(sync.process(Some(1)).await.unwrap(), Some(1));
+    assert_eq!(testkit.inner.create_block().transactions.len(), 2);
+
+    // The previous transaction is reported as final too.
+    fake_relay.enqueue_requests(vec![
+        FakeRelayRequest::TransactionStatus {
+            request: tx1.id(),
+            response: TransactionStatus::Committed(2),
+        },
+        FakeRelayRequest::TransactionBlock {
+            request: tx1.id(),
+            response: Some(block),
+        },
+        FakeRelayRequest::TransactionStatus {
+            request: tx0.id(),
+            response: TransactionStatus::Committed(2),
+        },
+        FakeRelayRequest::TransactionBlock {
+            request: tx0.id(),
+            response: Some(block),
+        },
+    ]);
+    assert_eq!(sync.process(Some(1)).await.unwrap(), Some(1));
+    assert_eq!(testkit.inner.create_block().transactions.len(), 2);
+
+    // The transactions reported as final are no longer checked.
+    fake_relay.enqueue_requests(vec![FakeRelayRequest::TransactionStatus {
+        request: tx1.id(),
+        response: TransactionStatus::Committed(3),
+    }]);
+    assert_eq!(sync.process(Some(1)).await.unwrap(), Some(1));
+    assert!(testkit.inner.create_block().transactions.is_empty());
+}
+
 #[tokio::test]
 async fn sync_with_bitcoin_empty_chain() {
     let mut testkit = AnchoringTestKit::default();
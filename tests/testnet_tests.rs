@@ -14,13 +14,18 @@
 
 use exonum::helpers::Height;
 use exonum::{
+    merkledb::ObjectHash,
     messages::{AnyTx, Verified},
     runtime::{ErrorMatch, SnapshotExt},
 };
 use exonum_btc_anchoring::{
-    blockchain::{errors::Error, BtcAnchoringInterface, SignInput},
+    blockchain::{
+        data_layout::TxInputId, errors::Error, schema::MAX_ANCHORING_TX_INPUTS,
+        BitcoinConfirmation, BtcAnchoringInterface, ReportConfirmation, SignInput, SignInputs,
+        SkippedHeights,
+    },
     btc::{self, BuilderError},
-    config::Config,
+    config::{CatchUpPolicy, Config},
     test_helpers::{
         create_fake_funding_transaction, get_anchoring_schema, AnchoringTestKit,
         ANCHORING_INSTANCE_ID,
@@ -37,8 +42,8 @@ fn assert_tx_error(tx: &CommittedTransaction, e: ErrorMatch) {
     );
 }
 
-fn unspent_funding_transaction(anchoring_testkit: &AnchoringTestKit) -> Option<btc::Transaction> {
-    get_anchoring_schema(&anchoring_testkit.inner.snapshot()).unspent_funding_transaction()
+fn unspent_funding_transactions(anchoring_testkit: &AnchoringTestKit) -> Vec<btc::Transaction> {
+    get_anchoring_schema(&anchoring_testkit.inner.snapshot()).unspent_funding_transactions()
 }
 
 fn change_tx_signature(tx: Verified<AnyTx>, keypair: &KeyPair) -> Verified<AnyTx> {
@@ -49,6 +54,32 @@ fn change_tx_signature(tx: Verified<AnyTx>, keypair: &KeyPair) -> Verified<AnyTx
     )
 }
 
+fn enable_replace_by_fee(anchoring_testkit: &mut AnchoringTestKit) {
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.replace_by_fee = true;
+    apply_anchoring_config(anchoring_testkit, config);
+}
+
+fn report_unconfirmed(anchoring_testkit: &mut AnchoringTestKit, txid: btc::Sha256d) {
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_unconfirmed_report_txs(txid))
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+}
+
+fn apply_anchoring_config(anchoring_testkit: &mut AnchoringTestKit, config: Config) {
+    anchoring_testkit.inner.create_block_with_transaction(
+        anchoring_testkit.create_config_change_tx(
+            ConfigPropose::new(0, anchoring_testkit.inner.height().next())
+                .service_config(ANCHORING_INSTANCE_ID, config),
+        ),
+    );
+    anchoring_testkit.inner.create_block();
+}
+
 fn test_anchoring_config_change<F>(mut config_change_predicate: F) -> AnchoringTestKit
 where
     F: FnMut(&mut AnchoringTestKit, &mut Config),
@@ -106,6 +137,8 @@ where
         // anchoring transaction.
         assert_eq!(payload.block_height, Height(0));
         assert_eq!(&new_cfg.anchoring_out_script(), out_script);
+        // Transition transaction is signed with the already committed configuration.
+        assert_eq!(payload.config_hash, None);
     }
 
     // Finalize transition transaction
@@ -141,6 +174,7 @@ where
             .anchoring_out_script(),
         *tx_meta.0
     );
+    assert_eq!(tx_meta.1.config_hash, Some(new_cfg.object_hash()));
 
     anchoring_testkit
 }
@@ -333,7 +367,7 @@ fn insufficient_funds() {
             proposal,
             Err(BuilderError::InsufficientFunds {
                 balance: 20,
-                total_fee: 1530
+                total_fee: 2780
             })
         );
     }
@@ -451,7 +485,7 @@ fn add_anchoring_node_insufficient_funds() {
     // Add an initial funding transaction to enable anchoring.
     anchoring_testkit
         .inner
-        .create_block_with_transactions(anchoring_testkit.create_funding_confirmation_txs(2000).0);
+        .create_block_with_transactions(anchoring_testkit.create_funding_confirmation_txs(3000).0);
 
     assert!(anchoring_testkit.last_anchoring_tx().is_none());
     // Establish anchoring transactions chain.
@@ -490,14 +524,14 @@ fn add_anchoring_node_insufficient_funds() {
         assert_eq!(
             proposal,
             Err(BuilderError::InsufficientFunds {
-                total_fee: 1530,
-                balance: 470
+                total_fee: 2780,
+                balance: 220
             })
         );
     }
 
     // Add funds.
-    let (txs, funding_tx) = anchoring_testkit.create_funding_confirmation_txs(2000);
+    let (txs, funding_tx) = anchoring_testkit.create_funding_confirmation_txs(4000);
     anchoring_testkit.inner.create_block_with_transactions(txs);
     // Ensure that we have a suitable transition anchoring transaction proposal.
     assert_eq!(
@@ -529,8 +563,7 @@ fn funding_tx_err_unsuitable() {
 }
 
 #[test]
-fn funding_tx_override() {
-    // Actually, we can override the funding transaction by another one.
+fn funding_txs_accumulate() {
     let anchoring_interval = 5;
     let mut anchoring_testkit = AnchoringTestKit::new(4, anchoring_interval);
 
@@ -538,17 +571,137 @@ fn funding_tx_override() {
     let (txs, first_funding_transaction) = anchoring_testkit.create_funding_confirmation_txs(2000);
     anchoring_testkit.inner.create_block_with_transactions(txs);
     assert_eq!(
-        unspent_funding_transaction(&anchoring_testkit).unwrap(),
-        first_funding_transaction
+        unspent_funding_transactions(&anchoring_testkit),
+        vec![first_funding_transaction.clone()]
     );
 
-    // Override the funding transaction by the second one.
+    // The second funding transaction does not override the first one.
     let (txs, second_funding_transaction) = anchoring_testkit.create_funding_confirmation_txs(2400);
     anchoring_testkit.inner.create_block_with_transactions(txs);
+    let unspent_funding_txs = unspent_funding_transactions(&anchoring_testkit);
+    assert_eq!(unspent_funding_txs.len(), 2);
+    assert!(unspent_funding_txs.contains(&first_funding_transaction));
+    assert!(unspent_funding_txs.contains(&second_funding_transaction));
+
+    // Both of funding transactions are spent by the first anchoring transaction.
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.0.input.len(), 2);
+    assert_eq!(
+        proposal.0.output[0].value,
+        4400 - proposal.fee(&inputs).unwrap()
+    );
+
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    assert_eq!(anchoring_testkit.last_anchoring_tx().unwrap(), proposal);
+    assert!(unspent_funding_transactions(&anchoring_testkit).is_empty());
+}
+
+#[test]
+fn funding_tx_multiple_outputs() {
+    let anchoring_interval = 5;
+    let mut anchoring_testkit = AnchoringTestKit::new(4, anchoring_interval);
+
+    // Create funding transaction which pays to the anchoring address twice.
+    let config = anchoring_testkit.actual_anchoring_config();
+    let mut funding_tx = create_fake_funding_transaction(&config.anchoring_address(), 3000);
+    let mut second_out = funding_tx.0.output[0].clone();
+    second_out.value = 2000;
+    funding_tx.0.output.push(second_out);
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit.create_funding_confirmation_txs_with(funding_tx.clone()),
+    );
+
+    // Both outputs are spent by the first anchoring transaction.
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(inputs, vec![funding_tx.clone(), funding_tx.clone()]);
+    assert_eq!(
+        proposal.0.output[0].value,
+        5000 - proposal.fee(&inputs).unwrap()
+    );
+
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    assert_eq!(anchoring_testkit.last_anchoring_tx().unwrap(), proposal);
+    assert!(unspent_funding_transactions(&anchoring_testkit).is_empty());
+
+    // Funding transaction cannot be used twice.
+    let block = anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit.create_funding_confirmation_txs_with(funding_tx),
+    );
+    assert_tx_error(
+        &block[0],
+        ErrorMatch::from_fail(&Error::AlreadyUsedFundingTx),
+    );
+}
+
+#[test]
+fn funding_txs_max_inputs() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+
+    // Add more funding transactions than can be spent by a single anchoring transaction.
+    let funding_txs_count = MAX_ANCHORING_TX_INPUTS + 2;
+    for _ in 0..funding_txs_count {
+        let (txs, _) = anchoring_testkit.create_funding_confirmation_txs(10_000);
+        anchoring_testkit.inner.create_block_with_transactions(txs);
+    }
+    assert_eq!(
+        unspent_funding_transactions(&anchoring_testkit).len(),
+        funding_txs_count
+    );
+
+    // The following anchoring transaction should spend as many funding transactions as possible.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.0.input.len(), MAX_ANCHORING_TX_INPUTS);
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    assert_eq!(
+        unspent_funding_transactions(&anchoring_testkit).len(),
+        funding_txs_count - (MAX_ANCHORING_TX_INPUTS - 1)
+    );
+
+    // The remaining funding transactions are carried to the following anchoring transaction.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 2));
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
     assert_eq!(
-        unspent_funding_transaction(&anchoring_testkit).unwrap(),
-        second_funding_transaction
+        proposal.0.input.len(),
+        funding_txs_count - (MAX_ANCHORING_TX_INPUTS - 1) + 1
+    );
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
     );
+    assert!(unspent_funding_transactions(&anchoring_testkit).is_empty());
 }
 
 #[test]
@@ -622,4 +775,1175 @@ fn sign_input_err_input_verification_failed() {
     );
 }
 
+/// Creates the testkit with two funding transactions, so the anchoring proposal has two inputs.
+fn testkit_with_two_inputs() -> AnchoringTestKit {
+    let mut anchoring_testkit = AnchoringTestKit::new(4, 5);
+    for satoshis in &[2000, 2400] {
+        let (txs, _) = anchoring_testkit.create_funding_confirmation_txs(*satoshis);
+        anchoring_testkit.inner.create_block_with_transactions(txs);
+    }
+    anchoring_testkit
+}
+
+#[test]
+fn sign_inputs() {
+    let mut anchoring_testkit = testkit_with_two_inputs();
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.0.input.len(), 2);
+
+    // Each anchoring node signs both inputs by the single transaction.
+    let txs = anchoring_testkit.create_batched_signature_txs();
+    assert_eq!(txs.len(), 4);
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(txs)
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+    assert_eq!(anchoring_testkit.last_anchoring_tx().unwrap(), proposal);
+}
+
+#[test]
+fn sign_inputs_err_unexpected_input_signatures() {
+    let mut testkit = AnchoringTestKit::default();
+    let us = testkit.inner.us();
+    // Create sign_inputs transaction for the anchoring node.
+    let mut sign_inputs = testkit
+        .create_batched_signature_tx_for_node(&us)
+        .unwrap()
+        .unwrap()
+        .payload()
+        .parse::<SignInputs>()
+        .unwrap();
+    // Add the signature for the absent input.
+    let input_signature = sign_inputs.input_signatures[0].clone();
+    sign_inputs.input_signatures.push(input_signature);
+    let malformed_tx = us
+        .service_keypair()
+        .sign_inputs(ANCHORING_INSTANCE_ID, sign_inputs);
+    // Commit this transaction and check status.
+    let block = testkit.inner.create_block_with_transaction(malformed_tx);
+    assert_tx_error(
+        &block[0],
+        ErrorMatch::from_fail(&Error::UnexpectedInputSignatures),
+    );
+}
+
+#[test]
+fn sign_inputs_err_input_verification_failed() {
+    let mut testkit = testkit_with_two_inputs();
+    let us = testkit.inner.us();
+    // Create sign_inputs transaction for the anchoring node.
+    let mut sign_inputs = testkit
+        .create_batched_signature_tx_for_node(&us)
+        .unwrap()
+        .unwrap()
+        .payload()
+        .parse::<SignInputs>()
+        .unwrap();
+    // Replace the signature for the second input by the one for the first input.
+    sign_inputs.input_signatures[1] = sign_inputs.input_signatures[0].clone();
+    let txid = sign_inputs.txid;
+    let malformed_tx = us
+        .service_keypair()
+        .sign_inputs(ANCHORING_INSTANCE_ID, sign_inputs);
+    // Commit this transaction and check status.
+    let block = testkit.inner.create_block_with_transaction(malformed_tx);
+    assert_tx_error(
+        &block[0],
+        ErrorMatch::from_fail(&Error::InputVerificationFailed)
+            .with_description_containing("secp: signature failed verification"),
+    );
+
+    // The correct signature for the first input is not accepted either.
+    let snapshot = testkit.inner.snapshot();
+    let schema = get_anchoring_schema(&snapshot);
+    assert!(schema
+        .input_signatures(&TxInputId::new(txid, 0))
+        .0
+        .is_empty());
+}
+
+#[test]
+fn replace_by_fee() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+    enable_replace_by_fee(&mut anchoring_testkit);
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    assert!(tx0.is_replaceable());
+    report_unconfirmed(&mut anchoring_testkit, tx0.id());
+
+    // Request the replacement of the latest anchoring transaction.
+    let fee = anchoring_testkit.actual_anchoring_config().transaction_fee * 2;
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_fee_bump_txs(tx0.id(), fee))
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    // Ensure that the anchoring proposal is the replacement.
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.0.input.len(), tx0.0.input.len());
+    assert_eq!(proposal.prev_tx_id(), tx0.prev_tx_id());
+    assert_eq!(proposal.anchoring_payload(), tx0.anchoring_payload());
+    assert!(proposal.fee(&inputs).unwrap() > tx0.fee(&inputs).unwrap());
+
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+
+    // Verify that the latest anchoring transaction has been replaced.
+    let tx0_replacement = {
+        let snapshot = anchoring_testkit.inner.snapshot();
+        let schema = get_anchoring_schema(&snapshot);
+        assert_eq!(schema.transactions_chain.len(), 1);
+        assert_eq!(schema.latest_anchored_height(), Some(Height(0)));
+        assert_eq!(schema.replaced_transaction(&tx0.id()), Some(tx0.clone()));
+        assert!(schema.fee_bump().is_none());
+        schema.transactions_chain.last().unwrap()
+    };
+    assert_eq!(tx0_replacement.id(), proposal.id());
+
+    // Ensure that the following anchoring transaction spends the replacement.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.prev_tx_id(), tx0_replacement.id());
+    assert_eq!(
+        proposal.anchoring_payload().unwrap().block_height,
+        Height(anchoring_interval)
+    );
+}
+
+#[test]
+fn bump_fee_err_disabled() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    assert!(!tx0.is_replaceable());
+
+    let block = anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_fee_bump_txs(tx0.id(), 100));
+    assert_tx_error(
+        &block[0],
+        ErrorMatch::from_fail(&Error::ReplaceByFeeDisabled),
+    );
+}
+
+#[test]
+fn bump_fee_err_insufficient_fee() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    enable_replace_by_fee(&mut anchoring_testkit);
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    report_unconfirmed(&mut anchoring_testkit, tx0.id());
+
+    let fee = anchoring_testkit.actual_anchoring_config().transaction_fee;
+    let block = anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_fee_bump_txs(tx0.id(), fee));
+    assert_tx_error(
+        &block[0],
+        ErrorMatch::from_fail(&Error::InsufficientFeeBump),
+    );
+}
+
+#[test]
+fn bump_fee_min_relay_fee() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    enable_replace_by_fee(&mut anchoring_testkit);
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    report_unconfirmed(&mut anchoring_testkit, tx0.id());
+    let replaced_fee = {
+        let snapshot = anchoring_testkit.inner.snapshot();
+        let schema = get_anchoring_schema(&snapshot);
+        tx0.fee(&schema.latest_transaction_inputs().unwrap())
+            .unwrap()
+    };
+    // The replacement should pay at least one satoshi per virtual byte in addition
+    // to the fee of the replaced transaction.
+    let vsize = tx0.vsize();
+    let min_fee = (replaced_fee + vsize + vsize - 1) / vsize;
+
+    let block = anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit.create_fee_bump_txs(tx0.id(), min_fee - 1),
+    );
+    assert_tx_error(
+        &block[0],
+        ErrorMatch::from_fail(&Error::InsufficientFeeBump),
+    );
+
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_fee_bump_txs(tx0.id(), min_fee))
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+    let snapshot = anchoring_testkit.inner.snapshot();
+    let fee_bump = get_anchoring_schema(&snapshot).fee_bump().unwrap();
+    assert_eq!(fee_bump.fee, min_fee);
+}
+
+#[test]
+fn bump_fee_err_not_unconfirmed() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    enable_replace_by_fee(&mut anchoring_testkit);
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+
+    // The latest anchoring transaction may have been already mined, since it has not been
+    // reported as unconfirmed.
+    let fee = anchoring_testkit.actual_anchoring_config().transaction_fee * 2;
+    let block = anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_fee_bump_txs(tx0.id(), fee));
+    assert_tx_error(
+        &block[0],
+        ErrorMatch::from_fail(&Error::UnexpectedReplacedTx),
+    );
+}
+
+#[test]
+fn bump_fee_cancelled_by_confirmation() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    enable_replace_by_fee(&mut anchoring_testkit);
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    report_unconfirmed(&mut anchoring_testkit, tx0.id());
+
+    let fee = anchoring_testkit.actual_anchoring_config().transaction_fee * 2;
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_fee_bump_txs(tx0.id(), fee))
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    // The replaced transaction is mined before the replacement is signed.
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_confirmation_report_txs(
+            ReportConfirmation {
+                index: 0,
+                txid: tx0.id(),
+                block_hash: btc::Sha256d::new([1; 32]),
+                block_height: 100,
+                confirmations: 1,
+            },
+        ))
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    // The fee bump is cancelled, so there is nothing to replace.
+    let snapshot = anchoring_testkit.inner.snapshot();
+    assert!(get_anchoring_schema(&snapshot).fee_bump().is_none());
+    assert!(anchoring_testkit.anchoring_transaction_proposal().is_none());
+}
+
+#[test]
+fn accelerate_unconfirmed_transaction() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+
+    // Increase the fee rate in order to accelerate the latest anchoring transaction.
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.transaction_fee *= 3;
+    let fee_rate = config.transaction_fee;
+    apply_anchoring_config(&mut anchoring_testkit, config);
+
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.prev_tx_id(), tx0.id());
+    let regular_fee = proposal.fee(&inputs).unwrap();
+
+    // Report that the latest anchoring transaction is unconfirmed.
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_unconfirmed_report_txs(tx0.id()))
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    // Ensure that the proposal pays for the whole package.
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.prev_tx_id(), tx0.id());
+    assert!(proposal.fee(&inputs).unwrap() > regular_fee);
+    {
+        let snapshot = anchoring_testkit.inner.snapshot();
+        let schema = get_anchoring_schema(&snapshot);
+        assert_eq!(schema.unconfirmed_transaction(), Some(tx0.id()));
+        assert_eq!(schema.package_fee_rate(&proposal, &inputs), Some(fee_rate));
+    }
+
+    // Finalize the accelerating transaction.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let snapshot = anchoring_testkit.inner.snapshot();
+    let schema = get_anchoring_schema(&snapshot);
+    assert_eq!(
+        schema.transactions_chain.last().unwrap().id(),
+        proposal.id()
+    );
+    assert!(schema.unconfirmed_transaction().is_none());
+}
+
+#[test]
+fn accelerate_unconfirmed_transaction_until_confirmed() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    let regular_fee = proposal.fee(&inputs).unwrap();
+
+    // The proposal accelerates the unconfirmed latest anchoring transaction.
+    report_unconfirmed(&mut anchoring_testkit, tx0.id());
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert!(proposal.fee(&inputs).unwrap() > regular_fee);
+
+    // Once the latest anchoring transaction is confirmed, the proposal pays the regular fee.
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_confirmation_report_txs(
+            ReportConfirmation {
+                index: 0,
+                txid: tx0.id(),
+                block_hash: btc::Sha256d::new([1; 32]),
+                block_height: 100,
+                confirmations: 1,
+            },
+        ))
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.fee(&inputs).unwrap(), regular_fee);
+    let snapshot = anchoring_testkit.inner.snapshot();
+    let schema = get_anchoring_schema(&snapshot);
+    assert!(schema.unconfirmed_transaction().is_none());
+    assert_eq!(schema.package_fee_rate(&proposal, &inputs), None);
+}
+
+#[test]
+fn report_unconfirmed_err_unexpected_tx() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+
+    let block = anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit.create_unconfirmed_report_txs(tx0.prev_tx_id()),
+    );
+    assert_tx_error(
+        &block[0],
+        ErrorMatch::from_fail(&Error::UnexpectedUnconfirmedTx),
+    );
+}
+
+#[test]
+fn report_confirmation() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    let report = ReportConfirmation {
+        index: 0,
+        txid: tx0.id(),
+        block_hash: btc::Sha256d::new([1; 32]),
+        block_height: 100,
+        confirmations: 1,
+    };
+
+    // Nodes observe different numbers of confirmations, so the number confirmed
+    // by the quorum is recorded.
+    let behind_reports = anchoring_testkit.create_confirmation_report_txs(report.clone());
+    let ahead_reports = anchoring_testkit.create_confirmation_report_txs(ReportConfirmation {
+        confirmations: 3,
+        ..report.clone()
+    });
+    let txs = ahead_reports
+        .into_iter()
+        .take(2)
+        .chain(behind_reports.into_iter().skip(2))
+        .collect::<Vec<_>>();
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(txs)
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    let snapshot = anchoring_testkit.inner.snapshot();
+    assert_eq!(
+        get_anchoring_schema(&snapshot).bitcoin_confirmation(&tx0.id()),
+        Some(BitcoinConfirmation {
+            block_hash: report.block_hash,
+            block_height: report.block_height,
+            confirmations: 1,
+        })
+    );
+
+    // The following reports update the number of confirmations.
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(anchoring_testkit.create_confirmation_report_txs(
+            ReportConfirmation {
+                confirmations: 6,
+                ..report.clone()
+            },
+        ))
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    let snapshot = anchoring_testkit.inner.snapshot();
+    let confirmation = get_anchoring_schema(&snapshot)
+        .bitcoin_confirmation(&tx0.id())
+        .unwrap();
+    assert_eq!(confirmation.confirmations, 6);
+}
+
+#[test]
+fn report_confirmation_reorg() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    let stale_report = ReportConfirmation {
+        index: 0,
+        txid: tx0.id(),
+        block_hash: btc::Sha256d::new([1; 32]),
+        block_height: 100,
+        confirmations: 1,
+    };
+    let actual_report = ReportConfirmation {
+        block_hash: btc::Sha256d::new([2; 32]),
+        ..stale_report.clone()
+    };
+
+    // The minority of the nodes reports the block which is reorganized later.
+    let stale_reports = anchoring_testkit.create_confirmation_report_txs(stale_report);
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(stale_reports.iter().take(2).cloned());
+    // The quorum of the nodes reports the actual block.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_confirmation_report_txs(actual_report.clone())
+            .into_iter()
+            .take(3),
+    );
+    // The lagging node still reports the outdated block, which should not be combined
+    // with the outdated reports of the other nodes.
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(stale_reports.into_iter().skip(3))
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    let snapshot = anchoring_testkit.inner.snapshot();
+    assert_eq!(
+        get_anchoring_schema(&snapshot).bitcoin_confirmation(&tx0.id()),
+        Some(BitcoinConfirmation {
+            block_hash: actual_report.block_hash,
+            block_height: actual_report.block_height,
+            confirmations: 1,
+        })
+    );
+}
+
+#[test]
+fn report_confirmation_err_unexpected_tx() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+
+    let block = anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit.create_confirmation_report_txs(ReportConfirmation {
+            index: 0,
+            txid: tx0.prev_tx_id(),
+            block_hash: btc::Sha256d::new([1; 32]),
+            block_height: 100,
+            confirmations: 1,
+        }),
+    );
+    assert_tx_error(
+        &block[0],
+        ErrorMatch::from_fail(&Error::UnexpectedConfirmedTx),
+    );
+}
+
+#[test]
+fn payload_config_hash() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // The first anchoring transaction commits to the actual configuration.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let config = anchoring_testkit.actual_anchoring_config();
+    let payload = anchoring_testkit
+        .last_anchoring_tx()
+        .unwrap()
+        .anchoring_payload()
+        .unwrap();
+    assert_eq!(payload.config_hash, Some(config.object_hash()));
+    assert_eq!(payload.blocks_root, None);
+
+    // The following anchoring transaction commits to the anchored blocks instead.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let payload = anchoring_testkit
+        .last_anchoring_tx()
+        .unwrap()
+        .anchoring_payload()
+        .unwrap();
+    assert_eq!(payload.config_hash, None);
+    assert!(payload.blocks_root.is_some());
+
+    // Change the configuration without changing the anchoring address.
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.transaction_fee += 1;
+    apply_anchoring_config(&mut anchoring_testkit, config.clone());
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 2));
+
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    let payload = proposal.anchoring_payload().unwrap();
+    assert_eq!(payload.block_height, Height(anchoring_interval * 2));
+    assert_eq!(payload.config_hash, Some(config.object_hash()));
+}
+
+#[test]
+fn pay_to_contract() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.pay_to_contract = true;
+    apply_anchoring_config(&mut anchoring_testkit, config.clone());
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    // The payload is committed by the anchoring output.
+    assert_eq!(tx0.0.output.len(), 1);
+    assert_eq!(tx0.anchoring_payload(), None);
+    let payload = {
+        let snapshot = anchoring_testkit.inner.snapshot();
+        let schema = get_anchoring_schema(&snapshot);
+        assert_eq!(schema.latest_anchored_height(), Some(Height(0)));
+        schema.anchoring_payload(&tx0).unwrap()
+    };
+    assert_eq!(payload.config_hash, Some(config.object_hash()));
+    assert_eq!(
+        tx0.0.output[0].script_pubkey,
+        config
+            .committed_redeem_script(Some(&payload.commitment()))
+            .as_ref()
+            .to_v0_p2wsh()
+    );
+
+    // Ensure that the following anchoring transaction spends the committed output.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(
+            anchoring_testkit
+                .create_signature_txs()
+                .into_iter()
+                .flatten(),
+        )
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+    let tx1 = anchoring_testkit.last_anchoring_tx().unwrap();
+    assert_eq!(tx1.prev_tx_id(), tx0.id());
+    assert_eq!(tx1.0.output.len(), 1);
+
+    // Switch back to the `OP_RETURN` commitments.
+    config.pay_to_contract = false;
+    apply_anchoring_config(&mut anchoring_testkit, config.clone());
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 2));
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx2 = anchoring_testkit.last_anchoring_tx().unwrap();
+    assert_eq!(tx2.prev_tx_id(), tx1.id());
+    assert_eq!(
+        tx2.anchoring_payload().unwrap().block_height,
+        Height(anchoring_interval * 2)
+    );
+    assert_eq!(
+        &tx2.0.output[0].script_pubkey,
+        &config.anchoring_out_script()
+    );
+}
+
+fn gen_sweep_address() -> btc::Address {
+    let public_key = btc::gen_keypair(bitcoin::Network::Testnet).0;
+    btc::Address(bitcoin::Address::p2wpkh(
+        &public_key.0,
+        bitcoin::Network::Testnet,
+    ))
+}
+
+#[test]
+fn sweep() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+    // Add an additional funding transaction.
+    let (txs, funding_tx) = anchoring_testkit.create_funding_confirmation_txs(2000);
+    anchoring_testkit.inner.create_block_with_transactions(txs);
+
+    // Request the sweep of the anchoring funds.
+    let sweep_address = gen_sweep_address();
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.sweep_address = Some(sweep_address.clone());
+    apply_anchoring_config(&mut anchoring_testkit, config);
+
+    // The next proposal spends the chain tail and the funding output to the sweep address.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(inputs, vec![tx0.clone(), funding_tx]);
+    assert_eq!(proposal.0.output.len(), 2);
+    assert_eq!(
+        proposal.0.output[0].script_pubkey,
+        sweep_address.0.script_pubkey()
+    );
+    assert_eq!(
+        proposal.anchoring_payload().unwrap().block_height,
+        Height(anchoring_interval)
+    );
+
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(
+            anchoring_testkit
+                .create_signature_txs()
+                .into_iter()
+                .flatten(),
+        )
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+    let tx1 = anchoring_testkit.last_anchoring_tx().unwrap();
+    assert_eq!(tx1.id(), proposal.id());
+    assert!(unspent_funding_transactions(&anchoring_testkit).is_empty());
+
+    // Anchoring is stopped after the sweep.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 2));
+    assert!(anchoring_testkit.anchoring_transaction_proposal().is_none());
+    let snapshot = anchoring_testkit.inner.snapshot();
+    assert_eq!(
+        get_anchoring_schema(&snapshot).sweep_transaction(),
+        Some(tx1)
+    );
+}
+
+#[test]
+fn sweep_remaining_funding_outputs() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    // Add more funding transactions than can be spent by a single sweep transaction.
+    let funding_txs_count = MAX_ANCHORING_TX_INPUTS + 2;
+    for _ in 0..funding_txs_count {
+        let (txs, _) = anchoring_testkit.create_funding_confirmation_txs(10_000);
+        anchoring_testkit.inner.create_block_with_transactions(txs);
+    }
+
+    let sweep_address = gen_sweep_address();
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.sweep_address = Some(sweep_address.clone());
+    apply_anchoring_config(&mut anchoring_testkit, config);
+
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.0.input.len(), MAX_ANCHORING_TX_INPUTS);
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx1 = anchoring_testkit.last_anchoring_tx().unwrap();
+    assert_eq!(
+        unspent_funding_transactions(&anchoring_testkit).len(),
+        funding_txs_count - (MAX_ANCHORING_TX_INPUTS - 1)
+    );
+
+    // The remaining funding outputs are swept by the following transaction, which refers
+    // to the previous sweep transaction instead of spending it.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 2));
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(
+        inputs.len(),
+        funding_txs_count - (MAX_ANCHORING_TX_INPUTS - 1)
+    );
+    assert!(!inputs.contains(&tx1));
+    assert_eq!(
+        proposal.anchoring_payload().unwrap().prev_tx_chain,
+        Some(tx1.id())
+    );
+    assert_eq!(
+        proposal.0.output[0].script_pubkey,
+        sweep_address.0.script_pubkey()
+    );
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(
+            anchoring_testkit
+                .create_signature_txs()
+                .into_iter()
+                .flatten(),
+        )
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+    let tx2 = anchoring_testkit.last_anchoring_tx().unwrap();
+    assert!(unspent_funding_transactions(&anchoring_testkit).is_empty());
+
+    // Anchoring is stopped once all funding outputs have been swept.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 3));
+    assert!(anchoring_testkit.anchoring_transaction_proposal().is_none());
+    let snapshot = anchoring_testkit.inner.snapshot();
+    let schema = get_anchoring_schema(&snapshot);
+    assert_eq!(schema.sweep_transactions.get(&tx1.id()), Some(1));
+    assert_eq!(schema.sweep_transactions.get(&tx2.id()), Some(2));
+    assert_eq!(schema.sweep_transaction(), Some(tx2));
+}
+
+#[test]
+fn sweep_address_cleared() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.sweep_address = Some(gen_sweep_address());
+    apply_anchoring_config(&mut anchoring_testkit, config.clone());
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval));
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx1 = anchoring_testkit.last_anchoring_tx().unwrap();
+
+    // The sweep is recorded regardless of the following changes of the sweep address.
+    config.sweep_address = None;
+    apply_anchoring_config(&mut anchoring_testkit, config);
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 2));
+    {
+        let snapshot = anchoring_testkit.inner.snapshot();
+        let schema = get_anchoring_schema(&snapshot);
+        assert_eq!(schema.sweep_transaction(), Some(tx1.clone()));
+        // The anchoring is not resumed until the wallet is replenished.
+        assert_eq!(
+            schema.actual_proposed_anchoring_transaction(snapshot.for_core()),
+            Some(Err(BuilderError::NoInputs))
+        );
+    }
+
+    // Replenish the wallet to resume the anchoring.
+    let (txs, funding_tx) = anchoring_testkit.create_funding_confirmation_txs(10_000);
+    anchoring_testkit.inner.create_block_with_transactions(txs);
+    let (proposal, inputs) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(inputs, vec![funding_tx]);
+    assert_eq!(
+        proposal.anchoring_payload().unwrap().prev_tx_chain,
+        Some(tx1.id())
+    );
+    assert_eq!(
+        proposal.0.output[0].script_pubkey,
+        anchoring_testkit
+            .actual_anchoring_config()
+            .anchoring_out_script()
+    );
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    assert_eq!(
+        anchoring_testkit.last_anchoring_tx().unwrap().id(),
+        proposal.id()
+    );
+}
+
+#[test]
+fn sweep_during_transition() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+
+    // Start the transition to the new anchoring address.
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.anchoring_keys.push(anchoring_testkit.add_node());
+    apply_anchoring_config(&mut anchoring_testkit, config);
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_ne!(
+        proposal.0.output[0].script_pubkey,
+        anchoring_testkit
+            .actual_anchoring_config()
+            .anchoring_out_script()
+    );
+
+    // Request the sweep by the actual configuration instead of finishing the transition.
+    let sweep_address = gen_sweep_address();
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.sweep_address = Some(sweep_address.clone());
+    apply_anchoring_config(&mut anchoring_testkit, config);
+
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.prev_tx_id(), tx0.id());
+    assert_eq!(
+        proposal.0.output[0].script_pubkey,
+        sweep_address.0.script_pubkey()
+    );
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(
+            anchoring_testkit
+                .create_signature_txs()
+                .into_iter()
+                .flatten(),
+        )
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    assert_eq!(
+        anchoring_testkit.last_anchoring_tx().unwrap().id(),
+        proposal.id()
+    );
+    assert!(anchoring_testkit.anchoring_transaction_proposal().is_none());
+}
+
+#[test]
+fn pause_and_resume() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let tx0 = anchoring_testkit.last_anchoring_tx().unwrap();
+
+    // Pause the anchoring.
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.paused = true;
+    apply_anchoring_config(&mut anchoring_testkit, config.clone());
+
+    // No anchoring transactions are proposed while the anchoring is paused.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 2));
+    assert!(anchoring_testkit.anchoring_transaction_proposal().is_none());
+    assert!(anchoring_testkit
+        .create_signature_txs()
+        .iter()
+        .all(Vec::is_empty));
+
+    // Resume the anchoring.
+    config.paused = false;
+    apply_anchoring_config(&mut anchoring_testkit, config);
+
+    // The anchoring is resumed from the following anchoring height.
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(proposal.prev_tx_id(), tx0.id());
+    assert_eq!(
+        proposal.anchoring_payload().unwrap().block_height,
+        Height(anchoring_interval)
+    );
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(
+            anchoring_testkit
+                .create_signature_txs()
+                .into_iter()
+                .flatten(),
+        )
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+    assert_eq!(
+        anchoring_testkit.last_anchoring_tx().unwrap().id(),
+        proposal.id()
+    );
+}
+
+#[test]
+fn catch_up_latest_height() {
+    let mut anchoring_testkit = AnchoringTestKit::default();
+    let anchoring_interval = anchoring_testkit
+        .actual_anchoring_config()
+        .anchoring_interval;
+
+    // Establish anchoring transactions chain.
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    let mut config = anchoring_testkit.actual_anchoring_config();
+    config.catch_up_policy = CatchUpPolicy::LatestHeight;
+    apply_anchoring_config(&mut anchoring_testkit, config);
+
+    // Miss several anchoring heights.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 4 + 2));
+
+    // The proposal anchors the latest anchoring height instead of the following one.
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(
+        proposal.anchoring_payload().unwrap().block_height,
+        Height(anchoring_interval * 4)
+    );
+    anchoring_testkit
+        .inner
+        .create_block_with_transactions(
+            anchoring_testkit
+                .create_signature_txs()
+                .into_iter()
+                .flatten(),
+        )
+        .transactions
+        .iter()
+        .try_for_each(|tx| tx.status())
+        .expect("Each transaction should be successful.");
+
+    let skipped_heights = |anchoring_testkit: &AnchoringTestKit| {
+        get_anchoring_schema(&anchoring_testkit.inner.snapshot())
+            .skipped_heights
+            .iter()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        skipped_heights(&anchoring_testkit),
+        vec![SkippedHeights {
+            from: anchoring_interval,
+            to: anchoring_interval * 3,
+        }]
+    );
+
+    // The following anchoring height is not skipped if it has not been missed.
+    anchoring_testkit
+        .inner
+        .create_blocks_until(Height(anchoring_interval * 5));
+    let (proposal, _) = anchoring_testkit.anchoring_transaction_proposal().unwrap();
+    assert_eq!(
+        proposal.anchoring_payload().unwrap().block_height,
+        Height(anchoring_interval * 5)
+    );
+    anchoring_testkit.inner.create_block_with_transactions(
+        anchoring_testkit
+            .create_signature_txs()
+            .into_iter()
+            .flatten(),
+    );
+    assert_eq!(skipped_heights(&anchoring_testkit).len(), 1);
+}
+
 // TODO Implement tests for anchoring recovery [ECR-3581]